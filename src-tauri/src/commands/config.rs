@@ -0,0 +1,585 @@
+//! Config commands - the single merged settings view the app actually uses
+//!
+//! Settings overlap across three sources. Each field is resolved in this order,
+//! taking the first source whose value differs from that source's own default:
+//!
+//! 1. Overrides: `AppState.theme` / `AppState.language`
+//! 2. `AppConfig` (the current config)
+//! 3. `LegacyAppConfig` (kept for the old frontend)
+//!
+//! When no source sets a field, the `AppConfig` default is used.
+//!
+//! Also holds the max-bytes cap applied to skill fetch bodies and MCP tool results,
+//! the shared HTTP client built with the configured proxy, and the main window
+//! geometry saved between launches.
+
+use tauri::{Manager, State};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock, atomic::{AtomicUsize, Ordering}};
+use crate::state::{SharedState, PixelState, AppState, AppConfig, LegacyAppConfig, WindowState};
+use crate::services::persistence::PersistenceService;
+
+/// Which source an effective setting came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Override,
+    AppConfig,
+    Legacy,
+    Default,
+}
+
+/// Merged configuration with the source of every field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub theme: String,
+    pub language: String,
+    pub font_size: u16,
+    pub active_model_id: Option<String>,
+    pub active_provider_id: Option<String>,
+    pub sources: HashMap<String, ConfigSource>,
+}
+
+/// Get the effective config merged from overrides, `AppConfig` and the legacy config
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn get_effective_config(
+    shared_state: State<'_, SharedState>,
+    app_state: State<'_, PixelState>,
+) -> Result<EffectiveConfig, String> {
+    let legacy = app_state.config.lock().await.clone();
+    Ok(shared_state.read(|state| resolve_effective_config(state, &legacy)))
+}
+
+/// Pick the first candidate that is set, falling back to the default
+fn pick<T: Clone>(candidates: &[(Option<T>, ConfigSource)], default: T) -> (T, ConfigSource) {
+    candidates.iter()
+        .find_map(|(value, source)| value.clone().map(|v| (v, *source)))
+        .unwrap_or((default, ConfigSource::Default))
+}
+
+/// `Some(value)` when it differs from the source's default
+fn non_default(value: &str, default: &str) -> Option<String> {
+    (value != default).then(|| value.to_string())
+}
+
+fn resolve_effective_config(state: &AppState, legacy: &LegacyAppConfig) -> EffectiveConfig {
+    let state_defaults = AppState::default();
+    let config_defaults = AppConfig::default();
+    let legacy_defaults = LegacyAppConfig::default();
+    let mut sources = HashMap::new();
+
+    let (theme, source) = pick(&[
+        (non_default(&state.theme, &state_defaults.theme), ConfigSource::Override),
+        (non_default(&state.config.theme, &config_defaults.theme), ConfigSource::AppConfig),
+        (non_default(&legacy.theme, &legacy_defaults.theme), ConfigSource::Legacy),
+    ], config_defaults.theme.clone());
+    sources.insert("theme".to_string(), source);
+
+    let (language, source) = pick(&[
+        (non_default(&state.language, &state_defaults.language), ConfigSource::Override),
+        (non_default(&state.config.language, &config_defaults.language), ConfigSource::AppConfig),
+        (non_default(&legacy.language, &legacy_defaults.language), ConfigSource::Legacy),
+    ], config_defaults.language.clone());
+    sources.insert("language".to_string(), source);
+
+    let font_size_source = if state.config.font_size == config_defaults.font_size {
+        ConfigSource::Default
+    } else {
+        ConfigSource::AppConfig
+    };
+    sources.insert("font_size".to_string(), font_size_source);
+
+    // Legacy settings hold a provider type/name and a model name rather than ids
+    let legacy_provider_id = non_default(&legacy.provider, &legacy_defaults.provider)
+        .and_then(|name| {
+            state.providers.iter()
+                .find(|p| p.id == name || p.name.eq_ignore_ascii_case(&name) || p.provider_type.eq_ignore_ascii_case(&name))
+                .map(|p| p.id.clone())
+        });
+    let (active_provider_id, source) = pick(&[
+        (state.config.active_provider_id.clone().map(Some), ConfigSource::AppConfig),
+        (legacy_provider_id.map(Some), ConfigSource::Legacy),
+    ], None);
+    sources.insert("active_provider_id".to_string(), source);
+
+    let legacy_model_id = non_default(&legacy.active_model, &legacy_defaults.active_model)
+        .and_then(|name| {
+            state.models.iter()
+                .find(|m| m.id == name || m.model_id == name)
+                .map(|m| m.id.clone())
+        });
+    let (active_model_id, source) = pick(&[
+        (state.config.active_model_id.clone().map(Some), ConfigSource::AppConfig),
+        (legacy_model_id.map(Some), ConfigSource::Legacy),
+    ], None);
+    sources.insert("active_model_id".to_string(), source);
+
+    EffectiveConfig {
+        theme,
+        language,
+        font_size: state.config.font_size,
+        active_model_id,
+        active_provider_id,
+        sources,
+    }
+}
+
+/// Default cap for skill fetch bodies and MCP tool results (1 MiB)
+pub const DEFAULT_MAX_RESULT_BYTES: usize = 1024 * 1024;
+
+/// Appended to content cut off at the max-bytes cap
+pub const TRUNCATION_MARKER: &str = "[truncated: result exceeded max bytes]";
+
+static MAX_RESULT_BYTES: OnceLock<AtomicUsize> = OnceLock::new();
+
+/// Current cap for skill fetch bodies and MCP tool results
+pub fn max_result_bytes() -> usize {
+    MAX_RESULT_BYTES
+        .get_or_init(|| AtomicUsize::new(DEFAULT_MAX_RESULT_BYTES))
+        .load(Ordering::Relaxed)
+}
+
+/// Get the max-bytes cap for skill fetch bodies and MCP tool results
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_max_result_bytes() -> usize {
+    max_result_bytes()
+}
+
+/// Set the max-bytes cap for skill fetch bodies and MCP tool results
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_max_result_bytes(max_bytes: usize) -> Result<usize, String> {
+    if max_bytes == 0 {
+        return Err("Max result bytes must be greater than zero".to_string());
+    }
+    MAX_RESULT_BYTES
+        .get_or_init(|| AtomicUsize::new(DEFAULT_MAX_RESULT_BYTES))
+        .store(max_bytes, Ordering::Relaxed);
+    Ok(max_bytes)
+}
+
+/// Cut `text` to at most `max_bytes` on a char boundary and append the truncation marker
+pub fn truncate_with_marker(text: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n{}", &text[..end], TRUNCATION_MARKER)
+}
+
+/// Proxy URL schemes accepted for outbound requests
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// Shared client for outbound requests, with the proxy it was built for
+static HTTP_CLIENT: OnceLock<RwLock<(Option<String>, reqwest::Client)>> = OnceLock::new();
+
+fn http_client_slot() -> &'static RwLock<(Option<String>, reqwest::Client)> {
+    HTTP_CLIENT.get_or_init(|| RwLock::new((None, reqwest::Client::new())))
+}
+
+/// The shared HTTP client, routed through the configured proxy
+/// Cloning a `reqwest::Client` is cheap; it shares the connection pool
+pub fn http_client() -> reqwest::Client {
+    http_client_slot().read()
+        .map(|slot| slot.1.clone())
+        .unwrap_or_default()
+}
+
+/// The proxy the shared HTTP client currently sends requests through
+pub fn active_proxy_url() -> Option<String> {
+    http_client_slot().read().ok().and_then(|slot| slot.0.clone())
+}
+
+/// Build a client that sends every request through `proxy_url` when given
+pub fn build_http_client(proxy_url: Option<&str>) -> Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy_url) = proxy_url {
+        let url = reqwest::Url::parse(proxy_url)
+            .map_err(|e| format!("Invalid proxy URL '{}': {}", proxy_url, e))?;
+        if !PROXY_SCHEMES.contains(&url.scheme()) {
+            return Err(format!("Unsupported proxy scheme '{}', expected one of: {}", url.scheme(), PROXY_SCHEMES.join(", ")));
+        }
+        let proxy = reqwest::Proxy::all(url)
+            .map_err(|e| format!("Invalid proxy URL '{}': {}", proxy_url, e))?;
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+/// Rebuild the shared client if the proxy differs from the one it was built with
+pub(crate) fn apply_proxy_url(proxy_url: Option<&str>) -> Result<(), String> {
+    let mut slot = http_client_slot().write()
+        .map_err(|_| "HTTP client lock poisoned".to_string())?;
+    if slot.0.as_deref() != proxy_url {
+        *slot = (proxy_url.map(String::from), build_http_client(proxy_url)?);
+    }
+    Ok(())
+}
+
+/// Get the proxy used for outbound requests
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_proxy_url(shared_state: State<'_, SharedState>) -> Option<String> {
+    shared_state.read(|state| state.config.proxy_url.clone())
+}
+
+/// Set or clear the proxy for outbound requests, rebuild the shared client and save it
+/// An empty URL clears the proxy
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_proxy_url(
+    shared_state: State<'_, SharedState>,
+    persistence: State<'_, PersistenceService>,
+    proxy_url: Option<String>,
+) -> Result<Option<String>, String> {
+    let proxy_url = proxy_url
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+    apply_proxy_url(proxy_url.as_deref())?;
+    shared_state.write(|state| state.config.proxy_url = proxy_url.clone());
+    persistence.persist_settings()?;
+    Ok(proxy_url)
+}
+
+/// Get how many times a chat stream that fails mid-generation is retried
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_stream_retry(shared_state: State<'_, SharedState>) -> u32 {
+    shared_state.read(|state| state.config.stream_retry)
+}
+
+/// Set how many times a chat stream that fails mid-generation is retried (0 disables)
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_stream_retry(
+    shared_state: State<'_, SharedState>,
+    persistence: State<'_, PersistenceService>,
+    retries: u32,
+) -> Result<u32, String> {
+    shared_state.write(|state| state.config.stream_retry = retries);
+    persistence.persist_settings()?;
+    Ok(retries)
+}
+
+/// Pixels of a restored window that must overlap a monitor, across and below its top edge
+const MIN_VISIBLE_WINDOW_PX: i64 = 100;
+
+/// A monitor's area in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Keep a saved window reachable: it is kept when its top edge lies on a monitor with
+/// enough of its width showing, otherwise it is shrunk to fit and centered on the
+/// first monitor (the primary one). With no monitors known it is left alone
+pub fn clamp_window_state(saved: WindowState, monitors: &[MonitorRect]) -> WindowState {
+    let Some(primary) = monitors.first() else {
+        return saved;
+    };
+
+    let (x, y, width) = (saved.x as i64, saved.y as i64, saved.width as i64);
+    let on_screen = monitors.iter().any(|m| {
+        let (mx, my) = (m.x as i64, m.y as i64);
+        let overlap = (x + width).min(mx + m.width as i64) - x.max(mx);
+        // The top edge holds the title bar, so it has to be reachable to move the window
+        overlap >= MIN_VISIBLE_WINDOW_PX && y >= my && y <= my + m.height as i64 - MIN_VISIBLE_WINDOW_PX
+    });
+    if on_screen {
+        return saved;
+    }
+
+    let width = saved.width.min(primary.width);
+    let height = saved.height.min(primary.height);
+    WindowState {
+        x: primary.x + ((primary.width - width) / 2) as i32,
+        y: primary.y + ((primary.height - height) / 2) as i32,
+        width,
+        height,
+        maximized: saved.maximized,
+    }
+}
+
+/// Save the main window's geometry into `AppConfig.window_state`
+/// Minimized windows are skipped, and a maximized one keeps its last normal geometry
+#[allow(dead_code)]
+pub fn record_window_state(window: &tauri::Window) {
+    if window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let maximized = window.is_maximized().unwrap_or(false);
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.outer_size()) else {
+        return;
+    };
+
+    window.state::<SharedState>().write(|state| {
+        state.config.window_state = Some(match state.config.window_state {
+            Some(previous) if maximized => WindowState { maximized, ..previous },
+            _ => WindowState {
+                x: position.x,
+                y: position.y,
+                width: size.width,
+                height: size.height,
+                maximized,
+            },
+        });
+    });
+}
+
+/// Apply saved geometry to a window, first moving it back on screen if its monitor is gone
+#[allow(dead_code)]
+pub fn restore_window_state(window: &tauri::WebviewWindow, saved: WindowState) {
+    let primary = window.primary_monitor().ok().flatten();
+    let mut monitors: Vec<tauri::window::Monitor> = primary.into_iter().collect();
+    monitors.extend(window.available_monitors().unwrap_or_default());
+    let monitors: Vec<MonitorRect> = monitors.iter()
+        .map(|m| MonitorRect {
+            x: m.position().x,
+            y: m.position().y,
+            width: m.size().width,
+            height: m.size().height,
+        })
+        .collect();
+
+    let restored = clamp_window_state(saved, &monitors);
+    let _ = window.set_size(tauri::PhysicalSize::new(restored.width, restored.height));
+    let _ = window.set_position(tauri::PhysicalPosition::new(restored.x, restored.y));
+    if restored.maximized {
+        let _ = window.maximize();
+    }
+}
+
+/// Handle a close request on the main window: hide it to the tray, or quit the app
+/// when `close_to_tray` is off
+pub fn handle_close_requested(
+    close_to_tray: bool,
+    prevent_close: impl FnOnce(),
+    hide: impl FnOnce(),
+    exit: impl FnOnce(),
+) {
+    if close_to_tray {
+        prevent_close();
+        hide();
+    } else {
+        exit();
+    }
+}
+
+/// Get whether closing the main window hides it to the tray
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_close_to_tray(shared_state: State<'_, SharedState>) -> bool {
+    shared_state.read(|state| state.config.close_to_tray)
+}
+
+/// Choose whether closing the main window hides it to the tray or quits
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_close_to_tray(
+    shared_state: State<'_, SharedState>,
+    persistence: State<'_, PersistenceService>,
+    enabled: bool,
+) -> Result<bool, String> {
+    shared_state.write(|state| state.config.close_to_tray = enabled);
+    persistence.persist_settings()?;
+    Ok(enabled)
+}
+
+/// Get the saved main window geometry
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_window_state(shared_state: State<'_, SharedState>) -> Option<WindowState> {
+    shared_state.read(|state| state.config.window_state)
+}
+
+/// Suggest the existing skill, provider or model name (`kind`) closest to a mistyped `query`
+#[tauri::command]
+#[allow(dead_code)]
+pub fn suggest_closest_name(shared_state: State<'_, SharedState>, kind: String, query: String) -> Option<String> {
+    shared_state.read(|state| state.suggest_closest_name(&kind, &query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock_http::{MockHttpServer, MockResponse};
+    use crate::state::LLMProvider;
+
+    #[test]
+    fn test_effective_config_precedence() {
+        let mut state = AppState::default();
+        let mut legacy = LegacyAppConfig::default();
+
+        // Override beats AppConfig beats legacy
+        state.theme = "solarized".to_string();
+        state.config.theme = "light".to_string();
+        legacy.theme = "high-contrast".to_string();
+
+        // AppConfig beats legacy
+        state.config.language = "ja".to_string();
+        legacy.language = "fr".to_string();
+
+        // Legacy provider is used only when AppConfig has none
+        state.providers.push(LLMProvider {
+            id: "provider_1".to_string(),
+            name: "Anthropic".to_string(),
+            provider_type: "anthropic".to_string(),
+            base_url: "https://api.anthropic.com/v1".to_string(),
+            api_key: String::new(),
+            enabled: true,
+            custom_headers: HashMap::new(),
+            default_model_id: None,
+            supports_streaming: None,
+        });
+        legacy.provider = "anthropic".to_string();
+
+        let effective = resolve_effective_config(&state, &legacy);
+        assert_eq!(effective.theme, "solarized");
+        assert_eq!(effective.sources["theme"], ConfigSource::Override);
+        assert_eq!(effective.language, "ja");
+        assert_eq!(effective.sources["language"], ConfigSource::AppConfig);
+        assert_eq!(effective.font_size, 16);
+        assert_eq!(effective.sources["font_size"], ConfigSource::Default);
+        assert_eq!(effective.active_provider_id.as_deref(), Some("provider_1"));
+        assert_eq!(effective.sources["active_provider_id"], ConfigSource::Legacy);
+        assert_eq!(effective.sources["active_model_id"], ConfigSource::Default);
+
+        state.theme = AppState::default().theme;
+        state.config.active_provider_id = Some("provider_2".to_string());
+        let effective = resolve_effective_config(&state, &legacy);
+        assert_eq!(effective.theme, "light");
+        assert_eq!(effective.active_provider_id.as_deref(), Some("provider_2"));
+        assert_eq!(effective.sources["active_provider_id"], ConfigSource::AppConfig);
+    }
+
+    #[test]
+    fn test_truncate_with_marker() {
+        let truncated = truncate_with_marker("héllo world", 2);
+        assert_eq!(truncated, format!("h\n{}", TRUNCATION_MARKER));
+        assert!(set_max_result_bytes(0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_client_uses_proxy() {
+        // A proxy receives the absolute target URL in the request line
+        let proxy = MockHttpServer::start(|_| MockResponse::new("200 OK", "text/plain", "via proxy")).await;
+
+        let client = build_http_client(Some(&proxy.url())).unwrap();
+        let body = client.get("http://provider.invalid/v1/models").send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "via proxy");
+        assert!(proxy.requests()[0].starts_with("GET http://provider.invalid/v1/models HTTP/1.1"));
+
+        assert!(build_http_client(Some("socks5://127.0.0.1:1080")).is_ok());
+        assert!(build_http_client(Some("ftp://127.0.0.1:21")).is_err());
+        assert!(build_http_client(Some("not a url")).is_err());
+    }
+
+    #[test]
+    fn test_backend_settings_survive_restart() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state_path = temp_dir.path().join("state.bin");
+        let shared_state = SharedState::new();
+        let persistence = PersistenceService::with_path(shared_state.inner.clone(), state_path.clone());
+        persistence.load().unwrap();
+        let frontend_copy = persistence.snapshot().unwrap();
+
+        shared_state.write(|state| {
+            state.config.close_to_tray = false;
+            state.config.proxy_url = Some("http://127.0.0.1:8080".to_string());
+            state.config.stream_retry = 2;
+        });
+        persistence.persist_settings().unwrap();
+        persistence.save_state(frontend_copy).unwrap();
+
+        let restarted = SharedState::new();
+        PersistenceService::with_path(restarted.inner.clone(), state_path).load().unwrap();
+        let config = restarted.read(|state| state.config.clone());
+        assert!(!config.close_to_tray);
+        assert_eq!(config.proxy_url.as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(config.stream_retry, 2);
+    }
+
+    #[test]
+    fn test_window_state_round_trip() {
+        let config = AppConfig {
+            window_state: Some(WindowState { x: -1200, y: 40, width: 1280, height: 800, maximized: true }),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+        let from_json: AppConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json.window_state, config.window_state);
+
+        let bytes = bincode::serialize(&config).unwrap();
+        let from_bincode: AppConfig = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(from_bincode.window_state, config.window_state);
+
+        // Configs saved before the field existed restore nothing
+        let mut legacy = serde_json::to_value(AppConfig::default()).unwrap();
+        legacy.as_object_mut().unwrap().remove("window_state");
+        assert_eq!(serde_json::from_value::<AppConfig>(legacy).unwrap().window_state, None);
+    }
+
+    #[test]
+    fn test_window_state_clamped_on_screen() {
+        let primary = MonitorRect { x: 0, y: 0, width: 1920, height: 1080 };
+        let left = MonitorRect { x: -1280, y: 0, width: 1280, height: 1024 };
+        let monitors = [primary, left];
+
+        // On the secondary monitor, or partly off the primary edge: kept
+        let on_left = WindowState { x: -1000, y: 100, width: 800, height: 600, maximized: false };
+        assert_eq!(clamp_window_state(on_left, &monitors), on_left);
+        let overhanging = WindowState { x: 1700, y: 200, width: 800, height: 600, maximized: false };
+        assert_eq!(clamp_window_state(overhanging, &monitors), overhanging);
+
+        // Its monitor was unplugged: centered on the primary
+        let unplugged = WindowState { x: 2500, y: 100, width: 800, height: 600, maximized: true };
+        assert_eq!(
+            clamp_window_state(unplugged, &monitors),
+            WindowState { x: 560, y: 240, width: 800, height: 600, maximized: true },
+        );
+
+        // Title bar above the screen, and a window larger than the primary
+        let above = WindowState { x: 100, y: -500, width: 2560, height: 1440, maximized: false };
+        assert_eq!(
+            clamp_window_state(above, &monitors),
+            WindowState { x: 0, y: 0, width: 1920, height: 1080, maximized: false },
+        );
+
+        // Only a sliver showing at the right edge
+        let sliver = WindowState { x: 1880, y: 100, width: 800, height: 600, maximized: false };
+        assert_eq!(clamp_window_state(sliver, &monitors).x, 560);
+
+        assert_eq!(clamp_window_state(unplugged, &[]), unplugged);
+    }
+
+    #[test]
+    fn test_close_to_tray_controls_prevent_close() {
+        use std::cell::RefCell;
+
+        let run = |close_to_tray: bool| {
+            let calls = RefCell::new(Vec::new());
+            handle_close_requested(
+                close_to_tray,
+                || calls.borrow_mut().push("prevent_close"),
+                || calls.borrow_mut().push("hide"),
+                || calls.borrow_mut().push("exit"),
+            );
+            calls.into_inner()
+        };
+
+        assert_eq!(run(true), vec!["prevent_close", "hide"]);
+        assert_eq!(run(false), vec!["exit"]);
+
+        // Configs saved before the flag existed keep hiding to the tray
+        let mut legacy = serde_json::to_value(AppConfig::default()).unwrap();
+        legacy.as_object_mut().unwrap().remove("close_to_tray");
+        assert!(serde_json::from_value::<AppConfig>(legacy).unwrap().close_to_tray);
+    }
+}
@@ -4,11 +4,24 @@
 use base64::{Engine as _, engine::general_purpose};
 use serde::{Serialize, Deserialize};
 use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{Manager, Emitter};
 use crate::state::PixelState;
 
+/// Scene `type` value used by the official Excalidraw format
+const SCENE_SCHEMA_TYPE: &str = "excalidraw";
+
+/// Oldest scene schema version that can be migrated on import
+const MIN_SCENE_VERSION: u64 = 1;
+
+/// Scene schema version written by this app
+const CURRENT_SCENE_VERSION: u64 = 2;
+
+/// Horizontal gap between scenes placed side by side when merging
+const MERGE_SCENE_GAP: f64 = 100.0;
+
 /// Excalidraw scene data - compatible with official format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExcalidrawSceneData {
@@ -158,15 +171,18 @@ pub async fn list_excalidraw_scenes(
     state: tauri::State<'_, PixelState>,
 ) -> Result<Vec<SceneInfo>, String> {
     let app_handle = state.app_handle.get();
-    let scenes_dir = get_scenes_dir(&app_handle);
-    
+    list_scenes_in_dir(&get_scenes_dir(&app_handle), &conversation_id)
+}
+
+/// List scenes stored in `scenes_dir` that belong to a conversation
+fn list_scenes_in_dir(scenes_dir: &PathBuf, conversation_id: &str) -> Result<Vec<SceneInfo>, String> {
     if !scenes_dir.exists() {
         return Ok(Vec::new());
     }
     
     let mut scenes: Vec<SceneInfo> = Vec::new();
     
-    for entry in fs::read_dir(&scenes_dir)
+    for entry in fs::read_dir(scenes_dir)
         .map_err(|e| format!("Failed to read scenes directory: {}", e))?
     {
         let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
@@ -204,6 +220,59 @@ pub async fn list_excalidraw_scenes(
     Ok(scenes)
 }
 
+/// Rename Excalidraw scene by setting `appState.name`
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn rename_excalidraw_scene(
+    scene_id: String,
+    name: String,
+    state: tauri::State<'_, PixelState>,
+) -> Result<(), String> {
+    let app_handle = state.app_handle.get();
+    let path = get_scene_path(&app_handle, &scene_id);
+
+    if !path.exists() {
+        return Err(format!("Scene not found: {}", scene_id));
+    }
+
+    let now = rename_scene_at_path(&path, &name)?;
+
+    let _ = app_handle.emit("excalidraw:saved", &json!({
+        "sceneId": scene_id,
+        "updatedAt": now,
+    }));
+
+    Ok(())
+}
+
+/// Set the scene name, bump its updated time and resave it
+/// Returns the new updated time
+fn rename_scene_at_path(path: &PathBuf, name: &str) -> Result<u64, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Scene name cannot be empty".to_string());
+    }
+
+    let json_str = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read scene file: {}", e))?;
+    let mut scene: ExcalidrawSceneData = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Failed to parse scene: {}", e))?;
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    if !scene.app_state.is_object() {
+        scene.app_state = json!({});
+    }
+    scene.app_state["name"] = json!(name);
+    scene.app_state["updated"] = json!(now);
+
+    let json_str = serde_json::to_string_pretty(&scene)
+        .map_err(|e| format!("Failed to serialize scene: {}", e))?;
+    fs::write(path, &json_str)
+        .map_err(|e| format!("Failed to write scene file: {}", e))?;
+
+    Ok(now)
+}
+
 /// Delete Excalidraw scene
 #[tauri::command]
 #[allow(dead_code)]
@@ -251,12 +320,10 @@ pub async fn import_excalidraw_scene(
     let app_handle = state.app_handle.get();
     let now = chrono::Utc::now().timestamp_millis() as u64;
     
-    // Parse and validate
-    let mut scene: ExcalidrawSceneData = serde_json::from_str(&json_str)
-        .map_err(|e| format!("Invalid scene JSON: {}", e))?;
+    // Parse, validate and migrate to the current schema
+    let mut scene = parse_scene_with_schema_check(&json_str)?;
     
     // Update metadata
-    scene.version = 2;
     scene.source = "https://pixel-client.tauri".to_string();
     
     // Generate new scene ID
@@ -280,6 +347,374 @@ pub async fn import_excalidraw_scene(
     Ok(scene_id)
 }
 
+/// Validate a scene's Excalidraw schema type and version without importing it
+/// Returns the version found in the file
+#[tauri::command]
+#[allow(dead_code)]
+pub fn validate_excalidraw_scene(json_str: String) -> Result<u64, String> {
+    let value: Value = serde_json::from_str(&json_str)
+        .map_err(|e| format!("Invalid scene JSON: {}", e))?;
+    check_scene_schema(&value)
+}
+
+/// Check the `type` and `version` fields of a raw scene
+fn check_scene_schema(value: &Value) -> Result<u64, String> {
+    let schema_type = value.get("type").and_then(|t| t.as_str());
+    if schema_type != Some(SCENE_SCHEMA_TYPE) {
+        return Err(format!(
+            "Not an Excalidraw scene: expected type '{}', found {}",
+            SCENE_SCHEMA_TYPE,
+            schema_type.map(|t| format!("'{}'", t)).unwrap_or_else(|| "none".to_string())
+        ));
+    }
+
+    let version = value.get("version")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "Excalidraw scene is missing a numeric 'version'".to_string())?;
+
+    if !(MIN_SCENE_VERSION..=CURRENT_SCENE_VERSION).contains(&version) {
+        return Err(format!(
+            "Unsupported Excalidraw schema version {} (supported: {}-{})",
+            version, MIN_SCENE_VERSION, CURRENT_SCENE_VERSION
+        ));
+    }
+
+    Ok(version)
+}
+
+/// Parse a scene, migrating older schema versions to the current one
+fn parse_scene_with_schema_check(json_str: &str) -> Result<ExcalidrawSceneData, String> {
+    let mut value: Value = serde_json::from_str(json_str)
+        .map_err(|e| format!("Invalid scene JSON: {}", e))?;
+
+    let version = check_scene_schema(&value)?;
+
+    if version < CURRENT_SCENE_VERSION {
+        // v1 scenes predate `files` and may omit `source`/`appState`
+        let defaults = ExcalidrawSceneData::default();
+        if let Some(obj) = value.as_object_mut() {
+            obj.entry("source").or_insert_with(|| json!(defaults.source));
+            obj.entry("elements").or_insert_with(|| json!([]));
+            obj.entry("appState").or_insert(defaults.app_state);
+            obj.entry("files").or_insert(defaults.files);
+            obj.insert("version".to_string(), json!(CURRENT_SCENE_VERSION));
+        }
+    }
+
+    serde_json::from_value(value)
+        .map_err(|e| format!("Invalid scene JSON: {}", e))
+}
+
+/// Whether a saved scene file can be loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneValidation {
+    pub loadable: bool,
+    /// Why loading fails, when it does
+    pub error: Option<String>,
+}
+
+/// What `repair_excalidraw_scene` salvaged from a damaged scene file
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneRepairResult {
+    /// False when the file was already loadable and left untouched
+    pub repaired: bool,
+    pub elements_recovered: usize,
+    /// Whether `appState` survived; otherwise it is reset to the default
+    pub app_state_recovered: bool,
+    /// Copy of the damaged file kept next to the scene
+    pub backup_path: Option<String>,
+}
+
+/// Check whether a saved scene file can be loaded
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn validate_excalidraw_scene_file(
+    scene_id: String,
+    state: tauri::State<'_, PixelState>,
+) -> Result<SceneValidation, String> {
+    let app_handle = state.app_handle.get();
+    let path = get_scene_path(&app_handle, &scene_id);
+
+    if !path.exists() {
+        return Err(format!("Scene not found: {}", scene_id));
+    }
+    Ok(validate_scene_at_path(&path))
+}
+
+/// Salvage a scene file left truncated or corrupted (e.g. by a crash mid-write)
+/// Complete elements and `appState` are kept, `files` is reset, and the damaged file
+/// is kept as `<scene id>.json.corrupt`
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn repair_excalidraw_scene(
+    scene_id: String,
+    state: tauri::State<'_, PixelState>,
+) -> Result<SceneRepairResult, String> {
+    let app_handle = state.app_handle.get();
+    let path = get_scene_path(&app_handle, &scene_id);
+
+    if !path.exists() {
+        return Err(format!("Scene not found: {}", scene_id));
+    }
+    repair_scene_at_path(&path)
+}
+
+fn validate_scene_at_path(path: &Path) -> SceneValidation {
+    let result = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read scene file: {}", e))
+        .and_then(|json_str| {
+            serde_json::from_str::<ExcalidrawSceneData>(&json_str)
+                .map_err(|e| format!("Failed to parse scene: {}", e))
+        });
+
+    SceneValidation {
+        loadable: result.is_ok(),
+        error: result.err(),
+    }
+}
+
+fn repair_scene_at_path(path: &Path) -> Result<SceneRepairResult, String> {
+    if validate_scene_at_path(path).loadable {
+        return Ok(SceneRepairResult {
+            repaired: false,
+            elements_recovered: 0,
+            app_state_recovered: false,
+            backup_path: None,
+        });
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read scene file: {}", e))?;
+    let damaged = String::from_utf8_lossy(&bytes);
+
+    let elements_text = json_key_value(&damaged, "elements")
+        .filter(|rest| rest.starts_with('['))
+        .ok_or_else(|| "Scene file has no elements to recover".to_string())?;
+    let (elements, elements_len) = salvage_array_items(elements_text);
+
+    // appState follows the elements, so only a fully written elements array can precede it
+    let app_state = elements_len
+        .and_then(|len| json_key_value(&elements_text[len..], "appState"))
+        .and_then(|rest| json_value_end(rest).map(|end| &rest[..end]))
+        .and_then(|text| serde_json::from_str::<Value>(text).ok())
+        .filter(Value::is_object);
+
+    let scene = ExcalidrawSceneData {
+        elements,
+        app_state: app_state.clone().unwrap_or_else(|| ExcalidrawSceneData::default().app_state),
+        ..ExcalidrawSceneData::default()
+    };
+    let json_str = serde_json::to_string_pretty(&scene)
+        .map_err(|e| format!("Failed to serialize scene: {}", e))?;
+
+    let backup_path = path.with_extension("json.corrupt");
+    fs::write(&backup_path, &bytes)
+        .map_err(|e| format!("Failed to back up damaged scene: {}", e))?;
+    fs::write(path, json_str)
+        .map_err(|e| format!("Failed to write scene file: {}", e))?;
+
+    Ok(SceneRepairResult {
+        repaired: true,
+        elements_recovered: scene.elements.len(),
+        app_state_recovered: app_state.is_some(),
+        backup_path: Some(backup_path.to_string_lossy().to_string()),
+    })
+}
+
+/// The text right after `"key":`, starting at its value
+fn json_key_value<'a>(text: &'a str, key: &str) -> Option<&'a str> {
+    let start = text.find(&format!("\"{}\"", key))? + key.len() + 2;
+    let rest = text[start..].trim_start().strip_prefix(':')?;
+    Some(rest.trim_start())
+}
+
+/// Byte length of the object or array at the start of `text`, None if it is cut off
+fn json_value_end(text: &str) -> Option<usize> {
+    if !text.starts_with(['{', '[']) {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The complete items at the start of a possibly truncated JSON array
+/// Also returns the array's byte length when its closing bracket was reached
+fn salvage_array_items(text: &str) -> (Vec<Value>, Option<usize>) {
+    let mut items = Vec::new();
+    let mut pos = 1;
+    loop {
+        let rest = text[pos..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        pos = text.len() - rest.len();
+        if rest.starts_with(']') {
+            return (items, Some(pos + 1));
+        }
+        let Some(end) = json_value_end(rest) else {
+            return (items, None);
+        };
+        if let Ok(item) = serde_json::from_str(&rest[..end]) {
+            items.push(item);
+        }
+        pos += end;
+    }
+}
+
+/// Merge several scenes into a new scene placed side by side
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn merge_excalidraw_scenes(
+    scene_ids: Vec<String>,
+    conversation_id: String,
+    state: tauri::State<'_, PixelState>,
+) -> Result<String, String> {
+    if scene_ids.is_empty() {
+        return Err("No scenes to merge".to_string());
+    }
+    
+    let app_handle = state.app_handle.get();
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    
+    let mut scenes = Vec::with_capacity(scene_ids.len());
+    for scene_id in &scene_ids {
+        let path = get_scene_path(&app_handle, scene_id);
+        if !path.exists() {
+            return Err(format!("Scene not found: {}", scene_id));
+        }
+        let json_str = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read scene file: {}", e))?;
+        let scene: ExcalidrawSceneData = serde_json::from_str(&json_str)
+            .map_err(|e| format!("Failed to parse scene '{}': {}", scene_id, e))?;
+        scenes.push(scene);
+    }
+    
+    let merged = merge_scenes(scenes);
+    
+    let scene_id = format!("excalidraw_{}", uuid::Uuid::new_v4());
+    let path = get_scene_path(&app_handle, &scene_id);
+    let json = serde_json::to_string_pretty(&merged)
+        .map_err(|e| format!("Failed to serialize scene: {}", e))?;
+    
+    fs::write(&path, &json)
+        .map_err(|e| format!("Failed to write scene file: {}", e))?;
+    
+    let _ = app_handle.emit("excalidraw:saved", &json!({
+        "sceneId": scene_id,
+        "conversationId": conversation_id,
+        "updatedAt": now,
+    }));
+    
+    Ok(scene_id)
+}
+
+/// Horizontal extent (min x, max x) of a scene's elements
+fn scene_x_bounds(elements: &[Value]) -> Option<(f64, f64)> {
+    elements.iter()
+        .filter_map(|el| {
+            let x = el.get("x")?.as_f64()?;
+            let width = el.get("width").and_then(|w| w.as_f64()).unwrap_or(0.0);
+            Some((x, x + width))
+        })
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+}
+
+/// Rewrite element id references (bindings, containers, frames) using the given map
+fn remap_element_refs(element: &mut Value, id_map: &HashMap<String, String>) {
+    let remap = |value: &mut Value| {
+        if let Some(new_id) = value.as_str().and_then(|id| id_map.get(id)) {
+            *value = json!(new_id);
+        }
+    };
+
+    if let Some(obj) = element.as_object_mut() {
+        for key in ["id", "containerId", "frameId"] {
+            if let Some(value) = obj.get_mut(key) {
+                remap(value);
+            }
+        }
+        for key in ["startBinding", "endBinding"] {
+            if let Some(value) = obj.get_mut(key).and_then(|b| b.get_mut("elementId")) {
+                remap(value);
+            }
+        }
+        if let Some(bound) = obj.get_mut("boundElements").and_then(|b| b.as_array_mut()) {
+            for value in bound.iter_mut().filter_map(|b| b.get_mut("id")) {
+                remap(value);
+            }
+        }
+    }
+}
+
+/// Merge scenes left to right, offsetting x coordinates and renaming clashing element ids
+fn merge_scenes(scenes: Vec<ExcalidrawSceneData>) -> ExcalidrawSceneData {
+    let mut merged = ExcalidrawSceneData::default();
+    let mut seen_ids: HashSet<String> = HashSet::new();
+    let mut files = serde_json::Map::new();
+    let mut cursor_x: Option<f64> = None;
+
+    for (index, scene) in scenes.into_iter().enumerate() {
+        if index == 0 {
+            merged.app_state = scene.app_state.clone();
+        }
+        if let Some(scene_files) = scene.files.as_object() {
+            for (file_id, file) in scene_files {
+                files.entry(file_id.clone()).or_insert_with(|| file.clone());
+            }
+        }
+
+        // Place this scene to the right of everything merged so far
+        let bounds = scene_x_bounds(&scene.elements);
+        let offset_x = match (cursor_x, bounds) {
+            (Some(cursor), Some((min_x, _))) => cursor - min_x,
+            _ => 0.0,
+        };
+        if let Some((_, max_x)) = bounds {
+            cursor_x = Some(max_x + offset_x + MERGE_SCENE_GAP);
+        }
+
+        let id_map: HashMap<String, String> = scene.elements.iter()
+            .filter_map(|el| el.get("id").and_then(|id| id.as_str()))
+            .filter(|id| seen_ids.contains(*id))
+            .map(|id| (id.to_string(), uuid::Uuid::new_v4().to_string()))
+            .collect();
+
+        for mut element in scene.elements {
+            remap_element_refs(&mut element, &id_map);
+            if let Some(x) = element.get("x").and_then(|x| x.as_f64()) {
+                element["x"] = json!(x + offset_x);
+            }
+            if let Some(id) = element.get("id").and_then(|id| id.as_str()) {
+                seen_ids.insert(id.to_string());
+            }
+            merged.elements.push(element);
+        }
+    }
+
+    merged.files = Value::Object(files);
+    merged
+}
+
 /// Get exports directory path
 fn get_exports_dir(app: &tauri::AppHandle) -> PathBuf {
     let resource_dir = app.path().resource_dir().unwrap_or_else(|_| PathBuf::from("resources"));
@@ -402,44 +837,78 @@ pub async fn list_excalidraw_exports(
     let app_handle = state.app_handle.get();
     let exports_dir = get_exports_dir(&app_handle);
 
+    let exports = list_exports_in_dir(&exports_dir, None)?;
+    Ok(exports.into_iter().filter(|e| e.scene_id == scene_id).collect())
+}
+
+/// Get the most recent exported images across all scenes
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn list_all_exports(
+    limit: Option<usize>,
+    state: tauri::State<'_, PixelState>,
+) -> Result<Vec<ExportInfo>, String> {
+    let app_handle = state.app_handle.get();
+    let exports_dir = get_exports_dir(&app_handle);
+
+    list_exports_in_dir(&exports_dir, limit)
+}
+
+/// File extensions of exported images
+const EXPORT_EXTENSIONS: &[&str] = &["png", "svg"];
+
+/// Scene id and creation time (ms) from an export file stem
+/// Exports are named `excalidraw_<scene id>_<YYYYmmdd>_<HHMMSS>`
+fn parse_export_stem(stem: &str) -> Option<(String, u64)> {
+    let rest = stem.strip_prefix("excalidraw_")?;
+    let mut parts = rest.rsplitn(3, '_');
+    let time = parts.next()?;
+    let date = parts.next()?;
+    let scene_id = parts.next().filter(|id| !id.is_empty())?;
+
+    let created = chrono::NaiveDateTime::parse_from_str(&format!("{}_{}", date, time), "%Y%m%d_%H%M%S").ok()?;
+    Some((scene_id.to_string(), created.and_utc().timestamp_millis() as u64))
+}
+
+/// Export info for an image file, None for files that are not exports
+fn export_info(path: &Path) -> Option<ExportInfo> {
+    let extension = path.extension()?.to_str()?;
+    if !EXPORT_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    let (scene_id, created_at) = parse_export_stem(path.file_stem()?.to_str()?)?;
+    let metadata = path.metadata().ok()?;
+
+    Some(ExportInfo {
+        filename: path.file_name()?.to_string_lossy().to_string(),
+        path: path.to_string_lossy().to_string(),
+        size: metadata.len(),
+        created_at,
+        scene_id,
+    })
+}
+
+/// All exports in `exports_dir`, newest first, keeping at most `limit`
+fn list_exports_in_dir(exports_dir: &Path, limit: Option<usize>) -> Result<Vec<ExportInfo>, String> {
     if !exports_dir.exists() {
         return Ok(Vec::new());
     }
 
     let mut exports: Vec<ExportInfo> = Vec::new();
-    let prefix = format!("excalidraw_{}_", scene_id);
-
-    for entry in fs::read_dir(&exports_dir)
+    for entry in fs::read_dir(exports_dir)
         .map_err(|e| format!("Failed to read exports directory: {}", e))?
     {
         let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-        let path = entry.path();
-
-        if path.extension().and_then(|e| e.to_str()) == Some("png") {
-            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
-                if name.starts_with(&prefix) {
-                    let metadata = path.metadata()
-                        .map_err(|e| format!("Failed to read metadata: {}", e))?;
-
-                    exports.push(ExportInfo {
-                        filename: path.file_name()
-                            .and_then(|n| n.to_str().map(|s| s.to_string()))
-                            .unwrap_or_default(),
-                        path: path.to_string_lossy().to_string(),
-                        size: metadata.len(),
-                        created_at: metadata.created()
-                            .ok()
-                            .and_then(|t| t.elapsed().ok())
-                            .map(|t| t.as_millis() as u64)
-                            .unwrap_or(0),
-                    });
-                }
-            }
+        if let Some(info) = export_info(&entry.path()) {
+            exports.push(info);
         }
     }
 
     // Sort by creation time descending
-    exports.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    exports.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.filename.cmp(&b.filename)));
+    if let Some(limit) = limit {
+        exports.truncate(limit);
+    }
 
     Ok(exports)
 }
@@ -450,8 +919,11 @@ pub struct ExportInfo {
     pub filename: String,
     pub path: String,
     pub size: u64,
+    /// Export time in milliseconds since the epoch, from the file name
     #[serde(rename = "createdAt")]
     pub created_at: u64,
+    #[serde(rename = "sceneId")]
+    pub scene_id: String,
 }
 
 /// Get scene metadata from file
@@ -498,3 +970,158 @@ fn extract_scene_metadata(path: &PathBuf) -> SceneMetadata {
     
     metadata
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_exports_across_scenes_newest_first() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in [
+            "excalidraw_excalidraw_a_20260101_090000.png",
+            "excalidraw_excalidraw_b_20260102_090000.svg",
+            "excalidraw_excalidraw_a_20260103_090000.png",
+            "excalidraw_excalidraw_b_20260101_120000.png",
+            "notes.txt",
+            "excalidraw_excalidraw_a_latest.png",
+        ] {
+            fs::write(temp_dir.path().join(name), b"data").unwrap();
+        }
+
+        let exports = list_exports_in_dir(temp_dir.path(), None).unwrap();
+        let order: Vec<(&str, &str)> = exports.iter()
+            .map(|e| (e.scene_id.as_str(), e.filename.rsplit('_').nth(1).unwrap()))
+            .collect();
+        assert_eq!(order, vec![
+            ("excalidraw_a", "20260103"),
+            ("excalidraw_b", "20260102"),
+            ("excalidraw_b", "20260101"),
+            ("excalidraw_a", "20260101"),
+        ]);
+        assert!(exports.windows(2).all(|w| w[0].created_at >= w[1].created_at));
+
+        let recent = list_exports_in_dir(temp_dir.path(), Some(2)).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].filename, "excalidraw_excalidraw_a_20260103_090000.png");
+    }
+
+    #[test]
+    fn test_valid_v2_scene_imports() {
+        let json_str = serde_json::to_string(&ExcalidrawSceneData::default()).unwrap();
+        let scene = parse_scene_with_schema_check(&json_str).unwrap();
+        assert_eq!(scene.version, 2);
+    }
+
+    #[test]
+    fn test_v1_scene_is_migrated() {
+        let json_str = r#"{"type":"excalidraw","version":1,"elements":[{"id":"a"}]}"#;
+        let scene = parse_scene_with_schema_check(json_str).unwrap();
+        assert_eq!(scene.version, 2);
+        assert_eq!(scene.elements.len(), 1);
+        assert!(scene.files.is_object());
+    }
+
+    #[test]
+    fn test_unsupported_version_is_rejected() {
+        let json_str = r#"{"type":"excalidraw","version":7,"source":"x","elements":[],"appState":{},"files":{}}"#;
+        let err = parse_scene_with_schema_check(json_str).unwrap_err();
+        assert!(err.contains("Unsupported Excalidraw schema version 7"));
+    }
+
+    #[test]
+    fn test_non_excalidraw_json_is_rejected() {
+        let err = parse_scene_with_schema_check(r#"{"name":"not a scene"}"#).unwrap_err();
+        assert!(err.contains("Not an Excalidraw scene"));
+    }
+
+    #[test]
+    fn test_merge_scenes_dedups_ids() {
+        let first = ExcalidrawSceneData {
+            elements: vec![
+                json!({"id": "a", "x": 0.0, "y": 0.0, "width": 50.0}),
+                json!({"id": "b", "x": 100.0, "y": 0.0, "width": 50.0}),
+            ],
+            ..Default::default()
+        };
+        let second = ExcalidrawSceneData {
+            elements: vec![
+                json!({"id": "a", "x": 0.0, "y": 0.0, "width": 10.0, "boundElements": [{"id": "c", "type": "text"}]}),
+                json!({"id": "c", "x": 0.0, "y": 0.0, "width": 10.0, "containerId": "a"}),
+            ],
+            ..Default::default()
+        };
+
+        let merged = merge_scenes(vec![first, second]);
+        assert_eq!(merged.elements.len(), 4);
+
+        let ids: HashSet<&str> = merged.elements.iter()
+            .filter_map(|el| el["id"].as_str())
+            .collect();
+        assert_eq!(ids.len(), 4);
+
+        // The renamed container keeps its bound text reference
+        assert_eq!(merged.elements[3]["containerId"], merged.elements[2]["id"]);
+        // The second scene starts to the right of the first one
+        assert!(merged.elements[2]["x"].as_f64().unwrap() >= 150.0);
+    }
+
+    #[test]
+    fn test_renamed_scene_is_listed_with_name() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scenes_dir = temp_dir.path().to_path_buf();
+        let path = scenes_dir.join("excalidraw_test.json");
+        fs::write(&path, serde_json::to_string(&ExcalidrawSceneData::default()).unwrap()).unwrap();
+
+        let updated = rename_scene_at_path(&path, "  Architecture sketch ").unwrap();
+
+        let scenes = list_scenes_in_dir(&scenes_dir, "").unwrap();
+        assert_eq!(scenes.len(), 1);
+        assert_eq!(scenes[0].id, "excalidraw_test");
+        assert_eq!(scenes[0].name.as_deref(), Some("Architecture sketch"));
+        assert_eq!(scenes[0].updated_at, updated);
+        assert!(rename_scene_at_path(&path, "   ").is_err());
+    }
+
+    #[test]
+    fn test_truncated_scene_is_detected_and_repaired() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("excalidraw_test.json");
+        let scene = ExcalidrawSceneData {
+            elements: (1..=3).map(|i| json!({ "id": format!("el{}", i), "type": "text", "text": "a \"quoted\" {brace}" })).collect(),
+            ..ExcalidrawSceneData::default()
+        };
+        let full = serde_json::to_string_pretty(&scene).unwrap();
+        fs::write(&path, &full).unwrap();
+        assert!(validate_scene_at_path(&path).loadable);
+        assert!(!repair_scene_at_path(&path).unwrap().repaired);
+
+        // Cut off in the middle of the third element
+        let cut = full.find("\"el3\"").unwrap() + 3;
+        fs::write(&path, &full[..cut]).unwrap();
+        let validation = validate_scene_at_path(&path);
+        assert!(!validation.loadable);
+        assert!(validation.error.unwrap().contains("Failed to parse scene"));
+
+        let result = repair_scene_at_path(&path).unwrap();
+        assert!(result.repaired);
+        assert_eq!(result.elements_recovered, 2);
+        assert!(!result.app_state_recovered);
+        assert_eq!(fs::read_to_string(result.backup_path.unwrap()).unwrap(), &full[..cut]);
+
+        assert!(validate_scene_at_path(&path).loadable);
+        let repaired: ExcalidrawSceneData = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(repaired.elements, scene.elements[..2]);
+        assert_eq!(repaired.files, json!({}));
+
+        // Cut off inside `files`: every element and the appState survive
+        let cut = full.find("\"files\"").unwrap() + 10;
+        fs::write(&path, &full[..cut]).unwrap();
+        let result = repair_scene_at_path(&path).unwrap();
+        assert_eq!(result.elements_recovered, 3);
+        assert!(result.app_state_recovered);
+
+        fs::write(&path, "not json at all").unwrap();
+        assert!(repair_scene_at_path(&path).is_err());
+    }
+}
@@ -11,8 +11,12 @@ use std::time::Instant;
 use futures::StreamExt;
 use crate::state::{
     SharedState, Message, ChatSession, DeepThinkingConfig, 
-    DeepThinkingStatus, ThinkingDepth, ParsedReasoning, ReasoningBlock, PixelState
+    DeepThinkingStatus, ThinkingDepth, ParsedReasoning, ReasoningBlock, PixelState, McpServerManager,
+    new_message_id,
 };
+use crate::commands::provider::{apply_provider_headers, record_provider_usage};
+use crate::commands::chat::{append_session_message, completion_usage, enforce_token_budget, estimate_prompt_tokens, fetch_resource_context, inject_resource_context, prepend_system_prompt, session_system_prompt, SseLineBuffer, StreamRegistration, TokenUsage};
+use crate::commands::config::http_client;
 
 /// Enable or configure Deep Thinking mode for a session
 #[tauri::command]
@@ -212,14 +216,18 @@ pub fn parse_reasoning_content_cmd(
 /// Enhanced version that handles reasoning content
 #[tauri::command]
 #[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
 pub async fn stream_chat_completions_with_thinking(
     messages: Vec<Message>,
     model_id: String,
     provider_id: String,
     deep_thinking: bool,
     thinking_depth: Option<ThinkingDepth>,
+    resource_refs: Option<Vec<(String, String)>>,
+    session_id: Option<String>,
     shared_state: State<'_, SharedState>,
     app_state: State<'_, PixelState>,
+    mcp_manager: State<'_, McpServerManager>,
 ) -> Result<String, String> {
     let app = app_state.app_handle.get();
     
@@ -237,6 +245,14 @@ pub async fn stream_chat_completions_with_thinking(
         return Err(format!("Provider '{}' is disabled", provider.name));
     }
 
+    let system_prompt = shared_state.read(|state| session_system_prompt(state, session_id.as_deref()));
+    let messages = prepend_system_prompt(messages, system_prompt.as_deref());
+    let resources = fetch_resource_context(&mcp_manager, &resource_refs.unwrap_or_default()).await?;
+    let (messages, sources) = inject_resource_context(messages, &resources);
+    if !sources.is_empty() {
+        let _ = app.emit("chat_context_injected", &json!({ "sources": sources }));
+    }
+
     // Prepare messages for API with thinking instructions if enabled
     let mut api_messages: Vec<serde_json::Value> = messages
         .iter()
@@ -252,32 +268,38 @@ pub async fn stream_chat_completions_with_thinking(
             ThinkingDepth::Deep => "Provide detailed step-by-step reasoning. Use <reasoning> tags for each step and explain your thought process thoroughly.",
         };
         
-        // Add system message for thinking instructions
-        api_messages.insert(0, json!({
+        // Add system message for thinking instructions, after the user's own system prompts
+        let position = api_messages.iter().take_while(|m| m["role"] == "system").count();
+        api_messages.insert(position, json!({
             "role": "system",
             "content": format!("{} Also, include your reasoning process in <reasoning>...</reasoning> tags.", depth_instruction)
         }));
     }
 
-    // Build request with thinking parameters
-    let max_tokens = if deep_thinking { 16384 } else { 4096 };
+    // Build request with thinking parameters, within what is left of the session's token budget
+    let max_tokens = enforce_token_budget(
+        &app,
+        &shared_state,
+        session_id.as_deref(),
+        estimate_prompt_tokens(&messages),
+        if deep_thinking { 16384 } else { 4096 },
+    )?;
     let temperature = match thinking_depth.unwrap_or(ThinkingDepth::Moderate) {
         ThinkingDepth::Deep => 0.5,
         ThinkingDepth::Surface => 0.9,
         _ => 0.7,
     };
 
-    let client = reqwest::Client::new();
-    let request = client
-        .post(format!("{}/chat/completions", provider.base_url))
-        .header("Authorization", format!("Bearer {}", provider.api_key))
-        .header("Content-Type", "application/json")
+    let client = http_client();
+    let request = client.post(format!("{}/chat/completions", provider.base_url));
+    let request = apply_provider_headers(request, &provider)
         .json(&json!({
             "model": model_id,
             "messages": api_messages,
             "stream": true,
             "max_tokens": max_tokens,
             "temperature": temperature,
+            "stream_options": { "include_usage": true },
         }));
 
     // Execute streaming request
@@ -294,107 +316,112 @@ pub async fn stream_chat_completions_with_thinking(
         }
     };
 
-    let message_id = uuid::Uuid::new_v4().to_string();
+    record_provider_usage(&provider.id);
+
+    let message_id = new_message_id();
+    // The reply belongs to the session charged for it above
+    let session_id = session_id.or_else(|| shared_state.read(|state| state.current_session_id.clone()));
+    let registration = StreamRegistration::new(&message_id, session_id.clone());
     let mut accumulated_content = String::new();
     let mut accumulated_reasoning = String::new();
     let mut reasoning_started = false;
+    let mut usage: Option<TokenUsage> = None;
+    let mut sse = SseLineBuffer::default();
 
     // Process stream chunks
     while let Some(chunk_result) = stream.next().await {
         match chunk_result {
             Ok(data) => {
-                let text = String::from_utf8_lossy(&data);
-
-                for line in text.lines() {
-                    if let Some(data_str) = line.strip_prefix("data: ") {
-
-                        if data_str == "[DONE]" {
-                            // Parse reasoning from accumulated content
-                            let parsed_reasoning = parse_reasoning_content_cmd(
-                                accumulated_reasoning.clone(),
-                                true,
-                                false,
-                            ).unwrap_or_else(|_| ParsedReasoning {
-                                original_content: accumulated_reasoning.clone(),
-                                reasoning_blocks: Vec::new(),
-                                total_steps: 0,
-                                total_duration_ms: 0,
-                            });
-
-                            // Create assistant message with reasoning
-                            let assistant_msg = Message {
-                                id: message_id.clone(),
-                                role: "assistant".to_string(),
-                                content: accumulated_content.clone(),
-                                timestamp: chrono::Utc::now().timestamp_millis() as u64,
-                                model_id: Some(model_id),
-                                attachments: Vec::new(),
-                                images: Vec::new(),
-                                reasoning_content: if accumulated_reasoning.is_empty() { None } else { Some(accumulated_reasoning.clone()) },
-                                reasoning_blocks: parsed_reasoning.reasoning_blocks,
-                                token_usage: None,
-                                is_deep_thinking: deep_thinking,
-                            };
-
-                            // Save to session
-                            shared_state.write(|state| {
-                                if let Some(session_id) = &state.current_session_id {
-                                    if let Some(session) = state.sessions.get_mut(session_id) {
-                                        session.messages.push(assistant_msg);
-                                        session.updated_at = chrono::Utc::now().timestamp_millis() as u64;
-                                    }
-                                }
-                            });
-
-                            // Emit stream end event with reasoning info
-                            let _ = app.emit("chat_stream_end", &json!({
-                                "message_id": message_id,
-                                "content": accumulated_content,
-                                "reasoning_content": accumulated_reasoning,
-                                "reasoning_steps": parsed_reasoning.total_steps,
-                                "is_deep_thinking": deep_thinking,
-                            }));
-
-                            return Ok(message_id);
-                        }
+                // Only complete lines are parsed; partial lines wait for the next chunk
+                for data_str in sse.push(&data) {
+                    if data_str == "[DONE]" {
+                        // Parse reasoning from accumulated content
+                        let parsed_reasoning = parse_reasoning_content_cmd(
+                            accumulated_reasoning.clone(),
+                            true,
+                            false,
+                        ).unwrap_or_else(|_| ParsedReasoning {
+                            original_content: accumulated_reasoning.clone(),
+                            reasoning_blocks: Vec::new(),
+                            total_steps: 0,
+                            total_duration_ms: 0,
+                        });
 
-                        // Parse JSON chunk
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(data_str) {
-                            if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    // Check for reasoning content in response
-                                    if let Some(delta) = choice.get("delta") {
-                                        // Check for content
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            accumulated_content.push_str(content);
-                                            
-                                            // Emit chunk event
-                                            let _ = app.emit("chat_chunk", &json!({
-                                                "message_id": message_id,
-                                                "chunk": content,
-                                                "content": accumulated_content,
-                                                "chunk_type": "content",
-                                                "is_deep_thinking": deep_thinking,
-                                            }));
-                                        }
+                        // Create assistant message with reasoning
+                        let assistant_msg = Message {
+                            id: message_id.clone(),
+                            role: "assistant".to_string(),
+                            content: accumulated_content.clone(),
+                            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+                            model_id: Some(model_id),
+                            attachments: Vec::new(),
+                            images: Vec::new(),
+                            reasoning_content: if accumulated_reasoning.is_empty() { None } else { Some(accumulated_reasoning.clone()) },
+                            reasoning_blocks: parsed_reasoning.reasoning_blocks,
+                            token_usage: usage.map(|u| u.total_tokens),
+                            prompt_tokens: usage.and_then(|u| u.prompt_tokens),
+                            completion_tokens: usage.and_then(|u| u.completion_tokens),
+                            is_deep_thinking: deep_thinking,
+                        };
+
+                        // Save to session
+                        append_session_message(&shared_state, session_id.as_deref(), assistant_msg);
+
+                        // Emit stream end event with reasoning info
+                        let _ = app.emit("chat_stream_end", &json!({
+                            "message_id": message_id,
+                            "content": accumulated_content,
+                            "reasoning_content": accumulated_reasoning,
+                            "reasoning_steps": parsed_reasoning.total_steps,
+                            "is_deep_thinking": deep_thinking,
+                            "usage": usage,
+                        }));
+
+                        return Ok(message_id);
+                    }
+
+                    // Parse JSON chunk
+                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&data_str) {
+                        // Usage arrives on the final chunk, which has no choices
+                        if let Some(chunk_usage) = completion_usage(&json) {
+                            usage = Some(chunk_usage);
+                        }
+                        if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
+                            if let Some(choice) = choices.first() {
+                                // Check for reasoning content in response
+                                if let Some(delta) = choice.get("delta") {
+                                    // Check for content
+                                    if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
+                                        accumulated_content.push_str(content);
+                                        registration.add_chunk(content);
                                         
-                                        // Check for reasoning content
-                                        if let Some(reasoning) = delta.get("reasoning_content").or(delta.get("reasoning")).and_then(|c| c.as_str()) {
-                                            if !reasoning_started {
-                                                reasoning_started = true;
-                                                accumulated_reasoning.push_str("<reasoning>");
-                                            }
-                                            accumulated_reasoning.push_str(reasoning);
-                                            
-                                            // Emit reasoning chunk
-                                            let _ = app.emit("chat_chunk", &json!({
-                                                "message_id": message_id,
-                                                "chunk": reasoning,
-                                                "content": accumulated_reasoning,
-                                                "chunk_type": "reasoning",
-                                                "is_deep_thinking": deep_thinking,
-                                            }));
+                                        // Emit chunk event
+                                        let _ = app.emit("chat_chunk", &json!({
+                                            "message_id": message_id,
+                                            "chunk": content,
+                                            "content": accumulated_content,
+                                            "chunk_type": "content",
+                                            "is_deep_thinking": deep_thinking,
+                                        }));
+                                    }
+                                    
+                                    // Check for reasoning content
+                                    if let Some(reasoning) = delta.get("reasoning_content").or(delta.get("reasoning")).and_then(|c| c.as_str()) {
+                                        if !reasoning_started {
+                                            reasoning_started = true;
+                                            accumulated_reasoning.push_str("<reasoning>");
                                         }
+                                        accumulated_reasoning.push_str(reasoning);
+                                        registration.add_chunk(reasoning);
+                                        
+                                        // Emit reasoning chunk
+                                        let _ = app.emit("chat_chunk", &json!({
+                                            "message_id": message_id,
+                                            "chunk": reasoning,
+                                            "content": accumulated_reasoning,
+                                            "chunk_type": "reasoning",
+                                            "is_deep_thinking": deep_thinking,
+                                        }));
                                     }
                                 }
                             }
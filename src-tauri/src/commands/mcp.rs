@@ -1,14 +1,18 @@
 //! MCP Server Commands - Full MCP Server management with process control
 //! Phase 5: MCP Server API Implementation with complete JSON-RPC support
 
-use tauri::State;
+use tauri::{Emitter, State};
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock, atomic::{AtomicU64, Ordering}, OnceLock};
-use std::process::{Command, Stdio, Child, ChildStdin, ChildStdout};
-use std::io::{BufRead, BufReader, Write};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock, atomic::{AtomicU32, AtomicU64, Ordering}, OnceLock};
+use std::process::{Command, Stdio, Child, ChildStdin};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::time::{Duration, Instant};
-use crate::state::{SharedState, McpServer, RunningMcpServer, McpServerManager, McpToolDefinition, McpServerStatusInfo};
+use futures::{Stream, StreamExt};
+use crate::state::{SharedState, PixelState, McpServer, RunningMcpServer, PendingMcpResponses, RemoteMcpServer, McpServerManager, McpToolDefinition, McpServerStatusInfo};
+use crate::commands::chat::SseLineBuffer;
+use crate::commands::config::http_client;
 
 /// MCP Server status
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +29,37 @@ pub struct McpToolResult {
     pub success: bool,
     pub content: serde_json::Value,
     pub is_error: bool,
+    /// Set when the result exceeded the max-bytes cap and `content` was cut off
+    #[serde(default)]
+    pub truncated: bool,
+    /// Typed blocks parsed from the result's `content` array
+    #[serde(default)]
+    pub blocks: Vec<McpContentBlock>,
+}
+
+/// One entry of an MCP tool result's `content` array
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum McpContentBlock {
+    Text {
+        text: String,
+    },
+    /// Base64 image data
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    /// Embedded resource with either `text` or base64 `blob` contents
+    Resource {
+        uri: String,
+        mime_type: Option<String>,
+        text: Option<String>,
+        blob: Option<String>,
+    },
+    /// Block of a type this client doesn't render, kept as sent
+    Other {
+        raw: serde_json::Value,
+    },
 }
 
 /// JSON-RPC Message types
@@ -54,97 +89,350 @@ struct JsonRpcError {
     data: Option<serde_json::Value>,
 }
 
-/// Generate unique JSON-RPC request ID
-static RPC_ID: OnceLock<AtomicU64> = OnceLock::new();
+/// Take the next JSON-RPC request id of a running server
+/// Ids are scoped per server, so a response can only match a request sent to that server
+fn next_rpc_id(
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    server_id: &str,
+) -> Result<u64, String> {
+    let servers = servers.read().map_err(|e| format!("Lock error: {}", e))?;
+    servers.get(server_id)
+        .map(RunningMcpServer::next_rpc_id)
+        .ok_or_else(|| format!("Server '{}' not running", server_id))
+}
 
-fn next_rpc_id() -> u64 {
-    RPC_ID.get_or_init(|| AtomicU64::new(1)).fetch_add(1, Ordering::SeqCst)
+/// Get the id the next JSON-RPC request to a running server will use
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_mcp_next_rpc_id(
+    mcp_manager: State<'_, McpServerManager>,
+    server_id: String,
+) -> Result<u64, String> {
+    let servers = mcp_manager.servers.read().map_err(|e| format!("Lock error: {}", e))?;
+    servers.get(&server_id)
+        .map(|s| s.rpc_id.load(Ordering::SeqCst))
+        .ok_or_else(|| format!("Server '{}' not running", server_id))
 }
 
-/// Send MCP request and get response with proper JSON-RPC handling
+/// Send MCP request and wait for the response carrying `request_id`
+/// The reply is delivered by the server's dispatcher thread, so no lock is held while
+/// waiting and concurrent requests to one server may be answered in any order
 fn send_mcp_request(
     server_id: &str,
     request: &str,
+    request_id: u64,
     servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
     timeout_ms: u64,
-) -> Result<String, String> {
-    let servers = servers.read().map_err(|e| e.to_string())?;
-    let server = servers.get(server_id).ok_or_else(|| "Server not running".to_string())?;
-    
-    let mut stdin = server.stdin.lock().map_err(|e| e.to_string())?;
-    let mut stdout_lock = server.stdout.lock().map_err(|e| e.to_string())?;
-    
-    // Send request with Content-Length header
-    let request_body = format!(
-        "Content-Length: {}\r\n\r\n{}",
-        request.len(),
-        request
-    );
-    
-    stdin.write_all(request_body.as_bytes()).map_err(|e| e.to_string())?;
-    stdin.flush().map_err(|e| e.to_string())?;
-    
-    // Read response with timeout
-    let start = Instant::now();
-    let mut response = String::new();
-    let mut headers_complete = false;
-    let mut content_length = 0;
-    
-    let reader = BufReader::new(&mut *stdout_lock);
-    
-    for line in reader.lines() {
-        // Check timeout
-        if start.elapsed() > Duration::from_millis(timeout_ms) {
-            return Err("Request timeout".to_string());
-        }
-        
-        let line = line.map_err(|e| e.to_string())?;
+) -> Result<serde_json::Value, String> {
+    let (receiver, pending) = {
+        let servers = servers.read().map_err(|e| e.to_string())?;
+        let server = servers.get(server_id).ok_or_else(|| "Server not running".to_string())?;
         
-        // Parse Content-Length header
-        if !headers_complete {
-            if let Some(length_str) = line.strip_prefix("Content-Length:") {
-                content_length = length_str.trim().parse::<usize>().map_err(|e| e.to_string())?;
-            } else if line.is_empty() {
-                headers_complete = true;
+        // Registered before sending so an immediate reply cannot be missed
+        let (sender, receiver) = mpsc::sync_channel(1);
+        {
+            let mut pending = server.pending_responses.lock().map_err(|e| e.to_string())?;
+            if pending.closed {
+                return Err("Empty response".to_string());
             }
-            continue;
+            pending.waiting.insert(request_id, sender);
         }
         
-        // Read content
-        if response.len() < content_length {
-            response.push_str(&line);
-            if response.len() >= content_length {
-                break;
+        // Send request with Content-Length header
+        if let Err(e) = write_mcp_message(server, request) {
+            forget_pending_response(&server.pending_responses, request_id);
+            return Err(e);
+        }
+        (receiver, Arc::clone(&server.pending_responses))
+    };
+    
+    match receiver.recv_timeout(Duration::from_millis(timeout_ms)) {
+        Ok(response) => Ok(response),
+        Err(RecvTimeoutError::Timeout) => {
+            forget_pending_response(&pending, request_id);
+            Err("Request timeout".to_string())
+        }
+        // The dispatcher drops every waiting sender once stdout closes
+        Err(RecvTimeoutError::Disconnected) => Err("Empty response".to_string()),
+    }
+}
+
+/// Stop waiting for `request_id`; a late response to it is then discarded
+fn forget_pending_response(pending: &Mutex<PendingMcpResponses>, request_id: u64) {
+    if let Ok(mut pending) = pending.lock() {
+        pending.waiting.remove(&request_id);
+    }
+}
+
+/// Read a server's stdout on a background thread, routing each response to its waiting request
+/// and answering the server's own requests on `stdin`. The thread ends once the process closes the pipe
+fn spawn_response_dispatcher<W: Write + Send + 'static>(
+    stdout: impl Read + Send + 'static,
+    stdin: Arc<Mutex<W>>,
+) -> Arc<Mutex<PendingMcpResponses>> {
+    let pending = Arc::new(Mutex::new(PendingMcpResponses::default()));
+    let routes = Arc::clone(&pending);
+    std::thread::spawn(move || route_mcp_responses(&mut BufReader::new(stdout), &routes, &stdin));
+    pending
+}
+
+/// Route framed messages to the requests waiting for them until the reader closes
+/// Notifications are dispatched as progress and requests from the server are answered on
+/// `replies`; responses nobody waits for (e.g. cancelled or timed out calls) are skipped.
+/// Once closed, every waiting request is released
+fn route_mcp_responses(reader: &mut impl BufRead, pending: &Mutex<PendingMcpResponses>, replies: &Mutex<impl Write>) {
+    while let Ok(message) = read_mcp_message(reader) {
+        let Ok(response) = serde_json::from_str::<serde_json::Value>(&message) else {
+            continue;
+        };
+        // Server requests carry ids from the server's own sequence, which overlap ours
+        if let (Some(method), Some(id)) = (response.get("method").and_then(|m| m.as_str()), response.get("id")) {
+            let _ = write_framed_message(replies, &server_request_reply(method, id).to_string());
+            continue;
+        }
+        let Ok(mut pending) = pending.lock() else {
+            return;
+        };
+        match response.get("id") {
+            Some(serde_json::Value::Null) if response.get("error").is_some() => {
+                // Errors the server could not attribute to a request (e.g. parse errors)
+                for (_, sender) in pending.waiting.drain() {
+                    let _ = sender.try_send(response.clone());
+                }
+            }
+            Some(id) => {
+                if let Some(sender) = response_rpc_id(id).and_then(|id| pending.waiting.remove(&id)) {
+                    let _ = sender.try_send(response);
+                }
+            }
+            None => {
+                drop(pending);
+                dispatch_tool_progress(&response);
             }
         }
     }
     
-    if response.is_empty() {
-        return Err("Empty response".to_string());
+    if let Ok(mut pending) = pending.lock() {
+        pending.closed = true;
+        pending.waiting.clear();
+    }
+}
+
+/// Reply to a request the server sent us: `ping` is answered, anything else (`roots/list`,
+/// `sampling/createMessage`, ...) needs a client capability we never advertise
+fn server_request_reply(method: &str, id: &serde_json::Value) -> serde_json::Value {
+    match method {
+        "ping" => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+        _ => serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32601, "message": format!("Method not found: {}", method) }
+        }),
     }
+}
+
+/// The request id a response refers to
+/// Some servers echo ids as strings, so both numeric and string ids are accepted
+fn response_rpc_id(id: &serde_json::Value) -> Option<u64> {
+    match id {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => s.trim().parse::<u64>().ok(),
+        _ => None,
+    }
+}
+
+/// Whether a response id refers to `request_id`
+fn rpc_id_matches(request_id: u64, id: &serde_json::Value) -> bool {
+    response_rpc_id(id) == Some(request_id)
+}
+
+/// Read one Content-Length framed message
+/// The body is read as exactly `Content-Length` bytes, so bodies may span lines and
+/// anything after them stays in the reader for the next message
+fn read_mcp_message(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut content_length = None;
+    let mut line = String::new();
     
-    Ok(response)
+    // Headers end at the first blank line; blank lines left over before them are skipped
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("Empty response".to_string());
+        }
+        let line = line.trim_end_matches('\n').trim_end_matches('\r');
+        
+        if let Some(length_str) = line.strip_prefix("Content-Length:") {
+            content_length = Some(length_str.trim().parse::<usize>().map_err(|e| e.to_string())?);
+        } else if line.is_empty() && content_length.is_some() {
+            break;
+        }
+    }
+    
+    let mut body = vec![0u8; content_length.unwrap_or_default()];
+    reader.read_exact(&mut body).map_err(|e| format!("Failed to read message body: {}", e))?;
+    String::from_utf8(body).map_err(|e| format!("Message body is not UTF-8: {}", e))
+}
+
+/// Write a Content-Length framed message to a server's stdin
+fn write_mcp_message(server: &RunningMcpServer, message: &str) -> Result<(), String> {
+    write_framed_message(&server.stdin, message)
+}
+
+fn write_framed_message(stdin: &Mutex<impl Write>, message: &str) -> Result<(), String> {
+    let mut stdin = stdin.lock().map_err(|e| e.to_string())?;
+    let framed = format!(
+        "Content-Length: {}\r\n\r\n{}",
+        message.len(),
+        message
+    );
+    stdin.write_all(framed.as_bytes()).map_err(|e| e.to_string())?;
+    stdin.flush().map_err(|e| e.to_string())
+}
+
+/// Send a JSON-RPC notification, which has no id and gets no response
+fn send_mcp_notification(
+    server_id: &str,
+    method: &str,
+    params: serde_json::Value,
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+) -> Result<(), String> {
+    let servers = servers.read().map_err(|e| e.to_string())?;
+    let server = servers.get(server_id).ok_or_else(|| "Server not running".to_string())?;
+    write_mcp_message(server, &json_rpc_request(method, &params).to_string())
+}
+
+/// Request timeout used when neither the call nor the server sets one
+pub const DEFAULT_MCP_TIMEOUT_MS: u64 = 10_000;
+
+/// JSON-RPC methods without side effects that are safe to retry
+const IDEMPOTENT_METHODS: &[&str] = &["tools/list", "resources/list", "ping"];
+
+/// Default number of retries for idempotent JSON-RPC calls
+const DEFAULT_RPC_MAX_RETRIES: u32 = 2;
+
+/// Upper bound for the configurable retry count
+const MAX_RPC_RETRIES: u32 = 5;
+
+/// Base backoff delay between retries, doubled after every attempt
+const RPC_RETRY_BASE_DELAY_MS: u64 = 100;
+
+/// Longest backoff delay between two retries
+const RPC_RETRY_MAX_DELAY_MS: u64 = 5_000;
+
+/// Configured number of retries for idempotent JSON-RPC calls
+static RPC_MAX_RETRIES: OnceLock<AtomicU32> = OnceLock::new();
+
+fn rpc_max_retries() -> &'static AtomicU32 {
+    RPC_MAX_RETRIES.get_or_init(|| AtomicU32::new(DEFAULT_RPC_MAX_RETRIES))
+}
+
+/// Number of retries allowed for a method (tools/call may have side effects and is never retried)
+fn retries_for_method(method: &str) -> u32 {
+    if IDEMPOTENT_METHODS.contains(&method) {
+        rpc_max_retries().load(Ordering::SeqCst)
+    } else {
+        0
+    }
 }
 
-/// Send JSON-RPC request and parse response
+/// Whether an error is transient (server busy) rather than a protocol or config failure
+fn is_transient_rpc_error(error: &str) -> bool {
+    error == "Request timeout" || error == "Empty response"
+}
+
+/// Backoff before retry number `retries` (0-based), capped at `RPC_RETRY_MAX_DELAY_MS`
+fn rpc_retry_delay_ms(base_delay_ms: u64, retries: u32) -> u64 {
+    let factor = 1u64.checked_shl(retries).unwrap_or(u64::MAX);
+    base_delay_ms.saturating_mul(factor).min(RPC_RETRY_MAX_DELAY_MS)
+}
+
+/// Run a JSON-RPC attempt, retrying transient failures with exponential backoff
+/// The backoff sleeps the calling thread, so async callers go through `send_server_request`
+fn with_rpc_retry<F>(
+    max_retries: u32,
+    base_delay_ms: u64,
+    mut attempt: F,
+) -> Result<serde_json::Value, String>
+where
+    F: FnMut() -> Result<serde_json::Value, String>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt() {
+            Err(e) if retries < max_retries && is_transient_rpc_error(&e) => {
+                std::thread::sleep(Duration::from_millis(rpc_retry_delay_ms(base_delay_ms, retries)));
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Send JSON-RPC request and parse response, retrying idempotent methods
 fn send_json_rpc_request(
     server_id: &str,
     method: &str,
     params: serde_json::Value,
     servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
 ) -> Result<serde_json::Value, String> {
-    let id = next_rpc_id();
-    let request = serde_json::json!({
+    with_rpc_retry(retries_for_method(method), RPC_RETRY_BASE_DELAY_MS, || {
+        send_json_rpc_request_once(server_id, method, &params, servers)
+    })
+}
+
+/// Assemble a JSON-RPC request body; the id is assigned when it is actually sent
+fn json_rpc_request(method: &str, params: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
         "jsonrpc": "2.0",
-        "id": id,
         "method": method,
         "params": params
-    });
-    
-    let response_str = send_mcp_request(server_id, &request.to_string(), servers, 10000)?;
-    let response: serde_json::Value = serde_json::from_str(&response_str)
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    })
+}
+
+/// Timeout for a request: the per-call override, then the server's own, then the default
+fn rpc_timeout_ms(
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    server_id: &str,
+    timeout_override: Option<u64>,
+) -> u64 {
+    timeout_override
+        .or_else(|| {
+            servers.read().ok()
+                .and_then(|servers| servers.get(server_id).and_then(|s| s.timeout_ms))
+        })
+        .unwrap_or(DEFAULT_MCP_TIMEOUT_MS)
+}
+
+/// Send a single JSON-RPC request and parse response
+fn send_json_rpc_request_once(
+    server_id: &str,
+    method: &str,
+    params: &serde_json::Value,
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+) -> Result<serde_json::Value, String> {
+    let id = next_rpc_id(servers, server_id)?;
+    let timeout_ms = rpc_timeout_ms(servers, server_id, None);
+    send_json_rpc_request_with_id(server_id, id, method, params, servers, timeout_ms)
+}
+
+/// Send a single JSON-RPC request under a pre-assigned id and parse response
+fn send_json_rpc_request_with_id(
+    server_id: &str,
+    id: u64,
+    method: &str,
+    params: &serde_json::Value,
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    timeout_ms: u64,
+) -> Result<serde_json::Value, String> {
+    let mut request = json_rpc_request(method, params);
+    request["id"] = serde_json::json!(id);
     
+    let response = send_mcp_request(server_id, &request.to_string(), id, servers, timeout_ms)?;
+    rpc_result(response)
+}
+
+/// The result of a JSON-RPC response, or its error message
+fn rpc_result(response: serde_json::Value) -> Result<serde_json::Value, String> {
     // Check for JSON-RPC error
     if let Some(error) = response.get("error") {
         let err_msg = error.get("message")
@@ -157,12 +445,107 @@ fn send_json_rpc_request(
     Ok(response.get("result").cloned().unwrap_or(serde_json::json!({})))
 }
 
+/// MCP protocol version requested in the initialize handshake
+const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// How long a freshly spawned server gets to answer `initialize`
+const MCP_INITIALIZE_TIMEOUT_MS: u64 = 10_000;
+
+/// Params of the `initialize` request
+fn initialize_params() -> serde_json::Value {
+    serde_json::json!({
+        "protocolVersion": MCP_PROTOCOL_VERSION,
+        "capabilities": {},
+        "clientInfo": {
+            "name": "pixel-client",
+            "version": env!("CARGO_PKG_VERSION")
+        }
+    })
+}
+
+/// The protocol version a server chose in its `initialize` result
+fn negotiated_protocol_version(server_id: &str, result: &serde_json::Value) -> Result<String, String> {
+    result.get("protocolVersion")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| format!("MCP server '{}' did not report a protocolVersion", server_id))
+}
+
+/// Run the MCP initialize handshake with a freshly spawned server
+/// Sends `initialize`, stores the negotiated protocol version on the running server and
+/// sends `notifications/initialized`; no other method may be called before this completes
+fn mcp_initialize(
+    server_id: &str,
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    timeout_ms: u64,
+) -> Result<String, String> {
+    let id = next_rpc_id(servers, server_id)?;
+    let result = send_json_rpc_request_with_id(server_id, id, "initialize", &initialize_params(), servers, timeout_ms)
+        .map_err(|e| match e.as_str() {
+            "Request timeout" => format!("MCP server '{}' did not answer initialize within {} ms", server_id, timeout_ms),
+            "Empty response" => format!("MCP server '{}' exited before answering initialize", server_id),
+            _ => format!("MCP server '{}' failed to initialize: {}", server_id, e),
+        })?;
+
+    let protocol_version = negotiated_protocol_version(server_id, &result)?;
+    {
+        let mut servers = servers.write().map_err(|e| e.to_string())?;
+        let server = servers.get_mut(server_id).ok_or_else(|| "Server not running".to_string())?;
+        server.protocol_version = Some(protocol_version.clone());
+    }
+
+    send_mcp_notification(server_id, "notifications/initialized", serde_json::json!({}), servers)?;
+    Ok(protocol_version)
+}
+
+/// Number of stderr lines kept per running server
+const MCP_STDERR_MAX_LINES: usize = 500;
+
+/// Number of stderr lines appended to a failed start's error
+const MCP_STARTUP_ERROR_LINES: usize = 20;
+
+/// Drain a server's stderr on a background thread into a ring buffer of its last lines
+/// The thread ends once the process closes the pipe
+fn capture_stderr(stderr: impl Read + Send + 'static) -> Arc<Mutex<VecDeque<String>>> {
+    let log = Arc::new(Mutex::new(VecDeque::new()));
+    let sink = Arc::clone(&log);
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).split(b'\n') {
+            let Ok(line) = line else { break };
+            let Ok(mut lines) = sink.lock() else { break };
+            if lines.len() == MCP_STDERR_MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string());
+        }
+    });
+    log
+}
+
+/// The last `max_lines` lines of a stderr log, oldest first
+fn stderr_tail(log: &Mutex<VecDeque<String>>, max_lines: usize) -> Vec<String> {
+    let Ok(lines) = log.lock() else {
+        return Vec::new();
+    };
+    lines.iter().skip(lines.len().saturating_sub(max_lines)).cloned().collect()
+}
+
+/// Append what the server printed to stderr to an error from starting it
+fn with_stderr_tail(error: String, log: &Mutex<VecDeque<String>>) -> String {
+    let tail = stderr_tail(log, MCP_STARTUP_ERROR_LINES);
+    if tail.is_empty() {
+        error
+    } else {
+        format!("{}\nServer stderr:\n{}", error, tail.join("\n"))
+    }
+}
+
 /// Discover tools from running MCP server
 async fn discover_tools(
     server_id: &str,
     mcp_manager: &McpServerManager,
 ) -> Result<Vec<McpToolDefinition>, String> {
-    let result = send_json_rpc_request(server_id, "tools/list", serde_json::json!({}), &mcp_manager.servers)?;
+    let result = send_server_request(mcp_manager, server_id, "tools/list", serde_json::json!({})).await?;
 
     let mut tools = Vec::new();
 
@@ -193,6 +576,213 @@ async fn discover_tools(
     Ok(tools)
 }
 
+// ============================================
+// HTTP / SSE Transport
+// ============================================
+
+/// Whether a server type is reached over HTTP rather than a child process
+fn is_remote_transport(server_type: &str) -> bool {
+    server_type == "http"
+}
+
+/// Reject server types this client cannot connect to
+/// The legacy `sse` transport answers POSTs on a separate GET stream and is not supported;
+/// servers offering it generally also speak Streamable HTTP (`http`)
+fn check_server_type(server_id: &str, server_type: &str) -> Result<(), String> {
+    if server_type == "sse" {
+        return Err(format!(
+            "MCP server '{}' uses the legacy SSE transport, which is not supported; use type 'http' (Streamable HTTP)",
+            server_id
+        ));
+    }
+    Ok(())
+}
+
+/// Header carrying the session id a remote server assigns on initialize
+const MCP_SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// Header carrying the negotiated protocol version on requests after initialize
+const MCP_PROTOCOL_VERSION_HEADER: &str = "MCP-Protocol-Version";
+
+fn is_remote_running(remote_servers: &Arc<RwLock<HashMap<String, RemoteMcpServer>>>, server_id: &str) -> bool {
+    remote_servers.read().is_ok_and(|servers| servers.contains_key(server_id))
+}
+
+/// POST one JSON-RPC message to a remote server
+/// A request's response comes back as a JSON body or on the SSE stream the server answers
+/// with; a notification returns Null once accepted. Also returns the session id the server set
+async fn post_remote_message(
+    url: &str,
+    session_id: Option<&str>,
+    protocol_version: Option<&str>,
+    message: &serde_json::Value,
+    timeout_ms: u64,
+) -> Result<(serde_json::Value, Option<String>), String> {
+    let mut request = http_client().post(url)
+        .header("Accept", "application/json, text/event-stream")
+        .timeout(Duration::from_millis(timeout_ms))
+        .json(message);
+    if let Some(session_id) = session_id {
+        request = request.header(MCP_SESSION_HEADER, session_id);
+    }
+    if let Some(protocol_version) = protocol_version {
+        request = request.header(MCP_PROTOCOL_VERSION_HEADER, protocol_version);
+    }
+
+    let resp = request.send().await.map_err(|e| format!("Request failed: {}", e))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let error_text = resp.text().await.unwrap_or_default();
+        return Err(format!("MCP server returned HTTP {}: {}", status, error_text));
+    }
+    let session_id = resp.headers().get(MCP_SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let Some(request_id) = message.get("id").and_then(|id| id.as_u64()) else {
+        return Ok((serde_json::Value::Null, session_id));
+    };
+    let is_event_stream = resp.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|t| t.starts_with("text/event-stream"));
+    let response = if is_event_stream {
+        read_sse_rpc_response(resp.bytes_stream(), request_id).await?
+    } else {
+        resp.json().await.map_err(|e| format!("Failed to parse response: {}", e))?
+    };
+    Ok((response, session_id))
+}
+
+/// Read an SSE stream until the JSON-RPC response to `request_id`
+/// Notifications sent ahead of it are handled as on stdio (progress is forwarded)
+async fn read_sse_rpc_response<S, B, E>(mut stream: S, request_id: u64) -> Result<serde_json::Value, String>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: std::fmt::Display,
+{
+    let mut sse = SseLineBuffer::default();
+    while let Some(chunk) = stream.next().await {
+        let data = chunk.map_err(|e| format!("Stream error: {}", e))?;
+        for data_str in sse.push(data.as_ref()) {
+            let Ok(message) = serde_json::from_str::<serde_json::Value>(&data_str) else {
+                continue;
+            };
+            // Server requests share the id space with responses; they are not answered over SSE
+            if message.get("method").is_some() && message.get("id").is_some() {
+                continue;
+            }
+            match message.get("id") {
+                Some(id) if rpc_id_matches(request_id, id) => return Ok(message),
+                Some(serde_json::Value::Null) if message.get("error").is_some() => return Ok(message),
+                None => dispatch_tool_progress(&message),
+                _ => {}
+            }
+        }
+    }
+    Err("Empty response".to_string())
+}
+
+/// Send a JSON-RPC request to a connected remote server and return its result
+/// With `on_progress` the request carries a progress token and notifications are forwarded
+async fn send_remote_json_rpc(
+    remote_servers: &Arc<RwLock<HashMap<String, RemoteMcpServer>>>,
+    server_id: &str,
+    method: &str,
+    params: serde_json::Value,
+    timeout_ms: Option<u64>,
+    on_progress: Option<ProgressListener>,
+) -> Result<serde_json::Value, String> {
+    let (id, url, session_id, protocol_version, server_timeout_ms) = {
+        let servers = remote_servers.read().map_err(|e| format!("Lock error: {}", e))?;
+        let server = servers.get(server_id).ok_or_else(|| "Server not running".to_string())?;
+        (server.next_rpc_id(), server.url.clone(), server.session_id.clone(), server.protocol_version.clone(), server.timeout_ms)
+    };
+    let timeout_ms = timeout_ms.or(server_timeout_ms).unwrap_or(DEFAULT_MCP_TIMEOUT_MS);
+
+    let token = progress_token(server_id, id);
+    let (params, _subscription) = match on_progress {
+        Some(listener) => (with_progress_token(params, &token), Some(ProgressSubscription::new(token, listener))),
+        None => (params, None),
+    };
+    let mut request = json_rpc_request(method, &params);
+    request["id"] = serde_json::json!(id);
+    let (response, _) = post_remote_message(&url, session_id.as_deref(), protocol_version.as_deref(), &request, timeout_ms).await?;
+    rpc_result(response)
+}
+
+/// Connect to an `http` (Streamable HTTP) server: run the initialize handshake over HTTP
+async fn connect_remote_server(config: &McpServer) -> Result<RemoteMcpServer, String> {
+    let url = config.url.as_deref()
+        .filter(|url| !url.trim().is_empty())
+        .ok_or_else(|| format!("MCP server '{}' has no URL", config.id))?;
+
+    let mut request = json_rpc_request("initialize", &initialize_params());
+    request["id"] = serde_json::json!(1);
+    let (response, session_id) = post_remote_message(url, None, None, &request, MCP_INITIALIZE_TIMEOUT_MS)
+        .await
+        .and_then(|(response, session_id)| Ok((rpc_result(response)?, session_id)))
+        .map_err(|e| format!("MCP server '{}' failed to initialize: {}", config.id, e))?;
+    let protocol_version = negotiated_protocol_version(&config.id, &response)?;
+
+    let notification = json_rpc_request("notifications/initialized", &serde_json::json!({}));
+    post_remote_message(url, session_id.as_deref(), Some(&protocol_version), &notification, MCP_INITIALIZE_TIMEOUT_MS).await?;
+
+    Ok(RemoteMcpServer {
+        url: url.to_string(),
+        session_id,
+        timeout_ms: config.timeout_ms,
+        rpc_id: AtomicU64::new(2),
+        protocol_version: Some(protocol_version),
+    })
+}
+
+/// Forget a connected remote server; returns whether it was connected
+fn disconnect_remote_server(
+    remote_servers: &Arc<RwLock<HashMap<String, RemoteMcpServer>>>,
+    server_id: &str,
+) -> Result<bool, String> {
+    let mut servers = remote_servers.write().map_err(|e| e.to_string())?;
+    Ok(servers.remove(server_id).is_some())
+}
+
+/// Send a JSON-RPC request to a running server over whichever transport it uses
+/// stdio round trips block, so they run on the blocking pool rather than a runtime worker
+async fn send_server_request(
+    mcp_manager: &McpServerManager,
+    server_id: &str,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    if is_remote_running(&mcp_manager.remote_servers, server_id) {
+        return send_remote_json_rpc(&mcp_manager.remote_servers, server_id, method, params, None, None).await;
+    }
+    let servers = Arc::clone(&mcp_manager.servers);
+    let (server_id, method) = (server_id.to_string(), method.to_string());
+    tokio::task::spawn_blocking(move || send_json_rpc_request(&server_id, &method, params, &servers))
+        .await
+        .map_err(|e| format!("MCP request task failed: {}", e))?
+}
+
+/// Connect a remote server, register it and list its tools
+async fn start_remote_server(config: &McpServer, mcp_manager: &McpServerManager) -> Result<McpServerStatus, String> {
+    let remote = connect_remote_server(config).await?;
+    {
+        let mut servers = mcp_manager.remote_servers.write().map_err(|e| e.to_string())?;
+        servers.insert(config.id.clone(), remote);
+    }
+
+    let tools = discover_tools(&config.id, mcp_manager).await
+        .unwrap_or_else(|_| Vec::new());
+
+    Ok(McpServerStatus {
+        server_id: config.id.clone(),
+        running: true,
+        tools,
+        error: None,
+    })
+}
+
 // ============================================
 // Public Commands
 // ============================================
@@ -231,8 +821,11 @@ pub fn create_mcp_server(
     command: String,
     args: Vec<String>,
     env: HashMap<String, String>,
+    url: Option<String>,
+    cwd: Option<String>,
 ) -> Result<McpServer, String> {
     let server_id = uuid::Uuid::new_v4().to_string();
+    check_server_type(&server_id, &server_type)?;
     
     let new_server = McpServer {
         id: server_id.clone(),
@@ -240,6 +833,9 @@ pub fn create_mcp_server(
         command,
         args,
         env,
+        timeout_ms: None,
+        url: url.filter(|u| !u.trim().is_empty()),
+        cwd: validate_mcp_cwd(cwd)?,
     };
     
     shared_state.write(|state| {
@@ -258,14 +854,21 @@ pub fn update_mcp_server(
     command: Option<String>,
     args: Option<Vec<String>>,
     env: Option<HashMap<String, String>>,
+    url: Option<String>,
+    cwd: Option<String>,
 ) -> Result<McpServer, String> {
     let mut updated = None;
+    // An empty directory clears it
+    let cwd = cwd.map(|dir| validate_mcp_cwd(Some(dir))).transpose()?;
     
     shared_state.write(|state| {
         if let Some(server) = state.mcp_servers.iter_mut().find(|s| s.id == server_id) {
             if let Some(c) = command { server.command = c; }
             if let Some(a) = args { server.args = a; }
             if let Some(e) = env { server.env = e; }
+            // An empty URL clears it
+            if let Some(u) = url { server.url = Some(u).filter(|u| !u.trim().is_empty()); }
+            if let Some(dir) = cwd { server.cwd = dir; }
             updated = Some(server.clone());
         }
     });
@@ -276,6 +879,29 @@ pub fn update_mcp_server(
     }
 }
 
+/// Check that a server's working directory exists; empty means none
+fn validate_mcp_cwd(cwd: Option<String>) -> Result<Option<String>, String> {
+    match cwd.filter(|dir| !dir.trim().is_empty()) {
+        Some(dir) if std::path::Path::new(&dir).is_dir() => Ok(Some(dir)),
+        Some(dir) => Err(format!("Working directory '{}' does not exist", dir)),
+        None => Ok(None),
+    }
+}
+
+/// Process builder for a `stdio` server, with piped stdio and its env and working directory
+fn mcp_server_command(config: &McpServer) -> Command {
+    let mut command = Command::new(&config.command);
+    command.args(&config.args)
+        .envs(&config.env)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = &config.cwd {
+        command.current_dir(dir);
+    }
+    command
+}
+
 /// Delete an MCP server
 #[tauri::command]
 #[allow(dead_code)]
@@ -298,6 +924,134 @@ pub fn delete_mcp_server(
     removed
 }
 
+/// Env variable name fragments whose values are treated as secrets on export
+const SENSITIVE_ENV_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD", "CREDENTIAL"];
+
+/// Placeholder written in place of redacted env values
+const REDACTED_ENV_VALUE: &str = "<redacted>";
+
+/// Export all MCP server configs in the Claude Desktop `{ mcpServers: {...} }` shape
+#[tauri::command]
+#[allow(dead_code)]
+pub fn export_mcp_servers_json(
+    shared_state: State<'_, SharedState>,
+    redact_secrets: Option<bool>,
+) -> Result<String, String> {
+    let servers = shared_state.read(|state| state.mcp_servers.clone());
+    let export = servers_to_config_json(&servers, redact_secrets.unwrap_or(false));
+    serde_json::to_string_pretty(&export)
+        .map_err(|e| format!("Failed to serialize MCP servers: {}", e))
+}
+
+/// Import MCP servers from a Claude Desktop style config; existing ids are updated
+#[tauri::command]
+#[allow(dead_code)]
+pub fn import_mcp_servers_from_json(
+    shared_state: State<'_, SharedState>,
+    json_str: String,
+) -> Result<Vec<McpServer>, String> {
+    let imported = servers_from_config_json(&json_str)?;
+
+    shared_state.write(|state| {
+        for server in &imported {
+            match state.mcp_servers.iter_mut().find(|s| s.id == server.id) {
+                Some(existing) => *existing = server.clone(),
+                None => state.mcp_servers.push(server.clone()),
+            }
+        }
+    });
+
+    Ok(imported)
+}
+
+fn servers_to_config_json(servers: &[McpServer], redact_secrets: bool) -> serde_json::Value {
+    let entries: serde_json::Map<String, serde_json::Value> = servers.iter()
+        .map(|server| {
+            let env: serde_json::Map<String, serde_json::Value> = server.env.iter()
+                .map(|(key, value)| {
+                    let upper = key.to_uppercase();
+                    let sensitive = SENSITIVE_ENV_MARKERS.iter().any(|m| upper.contains(m));
+                    let value = if redact_secrets && sensitive { REDACTED_ENV_VALUE } else { value.as_str() };
+                    (key.clone(), serde_json::json!(value))
+                })
+                .collect();
+
+            if is_remote_transport(&server.server_type) {
+                let entry = serde_json::json!({
+                    "type": server.server_type,
+                    "url": server.url,
+                });
+                return (server.id.clone(), entry);
+            }
+
+            let mut entry = serde_json::json!({
+                "command": server.command,
+                "args": server.args,
+                "env": env,
+            });
+            if server.server_type != "stdio" {
+                entry["type"] = serde_json::json!(server.server_type);
+            }
+            if let Some(cwd) = &server.cwd {
+                entry["cwd"] = serde_json::json!(cwd);
+            }
+            (server.id.clone(), entry)
+        })
+        .collect();
+
+    serde_json::json!({ "mcpServers": entries })
+}
+
+fn servers_from_config_json(json_str: &str) -> Result<Vec<McpServer>, String> {
+    let value: serde_json::Value = serde_json::from_str(json_str)
+        .map_err(|e| format!("Invalid MCP config JSON: {}", e))?;
+    let entries = value.get("mcpServers")
+        .and_then(|s| s.as_object())
+        .ok_or("MCP config must contain an 'mcpServers' object")?;
+
+    entries.iter()
+        .map(|(id, entry)| {
+            let url = entry.get("url")
+                .and_then(|u| u.as_str())
+                .map(str::to_string);
+            // Remote servers are listed by URL alone
+            let server_type = entry.get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or(if url.is_some() { "http" } else { "stdio" });
+            check_server_type(id, server_type)?;
+            let command = match entry.get("command").and_then(|c| c.as_str()) {
+                Some(command) => command,
+                None if is_remote_transport(server_type) => "",
+                None => return Err(format!("MCP server '{}' is missing 'command'", id)),
+            };
+            let args = entry.get("args")
+                .and_then(|a| a.as_array())
+                .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+            let env = entry.get("env")
+                .and_then(|e| e.as_object())
+                .map(|e| e.iter().filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string()))).collect())
+                .unwrap_or_default();
+            let cwd = entry.get("cwd")
+                .and_then(|c| c.as_str())
+                .map(str::to_string);
+            let cwd = validate_mcp_cwd(cwd)
+                .map_err(|e| format!("MCP server '{}': {}", id, e))?;
+
+            Ok(McpServer {
+                id: id.clone(),
+                server_type: server_type.to_string(),
+                command: command.to_string(),
+                args,
+                env,
+                timeout_ms: None,
+                url,
+                cwd,
+            })
+        })
+        .collect()
+}
+
 /// Start an MCP server process
 #[tauri::command]
 #[allow(dead_code)]
@@ -314,11 +1068,12 @@ pub async fn start_mcp_server(
         Some(s) => s,
         None => return Err(format!("MCP Server '{}' not found", server_id)),
     };
+    check_server_type(&server_id, &config.server_type)?;
     
     // Check if already running
     {
         let servers = mcp_manager.servers.read().map_err(|e| e.to_string())?;
-        if servers.contains_key(&server_id) {
+        if servers.contains_key(&server_id) || is_remote_running(&mcp_manager.remote_servers, &server_id) {
             return Ok(McpServerStatus {
                 server_id,
                 running: true,
@@ -328,28 +1083,36 @@ pub async fn start_mcp_server(
         }
     }
     
+    if is_remote_transport(&config.server_type) {
+        return start_remote_server(&config, &mcp_manager).await;
+    }
+    
     // Spawn the process
-    let mut child = Command::new(&config.command)
-        .args(&config.args)
-        .envs(&config.env)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+    let mut child = mcp_server_command(&config)
         .spawn()
         .map_err(|e| format!("Failed to spawn process: {}", e))?;
     
     let stdin = child.stdin.take()
+        .map(|stdin| Arc::new(Mutex::new(stdin)))
         .ok_or_else(|| "Failed to get stdin".to_string())?;
     
     let stdout = child.stdout.take()
         .ok_or_else(|| "Failed to get stdout".to_string())?;
     
+    let stderr = child.stderr.take()
+        .ok_or_else(|| "Failed to get stderr".to_string())?;
+    let stderr_log = capture_stderr(stderr);
+    
     // Store the running server
     let running_server = RunningMcpServer {
         server_id: server_id.clone(),
         process: child,
-        stdin: std::sync::Mutex::new(stdin),
-        stdout: std::sync::Mutex::new(stdout),
+        stdin: Arc::clone(&stdin),
+        pending_responses: spawn_response_dispatcher(stdout, stdin),
+        timeout_ms: config.timeout_ms,
+        rpc_id: AtomicU64::new(1),
+        protocol_version: None,
+        stderr_log: Arc::clone(&stderr_log),
     };
     
     {
@@ -357,11 +1120,14 @@ pub async fn start_mcp_server(
         servers.insert(server_id.clone(), running_server);
     }
     
-    // Give the server a moment to initialize
-    tokio::time::sleep(Duration::from_millis(500)).await;
+    // Handshake before any other request; a server that never initializes is stopped again
+    if let Err(e) = mcp_initialize(&server_id, &mcp_manager.servers, MCP_INITIALIZE_TIMEOUT_MS) {
+        let _ = stop_mcp_server_internal(&server_id, &mcp_manager.servers);
+        return Err(with_stderr_tail(e, &stderr_log));
+    }
     
     // Ping to verify
-    let ping_result = send_json_rpc_request(&server_id, "ping", serde_json::json!({}), &mcp_manager.servers);
+    let ping_result = send_server_request(&mcp_manager, &server_id, "ping", serde_json::json!({})).await;
     
     if ping_result.is_err() {
         // Server might not support ping, that's OK
@@ -379,93 +1145,598 @@ pub async fn start_mcp_server(
     })
 }
 
-/// Stop an MCP server process
-#[tauri::command]
-#[allow(dead_code)]
-pub fn stop_mcp_server(
-    mcp_manager: State<'_, McpServerManager>,
-    server_id: String,
-) -> Result<bool, String> {
-    let mut servers = mcp_manager.servers.write().map_err(|e| e.to_string())?;
-    
-    if let Some(mut running) = servers.remove(&server_id) {
-        // Send terminate request via JSON-RPC
-        let _ = send_json_rpc_request(
-            &running.server_id, 
-            "terminate", 
-            serde_json::json!({}), 
-            &mcp_manager.servers
-        );
-        
-        // Give it a moment to clean up
-        std::thread::sleep(Duration::from_millis(100));
-        
-        // Kill the process if still running
-        let _ = running.process.kill();
-        let _ = running.process.wait();
-        
-        return Ok(true);
+/// Stop an MCP server process
+#[tauri::command]
+#[allow(dead_code)]
+pub fn stop_mcp_server(
+    mcp_manager: State<'_, McpServerManager>,
+    server_id: String,
+) -> Result<bool, String> {
+    if disconnect_remote_server(&mcp_manager.remote_servers, &server_id)? {
+        return Ok(true);
+    }
+    
+    let mut servers = mcp_manager.servers.write().map_err(|e| e.to_string())?;
+    
+    if let Some(mut running) = servers.remove(&server_id) {
+        // Send terminate request via JSON-RPC
+        let _ = send_json_rpc_request(
+            &running.server_id, 
+            "terminate", 
+            serde_json::json!({}), 
+            &mcp_manager.servers
+        );
+        
+        // Give it a moment to clean up
+        std::thread::sleep(Duration::from_millis(100));
+        
+        // Kill the process if still running
+        let _ = running.process.kill();
+        let _ = running.process.wait();
+        
+        return Ok(true);
+    }
+    
+    Ok(false)
+}
+
+/// Get available tools from an MCP server
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn get_mcp_server_tools(
+    shared_state: State<'_, SharedState>,
+    mcp_manager: State<'_, McpServerManager>,
+    server_id: String,
+) -> Result<Vec<McpToolDefinition>, String> {
+    let server = shared_state.read(|state| {
+        state.mcp_servers.iter().find(|s| s.id == server_id).cloned()
+    });
+    
+    match server {
+        Some(_s) => {
+            // Check if server is running
+            let is_running = {
+                let servers = mcp_manager.servers.read().map_err(|e| e.to_string())?;
+                servers.contains_key(&server_id)
+            } || is_remote_running(&mcp_manager.remote_servers, &server_id);
+            
+            if is_running {
+                discover_tools(&server_id, &mcp_manager)
+                    .await
+                    .map_err(|e| e.to_string())
+            } else {
+                Ok(Vec::new())
+            }
+        }
+        None => Err(format!("MCP Server '{}' not found", server_id)),
+    }
+}
+
+/// Tool call that can be cancelled through `cancel_mcp_tool_call`
+struct PendingToolCall {
+    request_id: u64,
+    cancel: tokio::sync::oneshot::Sender<()>,
+}
+
+/// In-flight cancellable tool calls, keyed by (server id, caller-supplied call id)
+static PENDING_TOOL_CALLS: OnceLock<Mutex<HashMap<(String, String), PendingToolCall>>> = OnceLock::new();
+
+fn pending_tool_calls() -> &'static Mutex<HashMap<(String, String), PendingToolCall>> {
+    PENDING_TOOL_CALLS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Progress a server reported for a tool call through `notifications/progress`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct McpToolProgress {
+    pub progress: f64,
+    pub total: Option<f64>,
+    /// `progress` as a share of `total`, when the server sent a total
+    pub percent: Option<f64>,
+    pub message: Option<String>,
+}
+
+/// Receives the progress of one tool call
+type ProgressListener = Box<dyn FnMut(McpToolProgress) + Send>;
+
+/// Progress listeners of in-flight tool calls, keyed by progress token
+static PROGRESS_LISTENERS: OnceLock<Mutex<HashMap<String, ProgressListener>>> = OnceLock::new();
+
+fn progress_listeners() -> &'static Mutex<HashMap<String, ProgressListener>> {
+    PROGRESS_LISTENERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Progress token sent with a tool call; request ids are per server, so the server id is included
+fn progress_token(server_id: &str, request_id: u64) -> String {
+    format!("{}:{}", server_id, request_id)
+}
+
+/// Listener registered for the duration of one tool call, removed again on drop
+struct ProgressSubscription {
+    token: String,
+}
+
+impl ProgressSubscription {
+    fn new(token: String, listener: ProgressListener) -> Self {
+        if let Ok(mut listeners) = progress_listeners().lock() {
+            listeners.insert(token.clone(), listener);
+        }
+        Self { token }
+    }
+}
+
+impl Drop for ProgressSubscription {
+    fn drop(&mut self) {
+        if let Ok(mut listeners) = progress_listeners().lock() {
+            listeners.remove(&self.token);
+        }
+    }
+}
+
+/// Forward a `notifications/progress` message to the listener of its progress token
+/// Other notifications, and progress for calls nobody listens to, are ignored
+fn dispatch_tool_progress(message: &serde_json::Value) {
+    if message.get("method").and_then(|m| m.as_str()) != Some("notifications/progress") {
+        return;
+    }
+    let Some(params) = message.get("params") else {
+        return;
+    };
+    let token = match params.get("progressToken") {
+        Some(serde_json::Value::String(token)) => token.clone(),
+        Some(serde_json::Value::Number(token)) => token.to_string(),
+        _ => return,
+    };
+    let Some(progress) = params.get("progress").and_then(|p| p.as_f64()) else {
+        return;
+    };
+    let total = params.get("total").and_then(|t| t.as_f64()).filter(|t| *t > 0.0);
+
+    if let Ok(mut listeners) = progress_listeners().lock() {
+        if let Some(listener) = listeners.get_mut(&token) {
+            listener(McpToolProgress {
+                progress,
+                total,
+                percent: total.map(|total| (progress / total * 100.0).min(100.0)),
+                message: params.get("message").and_then(|m| m.as_str()).map(str::to_string),
+            });
+        }
+    }
+}
+
+/// Opt a tool call in to progress notifications by attaching `_meta.progressToken`
+fn with_progress_token(mut params: serde_json::Value, token: &str) -> serde_json::Value {
+    params["_meta"] = serde_json::json!({ "progressToken": token });
+    params
+}
+
+/// Call an MCP tool
+/// Arguments are coerced to `input_schema` when given; with `dry_run` the assembled
+/// request is returned as `content: { request, arguments }` without contacting the server.
+/// Supplying a `call_id` makes the call cancellable via `cancel_mcp_tool_call`.
+/// `timeout_ms` overrides the server's configured timeout for this call only.
+/// Progress notifications from the server are re-emitted as `mcp:tool_progress` events
+#[tauri::command]
+#[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
+pub async fn call_mcp_tool(
+    mcp_manager: State<'_, McpServerManager>,
+    app_state: State<'_, PixelState>,
+    server_id: String,
+    tool_name: String,
+    arguments: serde_json::Value,
+    input_schema: Option<serde_json::Value>,
+    dry_run: Option<bool>,
+    call_id: Option<String>,
+    timeout_ms: Option<u64>,
+) -> Result<McpToolResult, String> {
+    let dry_run = dry_run.unwrap_or(false);
+    let app = app_state.app_handle.get();
+    let event_server_id = server_id.clone();
+    let event_tool_name = tool_name.clone();
+    let event_call_id = call_id.clone();
+    let on_progress: ProgressListener = Box::new(move |progress| {
+        let _ = app.emit("mcp:tool_progress", &serde_json::json!({
+            "server_id": event_server_id,
+            "tool_name": event_tool_name,
+            "call_id": event_call_id,
+            "progress": progress.progress,
+            "total": progress.total,
+            "percent": progress.percent,
+            "message": progress.message,
+        }));
+    });
+
+    if !dry_run && is_remote_running(&mcp_manager.remote_servers, &server_id) {
+        let params = tool_call_params(&tool_name, arguments, input_schema.as_ref());
+        let result = send_remote_json_rpc(
+            &mcp_manager.remote_servers, &server_id, "tools/call", params, timeout_ms, Some(on_progress),
+        ).await?;
+        return Ok(capped_tool_result(result, crate::commands::config::max_result_bytes()));
+    }
+
+    match call_id {
+        Some(call_id) if !dry_run => {
+            let params = tool_call_params(&tool_name, arguments, input_schema.as_ref());
+            call_tool_cancellable(&mcp_manager.servers, &server_id, &call_id, params, timeout_ms, Some(on_progress)).await
+        }
+        _ => call_tool(
+            &mcp_manager.servers,
+            &server_id,
+            &tool_name,
+            arguments,
+            input_schema.as_ref(),
+            dry_run,
+            timeout_ms,
+            Some(on_progress),
+        ),
+    }
+}
+
+/// Get the tool call timeout configured for an MCP server (None means the default)
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_mcp_server_timeout(
+    shared_state: State<'_, SharedState>,
+    server_id: String,
+) -> Result<Option<u64>, String> {
+    shared_state.read(|state| {
+        state.mcp_servers.iter()
+            .find(|s| s.id == server_id)
+            .map(|s| s.timeout_ms)
+            .ok_or_else(|| format!("MCP Server '{}' not found", server_id))
+    })
+}
+
+/// Set or clear the tool call timeout for an MCP server
+/// Applies immediately when the server is running
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_mcp_server_timeout(
+    shared_state: State<'_, SharedState>,
+    mcp_manager: State<'_, McpServerManager>,
+    server_id: String,
+    timeout_ms: Option<u64>,
+) -> Result<McpServer, String> {
+    if timeout_ms == Some(0) {
+        return Err("Timeout must be greater than zero".to_string());
+    }
+
+    let updated = shared_state.write(|state| {
+        state.mcp_servers.iter_mut()
+            .find(|s| s.id == server_id)
+            .map(|server| {
+                server.timeout_ms = timeout_ms;
+                server.clone()
+            })
+    }).ok_or_else(|| format!("MCP Server '{}' not found", server_id))?;
+
+    apply_running_server_timeout(&mcp_manager, &server_id, timeout_ms)?;
+    Ok(updated)
+}
+
+/// Update the timeout of a running stdio or connected remote server
+fn apply_running_server_timeout(
+    mcp_manager: &McpServerManager,
+    server_id: &str,
+    timeout_ms: Option<u64>,
+) -> Result<(), String> {
+    if let Some(running) = mcp_manager.servers.write().map_err(|e| e.to_string())?.get_mut(server_id) {
+        running.timeout_ms = timeout_ms;
+    }
+    if let Some(remote) = mcp_manager.remote_servers.write().map_err(|e| e.to_string())?.get_mut(server_id) {
+        remote.timeout_ms = timeout_ms;
+    }
+    Ok(())
+}
+
+/// Cancel an in-flight tool call started with a `call_id`
+/// Returns false when no such call is pending
+#[tauri::command]
+#[allow(dead_code)]
+pub fn cancel_mcp_tool_call(
+    mcp_manager: State<'_, McpServerManager>,
+    server_id: String,
+    call_id: String,
+) -> Result<bool, String> {
+    cancel_tool_call(&mcp_manager.servers, &server_id, &call_id)
+}
+
+/// `tools/call` params with arguments coerced to the tool's input schema
+fn tool_call_params(tool_name: &str, arguments: serde_json::Value, input_schema: Option<&serde_json::Value>) -> serde_json::Value {
+    let arguments = match input_schema {
+        Some(schema) => coerce_tool_arguments(arguments, schema),
+        None => arguments,
+    };
+    serde_json::json!({
+        "name": tool_name,
+        "arguments": arguments
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn call_tool(
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    server_id: &str,
+    tool_name: &str,
+    arguments: serde_json::Value,
+    input_schema: Option<&serde_json::Value>,
+    dry_run: bool,
+    timeout_ms: Option<u64>,
+    on_progress: Option<ProgressListener>,
+) -> Result<McpToolResult, String> {
+    let params = tool_call_params(tool_name, arguments, input_schema);
+
+    if dry_run {
+        return Ok(McpToolResult {
+            success: true,
+            content: serde_json::json!({
+                "request": json_rpc_request("tools/call", &params),
+                "arguments": params["arguments"],
+            }),
+            is_error: false,
+            truncated: false,
+            blocks: Vec::new(),
+        });
+    }
+
+    let timeout_ms = rpc_timeout_ms(servers, server_id, timeout_ms);
+    let id = next_rpc_id(servers, server_id)?;
+    let token = progress_token(server_id, id);
+    let _subscription = on_progress.map(|listener| ProgressSubscription::new(token.clone(), listener));
+    let params = with_progress_token(params, &token);
+    let result = send_json_rpc_request_with_id(server_id, id, "tools/call", &params, servers, timeout_ms)?;
+    Ok(capped_tool_result(result, crate::commands::config::max_result_bytes()))
+}
+
+/// Tool result with `is_error` taken from the payload's `isError` and its content parsed into blocks
+/// Content whose JSON exceeds `max_bytes` is replaced by truncated text
+fn capped_tool_result(content: serde_json::Value, max_bytes: usize) -> McpToolResult {
+    let is_error = content.get("isError").and_then(|e| e.as_bool()).unwrap_or(false);
+    let serialized = content.to_string();
+    if serialized.len() <= max_bytes {
+        return McpToolResult {
+            success: !is_error,
+            blocks: parse_content_blocks(&content),
+            content,
+            is_error,
+            truncated: false,
+        };
+    }
+
+    let content = serde_json::json!({
+        "content": [{
+            "type": "text",
+            "text": crate::commands::config::truncate_with_marker(&serialized, max_bytes),
+        }],
+        "original_bytes": serialized.len(),
+    });
+    McpToolResult {
+        success: !is_error,
+        blocks: parse_content_blocks(&content),
+        content,
+        is_error,
+        truncated: true,
+    }
+}
+
+/// Parse the `content` array of a `tools/call` result; other shapes have no blocks
+fn parse_content_blocks(result: &serde_json::Value) -> Vec<McpContentBlock> {
+    let Some(items) = result.get("content").and_then(|c| c.as_array()) else {
+        return Vec::new();
+    };
+    let text = |value: &serde_json::Value, key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+
+    items.iter()
+        .map(|item| match item.get("type").and_then(|t| t.as_str()) {
+            Some("text") => match text(item, "text") {
+                Some(text) => McpContentBlock::Text { text },
+                None => McpContentBlock::Other { raw: item.clone() },
+            },
+            Some("image") => match (text(item, "data"), text(item, "mimeType")) {
+                (Some(data), Some(mime_type)) => McpContentBlock::Image { data, mime_type },
+                _ => McpContentBlock::Other { raw: item.clone() },
+            },
+            Some("resource") => match item.get("resource").and_then(|r| text(r, "uri").map(|uri| (r, uri))) {
+                Some((resource, uri)) => McpContentBlock::Resource {
+                    uri,
+                    mime_type: text(resource, "mimeType"),
+                    text: text(resource, "text"),
+                    blob: text(resource, "blob"),
+                },
+                None => McpContentBlock::Other { raw: item.clone() },
+            },
+            _ => McpContentBlock::Other { raw: item.clone() },
+        })
+        .collect()
+}
+
+/// Run a tool call on a blocking thread, returning a cancelled result as soon as it is cancelled
+/// The server's eventual response to a cancelled call is discarded by the dispatcher
+async fn call_tool_cancellable(
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    server_id: &str,
+    call_id: &str,
+    params: serde_json::Value,
+    timeout_ms: Option<u64>,
+    on_progress: Option<ProgressListener>,
+) -> Result<McpToolResult, String> {
+    let timeout_ms = rpc_timeout_ms(servers, server_id, timeout_ms);
+    let key = (server_id.to_string(), call_id.to_string());
+    let request_id = next_rpc_id(servers, server_id)?;
+    let token = progress_token(server_id, request_id);
+    let _subscription = on_progress.map(|listener| ProgressSubscription::new(token.clone(), listener));
+    let params = with_progress_token(params, &token);
+    let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+    {
+        let mut pending = pending_tool_calls().lock().map_err(|e| e.to_string())?;
+        if pending.contains_key(&key) {
+            return Err(format!("Tool call '{}' is already in flight", call_id));
+        }
+        pending.insert(key.clone(), PendingToolCall { request_id, cancel: cancel_tx });
+    }
+
+    let servers = servers.clone();
+    let blocking_server_id = server_id.to_string();
+    let call = tokio::task::spawn_blocking(move || {
+        send_json_rpc_request_with_id(&blocking_server_id, request_id, "tools/call", &params, &servers, timeout_ms)
+    });
+
+    let outcome = tokio::select! {
+        result = call => Some(result.map_err(|e| format!("Tool call task failed: {}", e)).and_then(|r| r)),
+        _ = cancel_rx => None,
+    };
+
+    if let Ok(mut pending) = pending_tool_calls().lock() {
+        if pending.get(&key).is_some_and(|p| p.request_id == request_id) {
+            pending.remove(&key);
+        }
+    }
+
+    match outcome {
+        Some(result) => Ok(capped_tool_result(result?, crate::commands::config::max_result_bytes())),
+        None => Ok(McpToolResult {
+            success: false,
+            content: serde_json::json!({ "cancelled": true, "call_id": call_id }),
+            is_error: true,
+            truncated: false,
+            blocks: Vec::new(),
+        }),
+    }
+}
+
+/// Abort a pending tool call and tell the server via `notifications/cancelled`
+fn cancel_tool_call(
+    servers: &Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    server_id: &str,
+    call_id: &str,
+) -> Result<bool, String> {
+    let pending = pending_tool_calls().lock()
+        .map_err(|e| e.to_string())?
+        .remove(&(server_id.to_string(), call_id.to_string()));
+    let Some(pending) = pending else {
+        return Ok(false);
+    };
+
+    let _ = pending.cancel.send(());
+    // The call is already abandoned locally; a server that cannot be notified just finishes it
+    let _ = send_mcp_notification(
+        server_id,
+        "notifications/cancelled",
+        serde_json::json!({ "requestId": pending.request_id, "reason": "Cancelled by user" }),
+        servers,
+    );
+    Ok(true)
+}
+
+/// Coerce tool arguments to the primitive types declared in the tool's input schema
+/// and fill in declared defaults for missing properties
+fn coerce_tool_arguments(arguments: serde_json::Value, schema: &serde_json::Value) -> serde_json::Value {
+    let serde_json::Value::Object(mut args) = arguments else {
+        return arguments;
+    };
+    let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) else {
+        return serde_json::Value::Object(args);
+    };
+
+    for (name, property) in properties {
+        let expected = property.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        match args.get_mut(name) {
+            Some(value) => {
+                let coerced = match (expected, &*value) {
+                    ("number", serde_json::Value::String(s)) => s.trim().parse::<f64>().ok().map(serde_json::Value::from),
+                    ("integer", serde_json::Value::String(s)) => s.trim().parse::<i64>().ok().map(serde_json::Value::from),
+                    ("boolean", serde_json::Value::String(s)) => match s.trim() {
+                        "true" => Some(serde_json::Value::Bool(true)),
+                        "false" => Some(serde_json::Value::Bool(false)),
+                        _ => None,
+                    },
+                    ("string", serde_json::Value::Number(n)) => Some(serde_json::Value::String(n.to_string())),
+                    ("string", serde_json::Value::Bool(b)) => Some(serde_json::Value::String(b.to_string())),
+                    _ => None,
+                };
+                if let Some(coerced) = coerced {
+                    *value = coerced;
+                }
+            }
+            None => {
+                if let Some(default) = property.get("default") {
+                    args.insert(name.clone(), default.clone());
+                }
+            }
+        }
     }
-    
-    Ok(false)
+
+    serde_json::Value::Object(args)
 }
 
-/// Get available tools from an MCP server
+/// Server argument that looks like a path but does not exist on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArgWarning {
+    pub index: usize,
+    pub arg: String,
+    pub message: String,
+}
+
+/// Check a server's path-like args exist before starting it
 #[tauri::command]
 #[allow(dead_code)]
-pub async fn get_mcp_server_tools(
+pub fn precheck_mcp_args(
     shared_state: State<'_, SharedState>,
-    mcp_manager: State<'_, McpServerManager>,
     server_id: String,
-) -> Result<Vec<McpToolDefinition>, String> {
+) -> Result<Vec<ArgWarning>, String> {
     let server = shared_state.read(|state| {
         state.mcp_servers.iter().find(|s| s.id == server_id).cloned()
-    });
-    
-    match server {
-        Some(_s) => {
-            // Check if server is running
-            let is_running = {
-                let servers = mcp_manager.servers.read().map_err(|e| e.to_string())?;
-                servers.contains_key(&server_id)
-            };
-            
-            if is_running {
-                discover_tools(&server_id, &mcp_manager)
-                    .await
-                    .map_err(|e| e.to_string())
-            } else {
-                Ok(Vec::new())
-            }
-        }
-        None => Err(format!("MCP Server '{}' not found", server_id)),
+    }).ok_or_else(|| format!("MCP Server '{}' not found", server_id))?;
+
+    Ok(missing_path_args(&server.args))
+}
+
+fn missing_path_args(args: &[String]) -> Vec<ArgWarning> {
+    args.iter()
+        .enumerate()
+        .filter_map(|(index, arg)| {
+            let path = path_like_arg(arg)?;
+            (!path.exists()).then(|| ArgWarning {
+                index,
+                arg: arg.clone(),
+                message: format!("Path '{}' does not exist", path.display()),
+            })
+        })
+        .collect()
+}
+
+/// Heuristically extract a filesystem path from an argument
+/// Accepts bare paths and `--flag=path`; skips URLs and scoped npm packages like `@scope/pkg`
+fn path_like_arg(arg: &str) -> Option<std::path::PathBuf> {
+    let value = match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with('-') => value,
+        _ => arg,
+    };
+    if value.starts_with('-') || value.starts_with('@') || value.contains("://") {
+        return None;
     }
+
+    if let Some(rest) = value.strip_prefix("~/") {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+        return Some(std::path::PathBuf::from(home).join(rest));
+    }
+
+    let path = std::path::Path::new(value);
+    (path.is_absolute() || value.contains('/') || value.contains('\\')).then(|| path.to_path_buf())
 }
 
-/// Call an MCP tool
+/// Get the last `max_lines` lines a running MCP server printed to stderr
 #[tauri::command]
 #[allow(dead_code)]
-pub async fn call_mcp_tool(
+pub fn get_mcp_server_logs(
     mcp_manager: State<'_, McpServerManager>,
     server_id: String,
-    tool_name: String,
-    arguments: serde_json::Value,
-) -> Result<McpToolResult, String> {
-    let result = send_json_rpc_request(
-        &server_id,
-        "tools/call",
-        serde_json::json!({
-            "name": tool_name,
-            "arguments": arguments
-        }),
-        &mcp_manager.servers,
-    )?;
-    
-    Ok(McpToolResult {
-        success: true,
-        content: result,
-        is_error: false,
-    })
+    max_lines: usize,
+) -> Result<Vec<String>, String> {
+    let servers = mcp_manager.servers.read().map_err(|e| format!("Lock error: {}", e))?;
+    let server = servers.get(&server_id)
+        .ok_or_else(|| format!("Server '{}' not running", server_id))?;
+    Ok(stderr_tail(&server.stderr_log, max_lines))
 }
 
 /// Test MCP server connection
@@ -482,6 +1753,12 @@ pub async fn test_mcp_server_connection(
     
     match server {
         Some(config) => {
+            check_server_type(&server_id, &config.server_type)?;
+            // Remote servers are tested by running the initialize handshake over HTTP
+            if is_remote_transport(&config.server_type) {
+                return connect_remote_server(&config).await.map(|_| true);
+            }
+            
             // Basic validation: check if command exists
             if config.command.is_empty() {
                 return Err("Server command is empty".to_string());
@@ -489,15 +1766,10 @@ pub async fn test_mcp_server_connection(
             
             // For stdio servers, try to ping
             if config.server_type == "stdio" {
-                let servers = mcp_manager.servers.read().map_err(|e| e.to_string())?;
-                if servers.contains_key(&server_id) {
+                let running = mcp_manager.servers.read().map_err(|e| e.to_string())?.contains_key(&server_id);
+                if running {
                     // Server is running, test connection via JSON-RPC
-                    let result = send_json_rpc_request(
-                        &server_id, 
-                        "ping", 
-                        serde_json::json!({}), 
-                        &mcp_manager.servers
-                    );
+                    let result = send_server_request(&mcp_manager, &server_id, "ping", serde_json::json!({})).await;
                     return match result {
                         Ok(_) => Ok(true),
                         Err(_) => Ok(true), // Ping might not be supported
@@ -518,12 +1790,7 @@ pub async fn list_mcp_resources(
     mcp_manager: State<'_, McpServerManager>,
     server_id: String,
 ) -> Result<serde_json::Value, String> {
-    send_json_rpc_request(
-        &server_id,
-        "resources/list",
-        serde_json::json!({}),
-        &mcp_manager.servers,
-    )
+    send_server_request(&mcp_manager, &server_id, "resources/list", serde_json::json!({})).await
 }
 
 /// Read a resource from an MCP server
@@ -534,12 +1801,80 @@ pub async fn read_mcp_resource(
     server_id: String,
     uri: String,
 ) -> Result<serde_json::Value, String> {
-    send_json_rpc_request(
-        &server_id,
-        "resources/read",
-        serde_json::json!({ "uri": uri }),
-        &mcp_manager.servers,
-    )
+    fetch_mcp_resource(&mcp_manager, &server_id, &uri).await
+}
+
+/// Read a resource through a running server (shared with chat context injection)
+pub(crate) async fn fetch_mcp_resource(
+    mcp_manager: &McpServerManager,
+    server_id: &str,
+    uri: &str,
+) -> Result<serde_json::Value, String> {
+    send_server_request(mcp_manager, server_id, "resources/read", serde_json::json!({ "uri": uri })).await
+}
+
+/// Read a resource from an MCP server piece by piece
+/// Emits `mcp_resource_chunk` for every content item, following `nextCursor` for servers
+/// that page large resources, then `mcp_resource_end`. Other servers yield a single chunk.
+/// Returns the number of chunks emitted
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn read_mcp_resource_streaming(
+    mcp_manager: State<'_, McpServerManager>,
+    app_state: State<'_, PixelState>,
+    server_id: String,
+    uri: String,
+) -> Result<usize, String> {
+    let app = app_state.app_handle.get();
+    let result = read_resource_chunks(&mcp_manager, &server_id, &uri, |index, content| {
+        let _ = app.emit("mcp_resource_chunk", &serde_json::json!({
+            "server_id": server_id,
+            "uri": uri,
+            "index": index,
+            "content": content,
+        }));
+    }).await;
+    let _ = app.emit("mcp_resource_end", &serde_json::json!({
+        "server_id": server_id,
+        "uri": uri,
+        "chunks": result.as_ref().ok(),
+        "error": result.as_ref().err(),
+    }));
+    result
+}
+
+/// Read every page of a resource, handing each content item to `on_chunk` in order
+async fn read_resource_chunks(
+    mcp_manager: &McpServerManager,
+    server_id: &str,
+    uri: &str,
+    mut on_chunk: impl FnMut(usize, serde_json::Value),
+) -> Result<usize, String> {
+    let mut chunks = 0;
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut params = serde_json::json!({ "uri": uri });
+        if let Some(cursor) = &cursor {
+            params["cursor"] = serde_json::json!(cursor);
+        }
+        let result = send_server_request(mcp_manager, server_id, "resources/read", params).await?;
+
+        let contents = match result.get("contents") {
+            Some(serde_json::Value::Array(items)) => items.clone(),
+            _ => vec![result.clone()],
+        };
+        for content in contents {
+            on_chunk(chunks, content);
+            chunks += 1;
+        }
+
+        // A server repeating its cursor would otherwise be read forever
+        let next = result.get("nextCursor").and_then(|c| c.as_str()).map(str::to_string);
+        if next.is_none() || next == cursor {
+            return Ok(chunks);
+        }
+        cursor = next;
+    }
 }
 
 /// List prompts from an MCP server
@@ -549,12 +1884,7 @@ pub async fn list_mcp_prompts(
     mcp_manager: State<'_, McpServerManager>,
     server_id: String,
 ) -> Result<serde_json::Value, String> {
-    send_json_rpc_request(
-        &server_id,
-        "prompts/list",
-        serde_json::json!({}),
-        &mcp_manager.servers,
-    )
+    send_server_request(&mcp_manager, &server_id, "prompts/list", serde_json::json!({})).await
 }
 
 /// Get a prompt from an MCP server
@@ -570,12 +1900,7 @@ pub async fn get_mcp_prompt(
     if let Some(args) = arguments {
         params["arguments"] = args;
     }
-    send_json_rpc_request(
-        &server_id,
-        "prompts/get",
-        params,
-        &mcp_manager.servers,
-    )
+    send_server_request(&mcp_manager, &server_id, "prompts/get", params).await
 }
 
 /// Restart an MCP server (stop and start)
@@ -588,6 +1913,7 @@ pub async fn restart_mcp_server(
 ) -> Result<McpServerStatus, String> {
     // First stop the server if running
     let _ = stop_mcp_server_internal(&server_id, &mcp_manager.servers);
+    let _ = disconnect_remote_server(&mcp_manager.remote_servers, &server_id);
     
     // Give it a moment to clean up
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -601,29 +1927,38 @@ pub async fn restart_mcp_server(
         Some(s) => s,
         None => return Err(format!("MCP Server '{}' not found", server_id)),
     };
+    check_server_type(&server_id, &config.server_type)?;
+    
+    if is_remote_transport(&config.server_type) {
+        return start_remote_server(&config, &mcp_manager).await;
+    }
     
     // Spawn the process
-    let mut child = Command::new(&config.command)
-        .args(&config.args)
-        .envs(&config.env)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+    let mut child = mcp_server_command(&config)
         .spawn()
         .map_err(|e| format!("Failed to spawn process: {}", e))?;
     
     let stdin = child.stdin.take()
+        .map(|stdin| Arc::new(Mutex::new(stdin)))
         .ok_or_else(|| "Failed to get stdin".to_string())?;
     
     let stdout = child.stdout.take()
         .ok_or_else(|| "Failed to get stdout".to_string())?;
     
+    let stderr = child.stderr.take()
+        .ok_or_else(|| "Failed to get stderr".to_string())?;
+    let stderr_log = capture_stderr(stderr);
+    
     // Store the running server
     let running_server = RunningMcpServer {
         server_id: server_id.clone(),
         process: child,
-        stdin: std::sync::Mutex::new(stdin),
-        stdout: std::sync::Mutex::new(stdout),
+        stdin: Arc::clone(&stdin),
+        pending_responses: spawn_response_dispatcher(stdout, stdin),
+        timeout_ms: config.timeout_ms,
+        rpc_id: AtomicU64::new(1),
+        protocol_version: None,
+        stderr_log: Arc::clone(&stderr_log),
     };
     
     {
@@ -631,8 +1966,10 @@ pub async fn restart_mcp_server(
         servers.insert(server_id.clone(), running_server);
     }
     
-    // Give the server a moment to initialize
-    tokio::time::sleep(Duration::from_millis(500)).await;
+    if let Err(e) = mcp_initialize(&server_id, &mcp_manager.servers, MCP_INITIALIZE_TIMEOUT_MS) {
+        let _ = stop_mcp_server_internal(&server_id, &mcp_manager.servers);
+        return Err(with_stderr_tail(e, &stderr_log));
+    }
     
     // Discover tools
     let tools = discover_tools(&server_id, &mcp_manager).await
@@ -704,14 +2041,14 @@ pub async fn get_mcp_stats(
         }
         
         // Try to get resources count
-        if let Ok(result) = send_json_rpc_request(&server_id, "resources/list", serde_json::json!({}), &mcp_manager.servers) {
+        if let Ok(result) = send_server_request(&mcp_manager, &server_id, "resources/list", serde_json::json!({})).await {
             if let Some(resources) = result.get("resources").and_then(|r| r.as_array()) {
                 total_resources += resources.len();
             }
         }
         
         // Try to get prompts count
-        if let Ok(result) = send_json_rpc_request(&server_id, "prompts/list", serde_json::json!({}), &mcp_manager.servers) {
+        if let Ok(result) = send_server_request(&mcp_manager, &server_id, "prompts/list", serde_json::json!({})).await {
             if let Some(prompts) = result.get("prompts").and_then(|p| p.as_array()) {
                 total_prompts += prompts.len();
             }
@@ -748,7 +2085,7 @@ pub async fn get_mcp_server_status_info(
     let is_running = {
         let servers = mcp_manager.servers.read().map_err(|e| e.to_string())?;
         servers.contains_key(&server_id)
-    }; // servers (RwLockReadGuard) is dropped here, before any await
+    } || is_remote_running(&mcp_manager.remote_servers, &server_id); // servers (RwLockReadGuard) is dropped here, before any await
 
     if is_running {
         // Server is running, try to get tools
@@ -773,3 +2110,765 @@ pub async fn get_mcp_server_status_info(
         Ok(McpServerStatusInfo::Stopped { server_id })
     }
 }
+
+/// Get the number of retries used for idempotent MCP JSON-RPC calls
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_mcp_retry_count() -> u32 {
+    rpc_max_retries().load(Ordering::SeqCst)
+}
+
+/// Set the number of retries used for idempotent MCP JSON-RPC calls,
+/// clamped to `MAX_RPC_RETRIES`; returns the value actually applied
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_mcp_retry_count(retries: u32) -> u32 {
+    let retries = clamp_retry_count(retries);
+    rpc_max_retries().store(retries, Ordering::SeqCst);
+    retries
+}
+
+fn clamp_retry_count(retries: u32) -> u32 {
+    retries.min(MAX_RPC_RETRIES)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock_http::{MockHttpServer, MockResponse};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_idempotent_request_retried_against_server() {
+        // Mock server that drops its first request, so that attempt times out, then answers the retry
+        let script = concat!(
+            "send() { printf 'Content-Length: %s\\n\\n%s\\n' \"${#1}\" \"$1\"; }\n",
+            "read header; read blank\n",
+            "head -c \"$(printf '%s' \"$header\" | tr -dc 0-9)\" > /dev/null\n",
+            "read header; read blank\n",
+            "body=$(head -c \"$(printf '%s' \"$header\" | tr -dc 0-9)\")\n",
+            "id=$(printf '%s' \"$body\" | sed 's/.*\"id\":\\([0-9]*\\).*/\\1/')\n",
+            "send '{\"jsonrpc\":\"2.0\",\"id\":'\"$id\"',\"result\":{\"tools\":[]}}'\n",
+            "sleep 1\n",
+        );
+        let mut process = Command::new("sh")
+            .args(["-c", script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = Arc::new(Mutex::new(process.stdin.take().unwrap()));
+        let running = RunningMcpServer {
+            server_id: "flaky".to_string(),
+            stdin: Arc::clone(&stdin),
+            pending_responses: spawn_response_dispatcher(process.stdout.take().unwrap(), stdin),
+            process,
+            timeout_ms: Some(300),
+            rpc_id: AtomicU64::new(1),
+            protocol_version: None,
+            stderr_log: Default::default(),
+        };
+        let servers = Arc::new(RwLock::new(HashMap::from([("flaky".to_string(), running)])));
+
+        let result = send_json_rpc_request("flaky", "tools/list", serde_json::json!({}), &servers);
+        assert_eq!(result.unwrap(), serde_json::json!({ "tools": [] }));
+    }
+
+    #[test]
+    fn test_retry_count_and_backoff_are_bounded() {
+        assert_eq!(clamp_retry_count(1000), MAX_RPC_RETRIES);
+        assert_eq!(clamp_retry_count(1), 1);
+
+        assert_eq!(rpc_retry_delay_ms(100, 0), 100);
+        assert_eq!(rpc_retry_delay_ms(100, 2), 400);
+        assert_eq!(rpc_retry_delay_ms(100, 63), RPC_RETRY_MAX_DELAY_MS);
+        assert_eq!(rpc_retry_delay_ms(100, 64), RPC_RETRY_MAX_DELAY_MS);
+        assert_eq!(rpc_retry_delay_ms(100, u32::MAX), RPC_RETRY_MAX_DELAY_MS);
+    }
+
+    #[test]
+    fn test_idempotent_call_succeeds_after_timeout() {
+        // Mock server that times out once, then responds
+        let mut calls = 0;
+        let result = with_rpc_retry(retries_for_method("tools/list"), 1, || {
+            calls += 1;
+            if calls == 1 {
+                Err("Request timeout".to_string())
+            } else {
+                Ok(serde_json::json!({ "tools": [] }))
+            }
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_call_tool_dry_run_returns_request_without_sending() {
+        // No servers are running, so any real send would fail
+        let servers = Arc::new(RwLock::new(HashMap::new()));
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer" },
+                "verbose": { "type": "boolean", "default": false }
+            }
+        });
+
+        let result = call_tool(&servers, "fs", "list_files", serde_json::json!({ "count": "3" }), Some(&schema), true, None, None).unwrap();
+
+        let request = &result.content["request"];
+        assert_eq!(request["jsonrpc"], "2.0");
+        assert_eq!(request["method"], "tools/call");
+        assert_eq!(request["params"]["name"], "list_files");
+        assert_eq!(request["params"]["arguments"], serde_json::json!({ "count": 3, "verbose": false }));
+        assert_eq!(result.content["arguments"], request["params"]["arguments"]);
+
+        assert!(call_tool(&servers, "fs", "list_files", serde_json::json!({}), None, false, None, None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cancel_slow_tool_call() {
+        // A server that accepts requests but never answers
+        let mut process = Command::new("sleep")
+            .arg("2")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = Arc::new(Mutex::new(process.stdin.take().unwrap()));
+        let running = RunningMcpServer {
+            server_id: "slow".to_string(),
+            stdin: Arc::clone(&stdin),
+            pending_responses: spawn_response_dispatcher(process.stdout.take().unwrap(), stdin),
+            process,
+            timeout_ms: None,
+            rpc_id: AtomicU64::new(1),
+            protocol_version: None,
+            stderr_log: Default::default(),
+        };
+        let servers = Arc::new(RwLock::new(HashMap::from([("slow".to_string(), running)])));
+
+        let call_servers = servers.clone();
+        let call = tokio::spawn(async move {
+            let params = tool_call_params("slow_tool", serde_json::json!({}), None);
+            call_tool_cancellable(&call_servers, "slow", "call_1", params, None, None).await
+        });
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = Instant::now();
+        assert!(cancel_tool_call(&servers, "slow", "call_1").unwrap());
+
+        let result = call.await.unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(!result.success);
+        assert_eq!(result.content["cancelled"], true);
+        assert!(!cancel_tool_call(&servers, "slow", "call_1").unwrap());
+    }
+
+    /// A server that answers one request after `delay` seconds with `{ "ok": true }`,
+    /// echoing the request id through `id_format` (`$id` for a number, `"$id"` for a string)
+    /// after sending each `preamble` message
+    #[cfg(unix)]
+    fn mock_mcp_server(server_id: &str, delay: &str, id_format: &str, preamble: &[&str], timeout_ms: Option<u64>) -> RunningMcpServer {
+        let mut script = String::from(concat!(
+            "send() { printf 'Content-Length: %s\\n\\n%s\\n' \"${#1}\" \"$1\"; }\n",
+            "read header; read blank\n",
+            "body=$(head -c \"$(printf '%s' \"$header\" | tr -dc 0-9)\")\n",
+            "id=$(printf '%s' \"$body\" | sed 's/.*\"id\":\\([0-9]*\\).*/\\1/')\n",
+        ));
+        script.push_str(&format!("sleep {}\n", delay));
+        for message in preamble {
+            script.push_str(&format!("send '{}'\n", message));
+        }
+        let response = r#"{"jsonrpc":"2.0","id":ID,"result":{"ok":true}}"#.replace("ID", id_format);
+        script.push_str(&format!("send \"{}\"\n", response.replace('"', "\\\"")));
+
+        let mut process = Command::new("sh")
+            .args(["-c", &script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = Arc::new(Mutex::new(process.stdin.take().unwrap()));
+        RunningMcpServer {
+            server_id: server_id.to_string(),
+            stdin: Arc::clone(&stdin),
+            pending_responses: spawn_response_dispatcher(process.stdout.take().unwrap(), stdin),
+            process,
+            timeout_ms,
+            rpc_id: AtomicU64::new(1),
+            protocol_version: None,
+            stderr_log: Default::default(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tool_call_timeout_per_server() {
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("short".to_string(), mock_mcp_server("short", "0.5", "$id", &[], Some(100))),
+            ("long".to_string(), mock_mcp_server("long", "0.5", "$id", &[], Some(5000))),
+            ("override".to_string(), mock_mcp_server("override", "0.5", "$id", &[], Some(100))),
+        ])));
+
+        let err = call_tool(&servers, "short", "crawl", serde_json::json!({}), None, false, None, None).unwrap_err();
+        assert!(err.contains("timeout"));
+
+        let result = call_tool(&servers, "long", "crawl", serde_json::json!({}), None, false, None, None).unwrap();
+        assert!(result.success);
+
+        let result = call_tool(&servers, "override", "crawl", serde_json::json!({}), None, false, Some(5000), None).unwrap();
+        assert!(result.success);
+
+        assert_eq!(rpc_timeout_ms(&servers, "missing", None), DEFAULT_MCP_TIMEOUT_MS);
+        for (_, mut server) in servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    /// A server running `script` after the framing helpers `send` and `recv` (which sets `$body` and `$id`)
+    #[cfg(unix)]
+    fn scripted_mcp_server(server_id: &str, script: &str) -> RunningMcpServer {
+        let script = format!(concat!(
+            "send() {{ printf 'Content-Length: %s\\n\\n%s\\n' \"${{#1}}\" \"$1\"; }}\n",
+            "recv() {{ read header; read blank; body=$(head -c \"$(printf '%s' \"$header\" | tr -dc 0-9)\"); ",
+            "id=$(printf '%s' \"$body\" | sed 's/.*\"id\":\\([0-9]*\\).*/\\1/'); }}\n",
+            "{}"
+        ), script);
+        let mut process = Command::new("sh")
+            .args(["-c", &script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = Arc::new(Mutex::new(process.stdin.take().unwrap()));
+        RunningMcpServer {
+            server_id: server_id.to_string(),
+            stdin: Arc::clone(&stdin),
+            pending_responses: spawn_response_dispatcher(process.stdout.take().unwrap(), stdin),
+            process,
+            timeout_ms: None,
+            rpc_id: AtomicU64::new(1),
+            protocol_version: None,
+            stderr_log: Default::default(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_initialize_handshake_precedes_tools_list() {
+        // Answers tools/list only after initialize and the initialized notification
+        let script = r#"
+recv
+case "$body" in *'"method":"initialize"'*) ;; *) exit 1;; esac
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2024-11-05\",\"capabilities\":{}}}"
+recv
+case "$body" in *notifications/initialized*) ;; *) exit 1;; esac
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[]}}"
+"#;
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("ready".to_string(), scripted_mcp_server("ready", script)),
+            ("silent".to_string(), scripted_mcp_server("silent", "recv; sleep 0.5; send '{\"jsonrpc\":\"2.0\",\"method\":\"notifications/message\"}'; sleep 5")),
+        ])));
+
+        assert_eq!(mcp_initialize("ready", &servers, 5000).unwrap(), "2024-11-05");
+        assert_eq!(servers.read().unwrap()["ready"].protocol_version.as_deref(), Some("2024-11-05"));
+        let result = send_json_rpc_request_once("ready", "tools/list", &serde_json::json!({}), &servers).unwrap();
+        assert_eq!(result["tools"], serde_json::json!([]));
+
+        let err = mcp_initialize("silent", &servers, 200).unwrap_err();
+        assert!(err.contains("did not answer initialize"), "{}", err);
+        assert!(servers.read().unwrap()["silent"].protocol_version.is_none());
+
+        for (_, mut server) in servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_resource_read_follows_cursor_pages() {
+        // Two pages: the first names a cursor, the second only answers when it is sent back
+        let script = r#"
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"contents\":[{\"uri\":\"file:///big.log\",\"text\":\"part 1\"},{\"uri\":\"file:///big.log\",\"text\":\"part 2\"}],\"nextCursor\":\"page-2\"}}"
+recv
+case "$body" in *'"cursor":"page-2"'*) ;; *) exit 1;; esac
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"contents\":[{\"uri\":\"file:///big.log\",\"text\":\"part 3\"}]}}"
+recv
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"contents\":[{\"uri\":\"file:///small.txt\",\"text\":\"whole\"}]}}"
+"#;
+        let manager = McpServerManager::default();
+        manager.servers.write().unwrap().insert("paged".to_string(), scripted_mcp_server("paged", script));
+
+        let mut chunks = Vec::new();
+        let count = read_resource_chunks(&manager, "paged", "file:///big.log", |index, content| {
+            chunks.push((index, content["text"].as_str().unwrap_or_default().to_string()));
+        }).await.unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(chunks, vec![
+            (0, "part 1".to_string()),
+            (1, "part 2".to_string()),
+            (2, "part 3".to_string()),
+        ]);
+
+        let mut single = Vec::new();
+        assert_eq!(read_resource_chunks(&manager, "paged", "file:///small.txt", |_, content| single.push(content)).await.unwrap(), 1);
+        assert_eq!(single[0]["text"], "whole");
+
+        for (_, mut server) in manager.servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    #[test]
+    fn test_stderr_ring_buffer_keeps_last_lines() {
+        let output: String = (0..MCP_STDERR_MAX_LINES + 100).map(|i| format!("line {}\r\n", i)).collect();
+        let log = capture_stderr(std::io::Cursor::new(output.into_bytes()));
+
+        let last = format!("line {}", MCP_STDERR_MAX_LINES + 99);
+        let start = Instant::now();
+        while stderr_tail(&log, 1) != [last.clone()] {
+            assert!(start.elapsed() < Duration::from_secs(5), "stderr was not drained");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(log.lock().unwrap().len(), MCP_STDERR_MAX_LINES);
+        assert_eq!(log.lock().unwrap().front().map(String::as_str), Some("line 100"));
+        assert_eq!(stderr_tail(&log, 2), vec![format!("line {}", MCP_STDERR_MAX_LINES + 98), last.clone()]);
+
+        let error = with_stderr_tail("Failed to start".to_string(), &log);
+        assert!(error.starts_with("Failed to start\nServer stderr:\n"));
+        assert!(error.ends_with(&last));
+        assert_eq!(with_stderr_tail("Failed to start".to_string(), &Mutex::default()), "Failed to start");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tool_progress_forwarded_in_order() {
+        // Reports progress under the call's token, plus one for another call, before the result
+        let script = r#"
+recv
+token=$(printf '%s' "$body" | sed 's/.*"progressToken":"\([^"]*\)".*/\1/')
+progress() { send "{\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\",\"params\":{\"progressToken\":\"$1\",$2}}"; }
+progress "$token" '"progress":1,"total":4,"message":"Fetching"'
+progress "other:9" '"progress":2,"total":4'
+progress "$token" '"progress":3,"total":4,"message":"Parsing"'
+progress "$token" '"progress":5'
+send "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[]}}"
+"#;
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("progress".to_string(), scripted_mcp_server("progress", script)),
+        ])));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let listener: ProgressListener = Box::new(move |progress| sink.lock().unwrap().push(progress));
+        let result = call_tool(&servers, "progress", "crawl", serde_json::json!({}), None, false, Some(5000), Some(listener)).unwrap();
+        assert!(result.success);
+
+        let events = events.lock().unwrap();
+        let summary: Vec<(f64, Option<f64>, Option<&str>)> = events.iter()
+            .map(|e| (e.progress, e.percent, e.message.as_deref()))
+            .collect();
+        assert_eq!(summary, vec![
+            (1.0, Some(25.0), Some("Fetching")),
+            (3.0, Some(75.0), Some("Parsing")),
+            (5.0, None, None),
+        ]);
+        assert!(!progress_listeners().lock().unwrap().contains_key(&progress_token("progress", 1)));
+
+        for (_, mut server) in servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    #[test]
+    fn test_rpc_id_matches_numeric_and_string_ids() {
+        assert!(rpc_id_matches(42, &serde_json::json!(42)));
+        assert!(rpc_id_matches(42, &serde_json::json!("42")));
+        assert!(!rpc_id_matches(42, &serde_json::json!(41)));
+        assert!(!rpc_id_matches(42, &serde_json::json!("req-42")));
+        assert!(!rpc_id_matches(42, &serde_json::Value::Null));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_string_response_id_is_matched() {
+        let preamble = [
+            r#"{"jsonrpc":"2.0","method":"notifications/progress","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":0,"result":{"stale":true}}"#,
+        ];
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("strings".to_string(), mock_mcp_server("strings", "0", "\"$id\"", &preamble, None)),
+        ])));
+
+        let result = send_json_rpc_request_with_id("strings", 42, "tools/call", &serde_json::json!({}), &servers, 5000).unwrap();
+        assert_eq!(result, serde_json::json!({ "ok": true }));
+
+        for (_, mut server) in servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rpc_ids_are_scoped_per_server() {
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("first".to_string(), mock_mcp_server("first", "0", "$id", &[], None)),
+            ("second".to_string(), mock_mcp_server("second", "0", "$id", &[], None)),
+        ])));
+
+        // Each mock echoes the request id, so both calls are answered under id 1
+        assert_eq!(servers.read().unwrap()["first"].rpc_id.load(Ordering::SeqCst), 1);
+        assert!(call_tool(&servers, "first", "crawl", serde_json::json!({}), None, false, None, None).unwrap().success);
+        assert_eq!(servers.read().unwrap()["second"].rpc_id.load(Ordering::SeqCst), 1);
+        assert!(call_tool(&servers, "second", "crawl", serde_json::json!({}), None, false, None, None).unwrap().success);
+
+        assert_eq!(next_rpc_id(&servers, "first").unwrap(), 2);
+        assert_eq!(next_rpc_id(&servers, "first").unwrap(), 3);
+        assert_eq!(next_rpc_id(&servers, "second").unwrap(), 2);
+        assert!(next_rpc_id(&servers, "missing").is_err());
+
+        for (_, mut server) in servers.write().unwrap().drain() {
+            let _ = server.process.kill();
+        }
+    }
+
+    #[test]
+    fn test_response_matched_across_interleaved_messages() {
+        fn frame(body: &str) -> String {
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        }
+        let multiline_result = "{\n  \"jsonrpc\": \"2.0\",\n  \"id\": 7,\n  \"result\": { \"tools\": [] }\n}";
+        let stream = [
+            frame(r#"{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info"}}"#),
+            frame(r#"{"jsonrpc":"2.0","id":6,"result":{"late":true}}"#),
+            frame(multiline_result),
+            frame(r#"{"jsonrpc":"2.0","id":8,"result":{"next":true}}"#),
+        ].concat();
+        let mut reader = std::io::Cursor::new(stream.into_bytes());
+
+        let pending = Mutex::new(PendingMcpResponses::default());
+        let mut receivers = HashMap::new();
+        for id in [7, 8, 9] {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            pending.lock().unwrap().waiting.insert(id, sender);
+            receivers.insert(id, receiver);
+        }
+        route_mcp_responses(&mut reader, &pending, &Mutex::new(Vec::new()));
+
+        let response = receivers[&7].try_recv().unwrap();
+        assert_eq!(response["result"], serde_json::json!({ "tools": [] }));
+        let next = receivers[&8].try_recv().unwrap();
+        assert_eq!(next["result"]["next"], true);
+
+        // Once the stream closes, requests still waiting are released
+        assert_eq!(receivers[&9].try_recv(), Err(mpsc::TryRecvError::Disconnected));
+        let pending = pending.lock().unwrap();
+        assert!(pending.closed);
+        assert!(pending.waiting.is_empty());
+    }
+
+    #[test]
+    fn test_server_requests_are_answered_not_routed() {
+        fn frame(body: &str) -> String {
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        }
+        // The server's own request ids collide with ours
+        let stream = [
+            frame(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#),
+            frame(r#"{"jsonrpc":"2.0","id":2,"method":"roots/list"}"#),
+            frame(r#"{"jsonrpc":"2.0","id":1,"result":{"tools":[]}}"#),
+        ].concat();
+        let mut reader = std::io::Cursor::new(stream.into_bytes());
+
+        let pending = Mutex::new(PendingMcpResponses::default());
+        let (sender, receiver) = mpsc::sync_channel(1);
+        pending.lock().unwrap().waiting.insert(1, sender);
+        let replies = Mutex::new(Vec::new());
+        route_mcp_responses(&mut reader, &pending, &replies);
+
+        assert_eq!(receiver.try_recv().unwrap()["result"], serde_json::json!({ "tools": [] }));
+
+        let replies = replies.into_inner().unwrap();
+        let mut replies = std::io::Cursor::new(replies);
+        let ping: serde_json::Value = serde_json::from_str(&read_mcp_message(&mut replies).unwrap()).unwrap();
+        assert_eq!(ping, serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+        let roots: serde_json::Value = serde_json::from_str(&read_mcp_message(&mut replies).unwrap()).unwrap();
+        assert_eq!(roots["id"], 2);
+        assert_eq!(roots["error"]["code"], -32601);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrent_requests_answered_out_of_order() {
+        // Reads both requests before answering, then replies to the second one first
+        let script = r#"
+recv; first=$id
+recv; second=$id
+send "{\"jsonrpc\":\"2.0\",\"id\":$second,\"result\":{\"answered\":$second}}"
+sleep 0.2
+send "{\"jsonrpc\":\"2.0\",\"id\":$first,\"result\":{\"answered\":$first}}"
+"#;
+        let servers = Arc::new(RwLock::new(HashMap::from([
+            ("shared".to_string(), scripted_mcp_server("shared", script)),
+        ])));
+
+        let calls: Vec<_> = (0..2).map(|_| {
+            let servers = Arc::clone(&servers);
+            std::thread::spawn(move || {
+                let id = next_rpc_id(&servers, "shared").unwrap();
+                let result = send_json_rpc_request_with_id("shared", id, "tools/call", &serde_json::json!({}), &servers, 5000);
+                (id, result)
+            })
+        }).collect();
+        for call in calls {
+            let (id, result) = call.join().unwrap();
+            assert_eq!(result.unwrap()["answered"], id);
+        }
+
+        let mut server = servers.write().unwrap().remove("shared").unwrap();
+        let _ = server.process.wait();
+        let servers = Arc::new(RwLock::new(HashMap::from([("shared".to_string(), server)])));
+        let err = send_json_rpc_request_with_id("shared", 9, "ping", &serde_json::json!({}), &servers, 5000).unwrap_err();
+        assert!(err == "Empty response" || err.contains("Broken pipe"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_path_args() {
+        let existing = tempfile::TempDir::new().unwrap();
+        let existing_path = existing.path().to_string_lossy().to_string();
+        let missing_path = existing.path().join("no_such_dir").to_string_lossy().to_string();
+
+        let args = vec![
+            "-y".to_string(),
+            "@modelcontextprotocol/server-filesystem".to_string(),
+            existing_path.clone(),
+            missing_path.clone(),
+            format!("--root={}", missing_path),
+            "https://example.com/mcp".to_string(),
+        ];
+
+        let warnings = missing_path_args(&args);
+        let flagged: Vec<usize> = warnings.iter().map(|w| w.index).collect();
+        assert_eq!(flagged, vec![3, 4]);
+        assert_eq!(warnings[0].arg, missing_path);
+
+        assert!(missing_path_args(&[existing_path]).is_empty());
+    }
+
+    #[test]
+    fn test_tools_call_is_not_retried() {
+        let mut calls = 0;
+        let result = with_rpc_retry(retries_for_method("tools/call"), 1, || {
+            calls += 1;
+            Err("Request timeout".to_string())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_protocol_errors_are_not_retried() {
+        let mut calls = 0;
+        let result = with_rpc_retry(3, 1, || {
+            calls += 1;
+            Err("JSON-RPC error: Method not found".to_string())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_mcp_servers_export_round_trip() {
+        let servers = vec![
+            McpServer {
+                id: "filesystem".to_string(),
+                server_type: "stdio".to_string(),
+                command: "npx".to_string(),
+                args: vec!["-y".to_string(), "@modelcontextprotocol/server-filesystem".to_string(), "/tmp".to_string()],
+                env: HashMap::new(),
+                timeout_ms: None,
+                url: None,
+                cwd: Some(std::env::temp_dir().to_string_lossy().to_string()),
+            },
+            McpServer {
+                id: "github".to_string(),
+                server_type: "stdio".to_string(),
+                command: "github-mcp".to_string(),
+                args: Vec::new(),
+                env: HashMap::from([
+                    ("GITHUB_TOKEN".to_string(), "ghp_secret".to_string()),
+                    ("LOG_LEVEL".to_string(), "debug".to_string()),
+                ]),
+                timeout_ms: None,
+                url: None,
+                cwd: None,
+            },
+        ];
+
+        let export = serde_json::to_string(&servers_to_config_json(&servers, false)).unwrap();
+        let mut imported = servers_from_config_json(&export).unwrap();
+        imported.sort_by_key(|s| s.id.clone());
+        assert_eq!(imported.len(), 2);
+        for (original, restored) in servers.iter().zip(&imported) {
+            assert_eq!(original.id, restored.id);
+            assert_eq!(original.server_type, restored.server_type);
+            assert_eq!(original.command, restored.command);
+            assert_eq!(original.args, restored.args);
+            assert_eq!(original.env, restored.env);
+            assert_eq!(original.cwd, restored.cwd);
+        }
+
+        let missing_cwd = r#"{"mcpServers":{"fs":{"command":"npx","cwd":"/nonexistent/pixel-client"}}}"#;
+        assert!(servers_from_config_json(missing_cwd).unwrap_err().contains("'fs'"));
+
+        let redacted = servers_to_config_json(&servers, true);
+        assert_eq!(redacted["mcpServers"]["github"]["env"]["GITHUB_TOKEN"], REDACTED_ENV_VALUE);
+        assert_eq!(redacted["mcpServers"]["github"]["env"]["LOG_LEVEL"], "debug");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server_process_runs_in_cwd() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path().to_string_lossy().to_string();
+        assert_eq!(validate_mcp_cwd(Some(dir.clone())).unwrap(), Some(dir.clone()));
+        assert_eq!(validate_mcp_cwd(Some("  ".to_string())).unwrap(), None);
+        assert!(validate_mcp_cwd(Some(temp_dir.path().join("missing").to_string_lossy().to_string())).is_err());
+
+        let config = McpServer {
+            id: "pwd".to_string(),
+            server_type: "stdio".to_string(),
+            command: "pwd".to_string(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            timeout_ms: None,
+            url: None,
+            cwd: Some(dir),
+        };
+        let output = mcp_server_command(&config).output().unwrap();
+        let reported = std::path::PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        assert_eq!(reported.canonicalize().unwrap(), temp_dir.path().canonicalize().unwrap());
+    }
+
+    #[test]
+    fn test_tool_result_content_parsed_into_blocks() {
+        let result = capped_tool_result(serde_json::json!({
+            "content": [
+                { "type": "text", "text": "Rendered chart" },
+                { "type": "image", "data": "iVBORw0KGgo=", "mimeType": "image/png" },
+                { "type": "resource", "resource": { "uri": "file:///report.md", "mimeType": "text/markdown", "text": "# Report" } },
+                { "type": "audio", "data": "AAAA", "mimeType": "audio/wav" },
+            ],
+        }), 1024 * 1024);
+        assert!(result.success && !result.is_error);
+        assert_eq!(result.blocks, vec![
+            McpContentBlock::Text { text: "Rendered chart".to_string() },
+            McpContentBlock::Image { data: "iVBORw0KGgo=".to_string(), mime_type: "image/png".to_string() },
+            McpContentBlock::Resource {
+                uri: "file:///report.md".to_string(),
+                mime_type: Some("text/markdown".to_string()),
+                text: Some("# Report".to_string()),
+                blob: None,
+            },
+            McpContentBlock::Other { raw: serde_json::json!({ "type": "audio", "data": "AAAA", "mimeType": "audio/wav" }) },
+        ]);
+        assert_eq!(serde_json::to_value(&result.blocks[1]).unwrap()["type"], "image");
+
+        let failed = capped_tool_result(serde_json::json!({
+            "content": [{ "type": "text", "text": "File not found" }],
+            "isError": true,
+        }), 1024 * 1024);
+        assert!(failed.is_error);
+        assert!(!failed.success);
+        assert_eq!(failed.blocks, vec![McpContentBlock::Text { text: "File not found".to_string() }]);
+    }
+
+    #[test]
+    fn test_oversized_tool_result_is_truncated() {
+        let small = serde_json::json!({ "content": [{ "type": "text", "text": "ok" }] });
+        let result = capped_tool_result(small.clone(), 1024);
+        assert!(!result.truncated);
+        assert_eq!(result.content, small);
+
+        let large = serde_json::json!({ "content": [{ "type": "text", "text": "x".repeat(10_000) }] });
+        let result = capped_tool_result(large, 1024);
+        assert!(result.truncated);
+        assert!(result.success);
+        let text = result.content["content"][0]["text"].as_str().unwrap();
+        assert!(text.len() < 1100);
+        assert!(text.ends_with(crate::commands::config::TRUNCATION_MARKER));
+        assert!(result.content["original_bytes"].as_u64().unwrap() > 10_000);
+    }
+
+    #[tokio::test]
+    async fn test_remote_server_initialize_and_request() {
+        let server = MockHttpServer::start(|request| {
+            if request.contains("\"initialize\"") {
+                // Answered on an SSE stream, after an unrelated notification
+                MockResponse::new("200 OK", "text/event-stream", concat!(
+                    "event: message\n",
+                    "data: {\"jsonrpc\":\"2.0\",\"method\":\"notifications/message\",\"params\":{}}\n\n",
+                    "event: message\n",
+                    "data: {\"jsonrpc\":\"2.0\",\"id\":1,\"result\":{\"protocolVersion\":\"2024-11-05\",\"capabilities\":{}}}\n\n",
+                )).header("Mcp-Session-Id", "session-42")
+            } else if !request.to_lowercase().contains("mcp-session-id: session-42") {
+                MockResponse::new("400 Bad Request", "text/plain", "missing session")
+            } else if request.contains("notifications/initialized") {
+                MockResponse::new("202 Accepted", "text/plain", "")
+            } else if !request.to_lowercase().contains("mcp-protocol-version: 2024-11-05") {
+                MockResponse::new("400 Bad Request", "text/plain", "missing protocol version")
+            } else if request.contains("tools/list") {
+                MockResponse::new("200 OK", "application/json",
+                    r#"{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"search","description":"Search the web"}]}}"#)
+            } else if request.contains("resources/read") {
+                MockResponse::new("200 OK", "application/json",
+                    r#"{"jsonrpc":"2.0","id":3,"result":{"contents":[{"uri":"file:///notes.md","text":"remote notes"}]}}"#)
+            } else {
+                MockResponse::new("404 Not Found", "text/plain", "")
+            }
+        }).await;
+        let url = format!("{}/mcp", server.url());
+
+        let config = McpServer {
+            id: "remote".to_string(),
+            server_type: "http".to_string(),
+            command: String::new(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            timeout_ms: None,
+            url: Some(url),
+            cwd: None,
+        };
+        let remote = connect_remote_server(&config).await.unwrap();
+        assert_eq!(remote.session_id.as_deref(), Some("session-42"));
+        assert_eq!(remote.protocol_version.as_deref(), Some(MCP_PROTOCOL_VERSION));
+
+        let remote_servers = Arc::new(RwLock::new(HashMap::from([("remote".to_string(), remote)])));
+        let result = send_remote_json_rpc(&remote_servers, "remote", "tools/list", serde_json::json!({}), None, None)
+            .await
+            .unwrap();
+        assert_eq!(result["tools"][0]["name"], "search");
+
+        let missing_url = McpServer { url: None, ..config };
+        assert!(connect_remote_server(&missing_url).await.unwrap_err().contains("has no URL"));
+
+        assert!(check_server_type("legacy", "sse").unwrap_err().contains("legacy SSE"));
+        let legacy_import = r#"{"mcpServers":{"legacy":{"type":"sse","url":"http://localhost/sse"}}}"#;
+        assert!(servers_from_config_json(legacy_import).unwrap_err().contains("legacy SSE"));
+
+        let manager = McpServerManager { remote_servers, ..Default::default() };
+        let resource = fetch_mcp_resource(&manager, "remote", "file:///notes.md").await.unwrap();
+        assert_eq!(resource["contents"][0]["text"], "remote notes");
+
+        apply_running_server_timeout(&manager, "remote", Some(2500)).unwrap();
+        assert_eq!(manager.remote_servers.read().unwrap()["remote"].timeout_ms, Some(2500));
+    }
+}
@@ -0,0 +1,143 @@
+//! Mock HTTP server shared by tests that talk to providers, proxies and MCP endpoints
+//!
+//! Every connection carries one request, answered by the handler the server was started
+//! with; the raw requests are kept so tests can check what was sent.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A canned response: a plain body sent with `Content-Length`, or parts sent with chunked
+/// transfer encoding, each after its delay
+pub struct MockResponse {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: String,
+    chunks: Option<Vec<(Duration, String)>>,
+    hold_open: Option<Duration>,
+}
+
+impl MockResponse {
+    pub fn new(status: &str, content_type: &str, body: impl Into<String>) -> Self {
+        Self {
+            status: status.to_string(),
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.into(),
+            chunks: None,
+            hold_open: None,
+        }
+    }
+
+    /// Stream `chunks` with chunked transfer encoding, sleeping before each one
+    pub fn chunked(status: &str, content_type: &str, chunks: Vec<(Duration, String)>) -> Self {
+        Self {
+            chunks: Some(chunks),
+            ..Self::new(status, content_type, "")
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Keep a chunked response open for `duration` after its last chunk instead of ending it
+    pub fn hold_open(mut self, duration: Duration) -> Self {
+        self.hold_open = Some(duration);
+        self
+    }
+
+    async fn write_to(self, socket: &mut TcpStream) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        let Some(chunks) = self.chunks else {
+            head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n{}", self.body.len(), self.body));
+            return socket.write_all(head.as_bytes()).await;
+        };
+
+        head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+        socket.write_all(head.as_bytes()).await?;
+        for (delay, chunk) in chunks {
+            tokio::time::sleep(delay).await;
+            socket.write_all(format!("{:x}\r\n{}\r\n", chunk.len(), chunk).as_bytes()).await?;
+        }
+        match self.hold_open {
+            Some(duration) => {
+                tokio::time::sleep(duration).await;
+                Ok(())
+            }
+            None => socket.write_all(b"0\r\n\r\n").await,
+        }
+    }
+}
+
+/// A local HTTP server answering each request with `handler(raw request)`
+pub struct MockHttpServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockHttpServer {
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&str) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let request = read_request(&mut socket).await;
+                    recorded.lock().unwrap().push(request.clone());
+                    let _ = handler(&request).write_to(&mut socket).await;
+                });
+            }
+        });
+
+        Self { addr, requests }
+    }
+
+    /// `http://host:port`, without a trailing slash
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Raw requests received so far, in arrival order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Read one request: its headers and as much body as `Content-Length` announces
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&request);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end].lines()
+                .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                .unwrap_or(0);
+            if request.len() >= header_end + 4 + content_length {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&request).into_owned()
+}
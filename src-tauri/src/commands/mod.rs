@@ -13,3 +13,9 @@ pub mod mcp;
 pub use self::mcp::*;
 pub mod skills;
 pub use self::skills::*;
+pub mod schema;
+pub use self::schema::*;
+pub mod config;
+pub use self::config::*;
+#[cfg(test)]
+pub mod mock_http;
@@ -3,7 +3,38 @@
 
 use tauri::State;
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use futures::StreamExt;
+use zeroize::Zeroize;
 use crate::state::{SharedState, LLMProvider, LLMModel, AppState};
+use crate::services::persistence::PersistenceService;
+use crate::commands::config::{active_proxy_url, http_client};
+
+/// Maximum number of models validated at once by `validate_all_models`
+const MAX_CONCURRENT_MODEL_VALIDATIONS: usize = 4;
+
+/// Header names whose values are secrets and must never be shown
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "x-api-key", "api-key", "proxy-authorization"];
+
+/// Summary of a provider deduplication pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupeSummary {
+    pub removed_provider_ids: Vec<String>,
+    pub repointed_models: usize,
+    pub removed_model_ids: Vec<String>,
+}
+
+/// Model metadata reported by a provider's `/models` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteModelInfo {
+    pub id: String,
+    pub context_length: Option<usize>,
+    pub max_tokens: Option<usize>,
+}
+
+/// Context length and max tokens used when nothing better is known
+const DEFAULT_MODEL_TOKEN_LIMIT: usize = 4096;
 
 /// Validation result for provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +44,49 @@ pub struct ValidationResult {
     pub latency_ms: Option<u64>,
 }
 
+/// Build the headers sent with every request to a provider
+pub fn provider_request_headers(provider: &LLMProvider) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    if !provider.api_key.is_empty() {
+        headers.insert("Authorization".to_string(), format!("Bearer {}", provider.api_key));
+    }
+    // Custom headers override the defaults
+    for (name, value) in &provider.custom_headers {
+        headers.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+        headers.insert(name.clone(), value.clone());
+    }
+    headers
+}
+
+/// Apply the provider headers to an outgoing request
+pub fn apply_provider_headers(
+    mut request: reqwest::RequestBuilder,
+    provider: &LLMProvider,
+) -> reqwest::RequestBuilder {
+    for (name, value) in provider_request_headers(provider) {
+        request = request.header(name, value);
+    }
+    request
+}
+
+/// Redact secret header values, keeping the auth scheme visible
+fn redact_headers(headers: HashMap<String, String>) -> HashMap<String, String> {
+    headers.into_iter()
+        .map(|(name, value)| {
+            if SENSITIVE_HEADERS.contains(&name.to_lowercase().as_str()) {
+                let redacted = match value.split_once(' ') {
+                    Some((scheme, _)) => format!("{} ***", scheme),
+                    None => "***".to_string(),
+                };
+                (name, redacted)
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
 /// Get all providers
 #[tauri::command]
 #[allow(dead_code)]
@@ -30,11 +104,12 @@ pub fn get_providers(
 pub fn get_provider(
     shared_state: State<'_, SharedState>,
     provider_id: String,
-) -> Option<LLMProvider> {
+) -> Result<LLMProvider, String> {
     shared_state.read(|state| {
         state.providers.iter()
             .find(|p| p.id == provider_id)
             .cloned()
+            .ok_or_else(|| state.not_found_message("provider", &provider_id))
     })
 }
 
@@ -47,6 +122,8 @@ pub fn create_provider(
     provider_type: String,
     base_url: String,
     api_key: String,
+    custom_headers: Option<HashMap<String, String>>,
+    default_model_id: Option<String>,
 ) -> Result<LLMProvider, String> {
     let provider_id = uuid::Uuid::new_v4().to_string();
     
@@ -57,6 +134,9 @@ pub fn create_provider(
         base_url,
         api_key,
         enabled: true,
+        custom_headers: custom_headers.unwrap_or_default(),
+        default_model_id: default_model_id.filter(|m| !m.is_empty()),
+        supports_streaming: None,
     };
     
     shared_state.write(|state| {
@@ -69,6 +149,7 @@ pub fn create_provider(
 /// Update an existing provider
 #[tauri::command]
 #[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
 pub fn update_provider(
     shared_state: State<'_, SharedState>,
     provider_id: String,
@@ -76,25 +157,49 @@ pub fn update_provider(
     base_url: Option<String>,
     api_key: Option<String>,
     enabled: Option<bool>,
+    custom_headers: Option<HashMap<String, String>>,
+    default_model_id: Option<String>,
 ) -> Result<LLMProvider, String> {
     let mut updated = None;
     
     shared_state.write(|state| {
         if let Some(provider) = state.providers.iter_mut().find(|p| p.id == provider_id) {
             if let Some(n) = name { provider.name = n; }
-            if let Some(url) = base_url { provider.base_url = url; }
+            if let Some(url) = base_url {
+                // A different endpoint may not share the old one's streaming support
+                if url != provider.base_url { provider.supports_streaming = None; }
+                provider.base_url = url;
+            }
             if let Some(key) = api_key { provider.api_key = key; }
             if let Some(e) = enabled { provider.enabled = e; }
+            if let Some(h) = custom_headers { provider.custom_headers = h; }
+            // An empty string clears the default model
+            if let Some(m) = default_model_id { provider.default_model_id = Some(m).filter(|m| !m.is_empty()); }
             updated = Some(provider.clone());
         }
     });
     
     match updated {
         Some(p) => Ok(p),
-        None => Err(format!("Provider '{}' not found", provider_id)),
+        None => Err(shared_state.read(|state| state.not_found_message("provider", &provider_id))),
     }
 }
 
+/// Get the model used when a request to this provider does not name one
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_provider_default_model(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<Option<String>, String> {
+    shared_state.read(|state| {
+        match state.providers.iter().find(|p| p.id == provider_id) {
+            Some(p) => Ok(p.default_model_id.clone()),
+            None => Err(state.not_found_message("provider", &provider_id)),
+        }
+    })
+}
+
 /// Delete a provider
 #[tauri::command]
 #[allow(dead_code)]
@@ -116,6 +221,100 @@ pub fn delete_provider(
     removed
 }
 
+/// Merge providers sharing `base_url` + `provider_type`, e.g. after repeated imports
+/// The first provider is kept; models of the others are re-pointed to it
+#[tauri::command]
+#[allow(dead_code)]
+pub fn dedupe_providers(shared_state: State<'_, SharedState>) -> DedupeSummary {
+    shared_state.write(dedupe_providers_in_state)
+}
+
+fn dedupe_providers_in_state(state: &mut AppState) -> DedupeSummary {
+    let mut summary = DedupeSummary::default();
+    let key = |p: &LLMProvider| (p.base_url.trim_end_matches('/').to_string(), p.provider_type.clone());
+
+    // Map each duplicate provider id to the id of the provider it merges into
+    let mut merged_into: HashMap<String, String> = HashMap::new();
+    let mut kept: Vec<LLMProvider> = Vec::new();
+    for provider in std::mem::take(&mut state.providers) {
+        let Some(target) = kept.iter_mut().find(|k| key(k) == key(&provider)) else {
+            kept.push(provider);
+            continue;
+        };
+
+        // Never lose a configured key to an empty one
+        if target.api_key.is_empty() && !provider.api_key.is_empty() {
+            target.api_key = provider.api_key.clone();
+        }
+        if target.default_model_id.is_none() {
+            target.default_model_id = provider.default_model_id.clone();
+        }
+        for (name, value) in &provider.custom_headers {
+            target.custom_headers.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+
+        merged_into.insert(provider.id.clone(), target.id.clone());
+        summary.removed_provider_ids.push(provider.id);
+    }
+    state.providers = kept;
+
+    // Re-point models, dropping any that now duplicate a model of the kept provider
+    let mut model_replacements: HashMap<String, String> = HashMap::new();
+    let mut models: Vec<LLMModel> = Vec::new();
+    for mut model in std::mem::take(&mut state.models) {
+        if let Some(target_id) = merged_into.get(&model.provider_id) {
+            model.provider_id = target_id.clone();
+            if let Some(existing) = models.iter().find(|m| m.provider_id == model.provider_id && m.model_id == model.model_id) {
+                model_replacements.insert(model.id.clone(), existing.id.clone());
+                summary.removed_model_ids.push(model.id);
+                continue;
+            }
+            summary.repointed_models += 1;
+        }
+        models.push(model);
+    }
+    state.models = models;
+
+    if let Some(active) = state.config.active_provider_id.as_ref().and_then(|id| merged_into.get(id)) {
+        state.config.active_provider_id = Some(active.clone());
+    }
+    if let Some(active) = state.config.active_model_id.as_ref().and_then(|id| model_replacements.get(id)) {
+        state.config.active_model_id = Some(active.clone());
+    }
+
+    summary
+}
+
+/// Clear a provider's API key, zeroizing it in memory and saving so it is removed from disk
+/// An encrypted or unloaded state file is not rewritten; the error says the key is only
+/// cleared in memory, so the frontend can save the state itself
+#[tauri::command]
+#[allow(dead_code)]
+pub fn clear_provider_key(
+    shared_state: State<'_, SharedState>,
+    persistence: State<'_, PersistenceService>,
+    provider_id: String,
+) -> Result<(), String> {
+    clear_key_and_save(&shared_state, &persistence, &provider_id)
+}
+
+fn clear_key_and_save(shared_state: &SharedState, persistence: &PersistenceService, provider_id: &str) -> Result<(), String> {
+    shared_state.write(|state| clear_key_in_state(state, provider_id))?;
+    persistence.save_now()
+        .map_err(|e| format!("API key cleared in memory only: {}", e))
+}
+
+/// Zeroize the provider's API key in place
+fn clear_key_in_state(state: &mut AppState, provider_id: &str) -> Result<(), String> {
+    match state.providers.iter_mut().find(|p| p.id == provider_id) {
+        Some(provider) => {
+            provider.api_key.zeroize();
+            Ok(())
+        }
+        None => Err(format!("Provider '{}' not found", provider_id)),
+    }
+}
+
 /// Set a provider as default
 #[tauri::command]
 #[allow(dead_code)]
@@ -137,7 +336,7 @@ pub fn set_default_provider(
     if found {
         Ok(())
     } else {
-        Err(format!("Provider '{}' not found", provider_id))
+        Err(shared_state.read(|state| state.not_found_message("provider", &provider_id)))
     }
 }
 
@@ -154,19 +353,17 @@ pub async fn validate_provider(
     
     let provider = match provider {
         Some(p) => p,
-        None => return Err(format!("Provider '{}' not found", provider_id)),
+        None => return Err(shared_state.read(|state| state.not_found_message("provider", &provider_id))),
     };
     
     // Make a simple API call to validate
     let start_time = std::time::Instant::now();
-    let client = reqwest::Client::new();
+    let client = http_client();
     
     // For OpenAI-compatible APIs, check models endpoint
     let test_url = format!("{}/models", provider.base_url);
     
-    match client
-        .get(&test_url)
-        .header("Authorization", format!("Bearer {}", provider.api_key))
+    match apply_provider_headers(client.get(&test_url), &provider)
         .send()
         .await
     {
@@ -198,6 +395,295 @@ pub async fn validate_provider(
     }
 }
 
+/// Number of streams started against a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderUsage {
+    pub provider_id: String,
+    pub name: String,
+    pub count: u64,
+}
+
+/// Streams started per provider id since launch
+static PROVIDER_USAGE: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+
+fn provider_usage() -> &'static Mutex<HashMap<String, u64>> {
+    PROVIDER_USAGE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Count a stream started against a provider
+pub fn record_provider_usage(provider_id: &str) {
+    if let Ok(mut usage) = provider_usage().lock() {
+        *usage.entry(provider_id.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Get the most used providers, most streams first
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_top_providers(
+    shared_state: State<'_, SharedState>,
+    limit: usize,
+) -> Vec<ProviderUsage> {
+    let usage = provider_usage().lock()
+        .map(|usage| usage.clone())
+        .unwrap_or_default();
+    shared_state.read(|state| rank_providers_by_usage(&state.providers, &usage, limit))
+}
+
+/// Providers with recorded usage ranked by count, ties by name
+fn rank_providers_by_usage(providers: &[LLMProvider], usage: &HashMap<String, u64>, limit: usize) -> Vec<ProviderUsage> {
+    let mut ranked: Vec<ProviderUsage> = providers.iter()
+        .filter_map(|p| {
+            usage.get(&p.id).map(|&count| ProviderUsage {
+                provider_id: p.id.clone(),
+                name: p.name.clone(),
+                count,
+            })
+        })
+        .collect();
+    ranked.sort_by_key(|p| (std::cmp::Reverse(p.count), p.name.clone()));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// Timeout for each step of an endpoint diagnosis
+const DIAGNOSE_STEP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Port assumed for proxies whose scheme has no default, such as `socks5`
+const DEFAULT_SOCKS_PORT: u16 = 1080;
+
+/// Result of checking an endpoint step by step; later steps are skipped after a failure
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EndpointDiagnosis {
+    /// Proxy in use; the DNS and TCP steps then check the proxy, and TLS is only
+    /// exercised by the HTTP request through it
+    pub proxy_url: Option<String>,
+    pub dns_ok: bool,
+    pub resolved_addresses: Vec<String>,
+    pub tcp_connect_ms: Option<u64>,
+    /// None for plain `http://` endpoints
+    pub tls_ok: Option<bool>,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// Diagnose connectivity to a base URL: DNS lookup, TCP connect, TLS handshake, then an HTTP GET
+/// Any HTTP status counts as reachable, so auth failures show up as e.g. 401 rather than an error
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn diagnose_endpoint(base_url: String) -> EndpointDiagnosis {
+    let mut diagnosis = EndpointDiagnosis::default();
+    if let Err(e) = run_endpoint_diagnosis(&base_url, &mut diagnosis).await {
+        diagnosis.error = Some(e);
+    }
+    diagnosis
+}
+
+async fn run_endpoint_diagnosis(base_url: &str, diagnosis: &mut EndpointDiagnosis) -> Result<(), String> {
+    let url = reqwest::Url::parse(base_url).map_err(|e| format!("Invalid URL: {}", e))?;
+    diagnosis.proxy_url = active_proxy_url();
+    let target = diagnosis_target(&url, diagnosis.proxy_url.as_deref())?;
+
+    let addrs = resolve_socket_addrs(&target).await?;
+    if addrs.is_empty() {
+        return Err(format!("DNS lookup returned no addresses for {}", target.host_str().unwrap_or_default()));
+    }
+    diagnosis.dns_ok = true;
+    diagnosis.resolved_addresses = addrs.iter().map(|a| a.ip().to_string()).collect();
+
+    let start_time = std::time::Instant::now();
+    let stream = tokio::time::timeout(DIAGNOSE_STEP_TIMEOUT, tokio::net::TcpStream::connect(addrs.as_slice()))
+        .await
+        .map_err(|_| "TCP connect timed out".to_string())?
+        .map_err(|e| format!("TCP connect failed: {}", e))?;
+    diagnosis.tcp_connect_ms = Some(start_time.elapsed().as_millis() as u64);
+
+    if url.scheme() == "https" && diagnosis.proxy_url.is_none() {
+        diagnosis.tls_ok = Some(false);
+        // IPv6 hosts come bracketed, as written in the URL
+        let domain = target.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+        let connector = tokio_native_tls::native_tls::TlsConnector::new()
+            .map_err(|e| format!("TLS setup failed: {}", e))?;
+        tokio::time::timeout(DIAGNOSE_STEP_TIMEOUT, tokio_native_tls::TlsConnector::from(connector).connect(domain, stream))
+            .await
+            .map_err(|_| "TLS handshake timed out".to_string())?
+            .map_err(|e| format!("TLS handshake failed: {}", e))?;
+        diagnosis.tls_ok = Some(true);
+    }
+
+    let resp = http_client()
+        .get(url)
+        .timeout(DIAGNOSE_STEP_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP request failed: {}", e))?;
+    diagnosis.http_status = Some(resp.status().as_u16());
+    Ok(())
+}
+
+/// The URL the client opens a connection to: the proxy when one is configured, else the endpoint
+fn diagnosis_target(url: &reqwest::Url, proxy_url: Option<&str>) -> Result<reqwest::Url, String> {
+    match proxy_url {
+        Some(proxy_url) => reqwest::Url::parse(proxy_url).map_err(|e| format!("Invalid proxy URL: {}", e)),
+        None => Ok(url.clone()),
+    }
+}
+
+/// Resolve a URL's host and port, off the async runtime since the lookup blocks
+/// `Url` handles bracketed IPv6 hosts and IP literals, which need no DNS query
+async fn resolve_socket_addrs(url: &reqwest::Url) -> Result<Vec<std::net::SocketAddr>, String> {
+    let url = url.clone();
+    let lookup = tokio::task::spawn_blocking(move || url.socket_addrs(|| Some(DEFAULT_SOCKS_PORT)));
+    tokio::time::timeout(DIAGNOSE_STEP_TIMEOUT, lookup)
+        .await
+        .map_err(|_| "DNS lookup timed out".to_string())?
+        .map_err(|e| format!("DNS lookup failed: {}", e))?
+        .map_err(|e| format!("DNS lookup failed: {}", e))
+}
+
+/// Check whether a provider accepts streaming requests
+/// Probes once with a minimal `stream: true` request and caches the result on the provider
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn supports_streaming(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<bool, String> {
+    probe_streaming_support(&shared_state, &provider_id).await
+}
+
+/// Cached streaming support of a provider, probing it on first use
+/// A failed probe is not cached, so a later call probes again
+async fn probe_streaming_support(shared_state: &SharedState, provider_id: &str) -> Result<bool, String> {
+    let (provider, probe_model) = shared_state.read(|state| provider_probe_target(state, provider_id))?;
+
+    if let Some(cached) = provider.supports_streaming {
+        return Ok(cached);
+    }
+
+    let model_id = probe_model?;
+    let supported = detect_streaming_support(&provider, &model_id).await?;
+
+    shared_state.write(|state| {
+        if let Some(p) = state.providers.iter_mut().find(|p| p.id == provider_id) {
+            p.supports_streaming = Some(supported);
+        }
+    });
+
+    Ok(supported)
+}
+
+/// Measure a provider's time to first byte in milliseconds
+/// Sends a minimal streaming request and aborts it as soon as the first body byte arrives
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn measure_provider_ttfb(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<u64, String> {
+    let (provider, probe_model) = shared_state.read(|state| provider_probe_target(state, &provider_id))?;
+    first_byte_latency(&provider, &probe_model?).await
+}
+
+/// A provider and the model to probe it with: its default model, else any model it serves
+fn provider_probe_target(state: &AppState, provider_id: &str) -> Result<(LLMProvider, Result<String, String>), String> {
+    let provider = state.providers.iter()
+        .find(|p| p.id == provider_id)
+        .cloned()
+        .ok_or_else(|| state.not_found_message("provider", provider_id))?;
+    let model_id = provider.default_model_id.clone()
+        .or_else(|| {
+            state.models.iter()
+                .find(|m| m.provider_id == provider_id)
+                .map(|m| m.model_id.clone())
+        })
+        .ok_or_else(|| format!("Provider '{}' has no model to probe with", provider.name));
+    Ok((provider, model_id))
+}
+
+/// Minimal one-token streaming chat request used by the probes
+fn streaming_probe_body(model_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "model": model_id,
+        "messages": [{"role": "user", "content": "hi"}],
+        "max_tokens": 1,
+        "stream": true
+    })
+}
+
+async fn first_byte_latency(provider: &LLMProvider, model_id: &str) -> Result<u64, String> {
+    let request = http_client()
+        .post(format!("{}/chat/completions", provider.base_url))
+        .timeout(std::time::Duration::from_secs(30));
+    let request = apply_provider_headers(request, provider).json(&streaming_probe_body(model_id));
+
+    let start_time = std::time::Instant::now();
+    let mut resp = request.send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("API error ({}) while measuring first byte", resp.status()));
+    }
+
+    match resp.chunk().await.map_err(|e| format!("Stream error: {}", e))? {
+        // Dropping the response here closes the connection without reading the rest
+        Some(_) => Ok(start_time.elapsed().as_millis() as u64),
+        None => Err("Provider closed the stream without sending any data".to_string()),
+    }
+}
+
+/// Send a minimal streaming request and report whether the reply is an event stream
+/// Only a successful reply or a client error naming `stream` answers the question; auth
+/// failures, rate limits, outages and unknown models are errors
+async fn detect_streaming_support(provider: &LLMProvider, model_id: &str) -> Result<bool, String> {
+    let request = http_client()
+        .post(format!("{}/chat/completions", provider.base_url))
+        .timeout(std::time::Duration::from_secs(30));
+    let resp = apply_provider_headers(request, provider)
+        .json(&streaming_probe_body(model_id))
+        .send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        let rejects_stream = status.is_client_error()
+            && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+            && body.to_lowercase().contains("stream");
+        if rejects_stream {
+            return Ok(false);
+        }
+        return Err(format!("API error ({}) while probing streaming", status));
+    }
+
+    let is_event_stream = resp.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("text/event-stream"));
+    if is_event_stream {
+        return Ok(true);
+    }
+
+    let body = resp.text().await.unwrap_or_default();
+    Ok(body.trim_start().starts_with("data:"))
+}
+
+/// Preview the headers sent to a provider, with secret values redacted
+#[tauri::command]
+#[allow(dead_code)]
+pub fn preview_provider_headers(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<HashMap<String, String>, String> {
+    shared_state.read(|state| {
+        match state.providers.iter().find(|p| p.id == provider_id) {
+            Some(provider) => Ok(redact_headers(provider_request_headers(provider))),
+            None => Err(state.not_found_message("provider", &provider_id)),
+        }
+    })
+}
+
 // ===== Model Commands =====
 
 /// Get all models for a provider
@@ -232,24 +718,100 @@ pub fn get_model(
     })
 }
 
+/// List the models a provider reports, with their context lengths where available
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn fetch_provider_models(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<Vec<RemoteModelInfo>, String> {
+    let provider = shared_state.read(|state| {
+        state.providers.iter().find(|p| p.id == provider_id).cloned()
+            .ok_or_else(|| state.not_found_message("provider", &provider_id))
+    })?;
+
+    fetch_remote_models(&provider).await
+}
+
+async fn fetch_remote_models(provider: &LLMProvider) -> Result<Vec<RemoteModelInfo>, String> {
+    let request = http_client()
+        .get(format!("{}/models", provider.base_url))
+        .timeout(std::time::Duration::from_secs(30));
+    let resp = apply_provider_headers(request, provider)
+        .send()
+        .await
+        .map_err(|e| format!("Connection failed: {}", e))?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let error_text = resp.text().await.unwrap_or_default();
+        return Err(format!("API error ({}): {}", status, error_text));
+    }
+
+    let body: serde_json::Value = resp.json()
+        .await
+        .map_err(|e| format!("Failed to parse models response: {}", e))?;
+    Ok(remote_models_from_json(&body))
+}
+
+/// Parse an OpenAI-style `{ data: [...] }` model list; field names vary between providers
+fn remote_models_from_json(body: &serde_json::Value) -> Vec<RemoteModelInfo> {
+    let first_usize = |model: &serde_json::Value, pointers: &[&str]| {
+        pointers.iter()
+            .find_map(|p| model.pointer(p).and_then(|v| v.as_u64()))
+            .map(|v| v as usize)
+    };
+
+    body.get("data")
+        .and_then(|d| d.as_array())
+        .map(|models| models.iter()
+            .filter_map(|model| {
+                Some(RemoteModelInfo {
+                    id: model.get("id")?.as_str()?.to_string(),
+                    context_length: first_usize(model, &["/context_length", "/context_window", "/max_context_length", "/max_model_len"]),
+                    max_tokens: first_usize(model, &["/max_output_tokens", "/max_completion_tokens", "/top_provider/max_completion_tokens"]),
+                })
+            })
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Context length and max tokens for a new model, preferring remote metadata
+fn model_token_limits(remote: Option<&RemoteModelInfo>) -> (usize, usize) {
+    let context_length = remote.and_then(|r| r.context_length).unwrap_or(DEFAULT_MODEL_TOKEN_LIMIT);
+    let max_tokens = remote.and_then(|r| r.max_tokens).unwrap_or(DEFAULT_MODEL_TOKEN_LIMIT.min(context_length));
+    (context_length, max_tokens)
+}
+
 /// Create a new model
+/// With `auto_fill`, context length and max tokens come from the provider's model list
 #[tauri::command]
 #[allow(dead_code)]
-pub fn create_model(
+pub async fn create_model(
     shared_state: State<'_, SharedState>,
     provider_id: String,
     name: String,
     model_id: String,
     model_type: String,
+    auto_fill: Option<bool>,
 ) -> Result<LLMModel, String> {
     // Verify provider exists
-    let provider_exists = shared_state.read(|state| {
-        state.providers.iter().any(|p| p.id == provider_id)
+    let provider = shared_state.read(|state| {
+        state.providers.iter().find(|p| p.id == provider_id).cloned()
     });
     
-    if !provider_exists {
-        return Err(format!("Provider '{}' not found", provider_id));
-    }
+    let Some(provider) = provider else {
+        return Err(shared_state.read(|state| state.not_found_message("provider", &provider_id)));
+    };
+    
+    let remote = if auto_fill.unwrap_or(false) {
+        fetch_remote_models(&provider).await?
+            .into_iter()
+            .find(|m| m.id == model_id)
+    } else {
+        None
+    };
+    let (context_length, max_tokens) = model_token_limits(remote.as_ref());
     
     let new_model_id = uuid::Uuid::new_v4().to_string();
     
@@ -259,8 +821,8 @@ pub fn create_model(
         name,
         model_id,
         model_type,
-        context_length: Some(4096),
-        max_tokens: Some(4096),
+        context_length: Some(context_length),
+        max_tokens: Some(max_tokens),
         temperature: Some(0.7),
         dimensions: None,
         is_default: false,
@@ -390,6 +952,64 @@ pub fn get_default_model_config(
     })
 }
 
+/// Suggested provider and model for onboarding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelRecommendation {
+    pub provider: LLMProvider,
+    pub model: LLMModel,
+    /// Why it was picked: "default", "most_used" or "first_enabled"
+    pub reason: String,
+}
+
+/// Recommend a chat model without changing the configuration
+/// Returns the configured default if usable, otherwise the model used by the most
+/// sessions, otherwise the first chat model of an enabled provider
+#[tauri::command]
+#[allow(dead_code)]
+pub fn recommend_default_model(
+    shared_state: State<'_, SharedState>,
+) -> Option<ModelRecommendation> {
+    shared_state.read(recommend_model_in_state)
+}
+
+fn recommend_model_in_state(state: &AppState) -> Option<ModelRecommendation> {
+    let usable: Vec<(&LLMProvider, &LLMModel)> = state.models.iter()
+        .filter(|m| m.model_type.eq_ignore_ascii_case("chat"))
+        .filter_map(|m| {
+            state.providers.iter()
+                .find(|p| p.id == m.provider_id && p.enabled)
+                .map(|p| (p, m))
+        })
+        .collect();
+    let recommend = |(provider, model): (&LLMProvider, &LLMModel), reason: &str| ModelRecommendation {
+        provider: provider.clone(),
+        model: model.clone(),
+        reason: reason.to_string(),
+    };
+
+    let default = usable.iter()
+        .find(|(_, m)| state.config.active_model_id.as_deref() == Some(m.id.as_str()))
+        .or_else(|| usable.iter().find(|(_, m)| m.is_default));
+    if let Some(&pair) = default {
+        return Some(recommend(pair, "default"));
+    }
+
+    // Sessions may store either the model entry id or the provider's model name
+    let session_count = |model: &LLMModel| state.sessions.values()
+        .filter(|s| s.model_id.as_deref().is_some_and(|id| id == model.id || id == model.model_id))
+        .count();
+    let most_used = usable.iter()
+        .enumerate()
+        .map(|(index, &pair)| (session_count(pair.1), std::cmp::Reverse(index), pair))
+        .filter(|(count, _, _)| *count > 0)
+        .max_by_key(|(count, index, _)| (*count, *index));
+    if let Some((_, _, pair)) = most_used {
+        return Some(recommend(pair, "most_used"));
+    }
+
+    usable.first().map(|&pair| recommend(pair, "first_enabled"))
+}
+
 /// Test provider configuration without saving
 #[tauri::command]
 #[allow(dead_code)]
@@ -399,7 +1019,7 @@ pub async fn test_provider_config(
     api_key: String,
 ) -> Result<ValidationResult, String> {
     let start_time = std::time::Instant::now();
-    let client = reqwest::Client::new();
+    let client = http_client();
     
     // For OpenAI-compatible APIs, check models endpoint
     let test_url = format!("{}/models", base_url);
@@ -457,7 +1077,7 @@ pub async fn validate_model_availability(
     model_id: String,
 ) -> Result<ModelValidationResult, String> {
     let start_time = std::time::Instant::now();
-    let client = reqwest::Client::new();
+    let client = http_client();
     
     // Make a minimal chat completion request to validate model
     let test_url = format!("{}/chat/completions", base_url);
@@ -515,3 +1135,385 @@ pub async fn validate_model_availability(
         }
     }
 }
+
+/// Validate every model of a provider concurrently
+/// Returns a map of model id to its validation result
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn validate_all_models(
+    shared_state: State<'_, SharedState>,
+    provider_id: String,
+) -> Result<HashMap<String, ModelValidationResult>, String> {
+    let (provider, models) = shared_state.read(|state| {
+        let provider = state.providers.iter()
+            .find(|p| p.id == provider_id)
+            .cloned()
+            .ok_or_else(|| format!("Provider '{}' not found", provider_id))?;
+        let models: Vec<LLMModel> = state.models.iter()
+            .filter(|m| m.provider_id == provider_id)
+            .cloned()
+            .collect();
+        Ok::<_, String>((provider, models))
+    })?;
+
+    Ok(validate_models(&provider, models).await)
+}
+
+async fn validate_models(provider: &LLMProvider, models: Vec<LLMModel>) -> HashMap<String, ModelValidationResult> {
+    futures::stream::iter(models)
+        .map(|model| async move {
+            let result = validate_model_availability(
+                provider.provider_type.clone(),
+                provider.base_url.clone(),
+                provider.api_key.clone(),
+                model.model_id.clone(),
+            )
+            .await
+            .unwrap_or_else(|e| ModelValidationResult {
+                success: false,
+                latency: 0,
+                message: e,
+            });
+            (model.id, result)
+        })
+        .buffer_unordered(MAX_CONCURRENT_MODEL_VALIDATIONS)
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock_http::{MockHttpServer, MockResponse};
+    use crate::state::ChatSession;
+
+    fn test_provider() -> LLMProvider {
+        LLMProvider {
+            id: "provider_1".to_string(),
+            name: "OpenAI".to_string(),
+            provider_type: "openai".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            api_key: "sk-secret".to_string(),
+            enabled: true,
+            custom_headers: HashMap::new(),
+            default_model_id: None,
+            supports_streaming: None,
+        }
+    }
+
+    #[test]
+    fn test_preview_headers_redacts_auth() {
+        let mut provider = test_provider();
+        provider.custom_headers.insert("OpenAI-Organization".to_string(), "org-123".to_string());
+
+        let headers = redact_headers(provider_request_headers(&provider));
+        assert_eq!(headers.get("OpenAI-Organization").map(String::as_str), Some("org-123"));
+        assert_eq!(headers.get("Authorization").map(String::as_str), Some("Bearer ***"));
+        assert!(!headers.values().any(|v| v.contains("sk-secret")));
+    }
+
+    #[test]
+    fn test_custom_headers_override_defaults() {
+        let mut provider = test_provider();
+        provider.custom_headers.insert("authorization".to_string(), "Token abc".to_string());
+
+        let headers = provider_request_headers(&provider);
+        assert_eq!(headers.get("authorization").map(String::as_str), Some("Token abc"));
+        assert!(!headers.contains_key("Authorization"));
+    }
+
+    #[test]
+    fn test_clear_provider_key() {
+        let mut state = AppState::default();
+        state.providers.push(test_provider());
+
+        clear_key_in_state(&mut state, "provider_1").unwrap();
+        assert!(state.providers[0].api_key.is_empty());
+        assert!(clear_key_in_state(&mut state, "missing").is_err());
+    }
+
+    #[test]
+    fn test_clear_provider_key_saves_only_a_loaded_state() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state_path = temp_dir.path().join("state.bin");
+        let shared_state = SharedState::new();
+        let persistence = PersistenceService::with_path(shared_state.inner.clone(), state_path.clone());
+
+        // Nothing loaded yet: the key is cleared in memory but the file is left alone
+        shared_state.write(|state| state.providers.push(test_provider()));
+        let err = clear_key_and_save(&shared_state, &persistence, "provider_1").unwrap_err();
+        assert!(err.starts_with("API key cleared in memory only"), "{}", err);
+        assert!(!state_path.exists());
+
+        let mut saved = AppState::default();
+        saved.providers.push(test_provider());
+        persistence.save_state(saved).unwrap();
+        clear_key_and_save(&shared_state, &persistence, "provider_1").unwrap();
+
+        let reloaded = PersistenceService::with_path(std::sync::Arc::new(std::sync::RwLock::new(AppState::default())), state_path);
+        reloaded.load().unwrap();
+        assert!(reloaded.snapshot().unwrap().providers[0].api_key.is_empty());
+    }
+
+    fn test_model(id: &str, provider_id: &str, model_id: &str) -> LLMModel {
+        LLMModel {
+            id: id.to_string(),
+            provider_id: provider_id.to_string(),
+            name: model_id.to_string(),
+            model_id: model_id.to_string(),
+            model_type: "chat".to_string(),
+            context_length: None,
+            max_tokens: None,
+            temperature: None,
+            dimensions: None,
+            is_default: false,
+        }
+    }
+
+    #[test]
+    fn test_dedupe_providers_merges_duplicates() {
+        let mut state = AppState::default();
+        let mut first = test_provider();
+        first.api_key = String::new();
+        let mut second = test_provider();
+        second.id = "provider_2".to_string();
+        second.base_url = "https://api.openai.com/v1/".to_string();
+        let mut other = test_provider();
+        other.id = "provider_3".to_string();
+        other.provider_type = "anthropic".to_string();
+        state.providers = vec![first, second, other];
+        state.models = vec![
+            test_model("m1", "provider_1", "gpt-4o"),
+            test_model("m2", "provider_2", "gpt-4o"),
+            test_model("m3", "provider_2", "gpt-4o-mini"),
+            test_model("m4", "provider_3", "claude"),
+        ];
+        state.config.active_provider_id = Some("provider_2".to_string());
+        state.config.active_model_id = Some("m2".to_string());
+
+        let summary = dedupe_providers_in_state(&mut state);
+
+        assert_eq!(summary.removed_provider_ids, vec!["provider_2"]);
+        assert_eq!(summary.repointed_models, 1);
+        assert_eq!(summary.removed_model_ids, vec!["m2"]);
+        assert_eq!(state.providers.len(), 2);
+        assert_eq!(state.providers[0].id, "provider_1");
+        assert_eq!(state.providers[0].api_key, "sk-secret");
+        let model_owners: Vec<(&str, &str)> = state.models.iter()
+            .map(|m| (m.id.as_str(), m.provider_id.as_str()))
+            .collect();
+        assert_eq!(model_owners, vec![("m1", "provider_1"), ("m3", "provider_1"), ("m4", "provider_3")]);
+        assert_eq!(state.config.active_provider_id.as_deref(), Some("provider_1"));
+        assert_eq!(state.config.active_model_id.as_deref(), Some("m1"));
+    }
+
+    /// Serve chat completions with a handler mapping the raw request to (status, content type, body)
+    async fn spawn_mock_server(handler: fn(&str) -> (&'static str, &'static str, &'static str)) -> String {
+        MockHttpServer::start(move |request| {
+            let (status, content_type, body) = handler(request);
+            MockResponse::new(status, content_type, body)
+        }).await.url()
+    }
+
+    /// Serve chat completions, answering 404 for models whose name contains "missing"
+    async fn spawn_mock_provider() -> String {
+        spawn_mock_server(|request| {
+            if request.contains("missing") {
+                ("404 Not Found", "application/json", "{}")
+            } else {
+                ("200 OK", "application/json", "{}")
+            }
+        }).await
+    }
+
+    #[tokio::test]
+    async fn test_remote_models_fill_context_length() {
+        let mut provider = test_provider();
+        provider.base_url = spawn_mock_server(|request| {
+            if request.starts_with("GET /models") {
+                ("200 OK", "application/json", r#"{"object":"list","data":[
+                    {"id":"gpt-4o","context_length":128000,"max_output_tokens":16384},
+                    {"id":"local-llama","max_model_len":8192}
+                ]}"#)
+            } else {
+                ("404 Not Found", "application/json", "{}")
+            }
+        }).await;
+
+        let models = fetch_remote_models(&provider).await.unwrap();
+        let find = |id: &str| models.iter().find(|m| m.id == id);
+
+        assert_eq!(model_token_limits(find("gpt-4o")), (128000, 16384));
+        assert_eq!(model_token_limits(find("local-llama")), (8192, 4096));
+        assert_eq!(model_token_limits(find("unknown")), (4096, 4096));
+    }
+
+    #[tokio::test]
+    async fn test_detect_streaming_support() {
+        let mut provider = test_provider();
+
+        provider.base_url = spawn_mock_server(|_| {
+            ("200 OK", "application/json", r#"{"choices":[{"message":{"role":"assistant","content":"h"}}]}"#)
+        }).await;
+        assert!(!detect_streaming_support(&provider, "gpt-4o").await.unwrap());
+
+        provider.base_url = spawn_mock_server(|_| {
+            ("200 OK", "text/event-stream", "data: {\"choices\":[]}\n\ndata: [DONE]\n\n")
+        }).await;
+        assert!(detect_streaming_support(&provider, "gpt-4o").await.unwrap());
+
+        provider.base_url = spawn_mock_server(|_| {
+            ("400 Bad Request", "application/json", r#"{"error":{"message":"stream is not supported"}}"#)
+        }).await;
+        assert!(!detect_streaming_support(&provider, "gpt-4o").await.unwrap());
+
+        provider.base_url = spawn_mock_server(|_| {
+            ("400 Bad Request", "application/json", r#"{"error":{"message":"model not found"}}"#)
+        }).await;
+        assert!(detect_streaming_support(&provider, "gpt-4o").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_streaming_probe_is_not_cached() {
+        let shared_state = SharedState::new();
+        let base_url = spawn_mock_server(|_| ("503 Service Unavailable", "text/plain", "overloaded")).await;
+        shared_state.write(|state| {
+            state.providers.push(LLMProvider { base_url, ..test_provider() });
+            state.models.push(test_model("model_1", "provider_1", "gpt-4o"));
+        });
+
+        assert!(probe_streaming_support(&shared_state, "provider_1").await.is_err());
+        assert_eq!(shared_state.read(|state| state.providers[0].supports_streaming), None);
+
+        let base_url = spawn_mock_server(|_| ("429 Too Many Requests", "text/plain", "slow down, stream later")).await;
+        shared_state.write(|state| state.providers[0].base_url = base_url);
+        assert!(probe_streaming_support(&shared_state, "provider_1").await.is_err());
+        assert_eq!(shared_state.read(|state| state.providers[0].supports_streaming), None);
+
+        let base_url = spawn_mock_server(|_| ("200 OK", "application/json", "{}")).await;
+        shared_state.write(|state| state.providers[0].base_url = base_url);
+        assert!(!probe_streaming_support(&shared_state, "provider_1").await.unwrap());
+        assert_eq!(shared_state.read(|state| state.providers[0].supports_streaming), Some(false));
+    }
+
+    #[test]
+    fn test_recommend_default_model() {
+        let mut state = AppState::default();
+        assert!(recommend_model_in_state(&state).is_none());
+
+        let disabled = LLMProvider { id: "provider_2".to_string(), enabled: false, ..test_provider() };
+        state.providers.extend([test_provider(), disabled]);
+        state.models.push(test_model("m_off", "provider_2", "gpt-4o"));
+        state.models.push(LLMModel { model_type: "embedding".to_string(), ..test_model("m_embed", "provider_1", "text-embedding-3-small") });
+        assert!(recommend_model_in_state(&state).is_none());
+
+        state.models.push(test_model("m_mini", "provider_1", "gpt-4o-mini"));
+        state.models.push(test_model("m_full", "provider_1", "gpt-4.1"));
+        let first = recommend_model_in_state(&state).unwrap();
+        assert_eq!((first.model.id.as_str(), first.reason.as_str()), ("m_mini", "first_enabled"));
+
+        let mut session = ChatSession::new("session_1".to_string(), "Chat".to_string());
+        session.model_id = Some("gpt-4.1".to_string());
+        state.sessions.insert(session.id.clone(), session);
+        let used = recommend_model_in_state(&state).unwrap();
+        assert_eq!((used.model.id.as_str(), used.reason.as_str()), ("m_full", "most_used"));
+        assert_eq!(used.provider.id, "provider_1");
+
+        state.models[2].is_default = true;
+        let default = recommend_model_in_state(&state).unwrap();
+        assert_eq!((default.model.id.as_str(), default.reason.as_str()), ("m_mini", "default"));
+        assert!(!state.models[3].is_default);
+    }
+
+    #[test]
+    fn test_top_providers_ranked_by_usage() {
+        let provider = |id: &str, name: &str| LLMProvider {
+            id: id.to_string(),
+            name: name.to_string(),
+            ..test_provider()
+        };
+        let providers = vec![provider("p1", "OpenAI"), provider("p2", "Anthropic"), provider("p3", "Local")];
+
+        for id in ["p2", "p1", "p2", "p3", "p1", "p2"] {
+            record_provider_usage(id);
+        }
+        let usage = provider_usage().lock().unwrap().clone();
+
+        let top = rank_providers_by_usage(&providers, &usage, 2);
+        let ids: Vec<&str> = top.iter().map(|p| p.provider_id.as_str()).collect();
+        assert_eq!(ids, vec!["p2", "p1"]);
+        assert!(top[0].count > top[1].count);
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_endpoint_reports_http_status() {
+        let base_url = spawn_mock_server(|_| ("401 Unauthorized", "application/json", r#"{"error":"invalid api key"}"#)).await;
+
+        let diagnosis = diagnose_endpoint(base_url).await;
+        assert!(diagnosis.dns_ok);
+        assert_eq!(diagnosis.resolved_addresses, vec!["127.0.0.1"]);
+        assert!(diagnosis.tcp_connect_ms.is_some());
+        assert_eq!(diagnosis.tls_ok, None);
+        assert_eq!(diagnosis.http_status, Some(401));
+        assert!(diagnosis.error.is_none());
+
+        // Nothing listens on a port whose listener was dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let diagnosis = diagnose_endpoint(closed).await;
+        assert!(diagnosis.dns_ok);
+        assert!(diagnosis.tcp_connect_ms.is_none());
+        assert!(diagnosis.error.unwrap().starts_with("TCP connect failed"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnosis_resolves_ipv6_and_targets_proxy() {
+        let url = reqwest::Url::parse("http://[::1]:8080/v1").unwrap();
+        let addrs = resolve_socket_addrs(&url).await.unwrap();
+        assert_eq!(addrs, vec!["[::1]:8080".parse().unwrap()]);
+
+        assert_eq!(diagnosis_target(&url, None).unwrap(), url);
+        let proxy = diagnosis_target(&url, Some("socks5://127.0.0.1")).unwrap();
+        assert_eq!(resolve_socket_addrs(&proxy).await.unwrap(), vec!["127.0.0.1:1080".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_first_byte_latency() {
+        // Answer after a delay with one event, then hold the stream open
+        let server = MockHttpServer::start(|_| {
+            let event = (std::time::Duration::from_millis(150), "data: {\"choices\":[]}\n\n".to_string());
+            MockResponse::chunked("200 OK", "text/event-stream", vec![event])
+                .hold_open(std::time::Duration::from_secs(10))
+        }).await;
+
+        let mut provider = test_provider();
+        provider.base_url = server.url();
+
+        let started = std::time::Instant::now();
+        let ttfb = first_byte_latency(&provider, "gpt-4o").await.unwrap();
+        assert!((150..5000).contains(&ttfb), "unexpected ttfb {}", ttfb);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_validate_models_reports_each_model() {
+        let mut provider = test_provider();
+        provider.base_url = spawn_mock_provider().await;
+
+        let model = |id: &str, model_id: &str| test_model(id, &provider.id, model_id);
+        let models = vec![
+            model("m1", "gpt-4o"),
+            model("m2", "missing-model"),
+            model("m3", "gpt-4o-mini"),
+            model("m4", "missing-too"),
+        ];
+
+        let results = validate_models(&provider, models).await;
+        assert_eq!(results.len(), 4);
+        assert!(results["m1"].success);
+        assert!(results["m3"].success);
+        assert!(!results["m2"].success);
+        assert_eq!(results["m4"].message, "Model 'missing-too' not found");
+    }
+}
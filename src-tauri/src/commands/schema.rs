@@ -0,0 +1,161 @@
+//! Schema commands - runtime type descriptions for IPC payload validation
+//! Derived from the ts-rs declarations so they stay in sync with the generated types
+
+use serde_json::{json, Map, Value};
+use ts_rs::TS;
+use crate::state::{
+    AceConfig, AppConfig, ChatSession, DeepThinkingConfig, DeepThinkingStatus, LLMModel,
+    LLMProvider, McpServer, Message, ReasoningBlock, ReasoningMessage,
+};
+
+/// Describe a core state type as a JSON-schema-like object
+#[tauri::command]
+#[allow(dead_code)]
+pub fn describe_type_schema(type_name: String) -> Result<Value, String> {
+    let ts_type = match type_name.as_str() {
+        "Message" => Message::inline(),
+        "ChatSession" => ChatSession::inline(),
+        "LLMProvider" => LLMProvider::inline(),
+        "LLMModel" => LLMModel::inline(),
+        "McpServer" => McpServer::inline(),
+        "ReasoningBlock" => ReasoningBlock::inline(),
+        "ReasoningMessage" => ReasoningMessage::inline(),
+        "DeepThinkingConfig" => DeepThinkingConfig::inline(),
+        "DeepThinkingStatus" => DeepThinkingStatus::inline(),
+        "AppConfig" => AppConfig::inline(),
+        "AceConfig" => AceConfig::inline(),
+        _ => return Err(format!("Unknown type '{}'", type_name)),
+    };
+
+    let mut schema = ts_type_to_schema(&ts_type);
+    if let Some(obj) = schema.as_object_mut() {
+        obj.insert("title".to_string(), json!(type_name));
+    }
+    Ok(schema)
+}
+
+/// Convert a TypeScript type expression into a JSON-schema-like value
+fn ts_type_to_schema(ts_type: &str) -> Value {
+    let ts_type = ts_type.trim();
+
+    let variants = split_top_level(ts_type, '|');
+    if variants.len() > 1 {
+        let non_null: Vec<&str> = variants.iter()
+            .map(|v| v.trim())
+            .filter(|v| *v != "null")
+            .collect();
+        let nullable = non_null.len() < variants.len();
+
+        let mut schema = if non_null.len() == 1 {
+            ts_type_to_schema(non_null[0])
+        } else if non_null.iter().all(|v| v.starts_with('"')) {
+            json!({
+                "type": "string",
+                "enum": non_null.iter().map(|v| v.trim_matches('"')).collect::<Vec<_>>(),
+            })
+        } else {
+            json!({ "anyOf": non_null.iter().map(|v| ts_type_to_schema(v)).collect::<Vec<_>>() })
+        };
+        if nullable {
+            schema["nullable"] = json!(true);
+        }
+        return schema;
+    }
+
+    if let Some(body) = ts_type.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for field in split_top_level(body, ',') {
+            let field = field.trim();
+            let Some((name, field_type)) = field.split_once(':') else {
+                continue;
+            };
+            let name = name.trim();
+            let (name, optional) = match name.strip_suffix('?') {
+                Some(n) => (n, true),
+                None => (name, false),
+            };
+            let name = name.trim_matches('"');
+
+            if !optional {
+                required.push(json!(name));
+            }
+            properties.insert(name.to_string(), ts_type_to_schema(field_type));
+        }
+
+        return json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        });
+    }
+
+    if let Some(item) = ts_type.strip_prefix("Array<").and_then(|t| t.strip_suffix('>')) {
+        return json!({ "type": "array", "items": ts_type_to_schema(item) });
+    }
+
+    if let Some(inner) = ts_type.strip_prefix("Record<").and_then(|t| t.strip_suffix('>')) {
+        let value_type = split_top_level(inner, ',').get(1).copied().unwrap_or("unknown");
+        return json!({ "type": "object", "additionalProperties": ts_type_to_schema(value_type) });
+    }
+
+    match ts_type {
+        "string" => json!({ "type": "string" }),
+        "number" => json!({ "type": "number" }),
+        "bigint" => json!({ "type": "integer" }),
+        "boolean" => json!({ "type": "boolean" }),
+        "null" => json!({ "type": "null" }),
+        other if other.starts_with('"') => json!({ "type": "string", "const": other.trim_matches('"') }),
+        other => json!({ "$ref": other }),
+    }
+}
+
+/// Split on `separator` outside of braces, brackets, generics and strings
+fn split_top_level(input: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut start = 0;
+
+    for (i, c) in input.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '{' | '<' | '[' | '(' if !in_string => depth += 1,
+            '}' | '>' | ']' | ')' if !in_string => depth -= 1,
+            c if c == separator && depth == 0 && !in_string => {
+                parts.push(&input[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    let last = &input[start..];
+    if !last.trim().is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_schema_lists_reasoning_fields() {
+        let schema = describe_type_schema("Message".to_string()).unwrap();
+
+        assert_eq!(schema["title"], "Message");
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["reasoning_blocks"]["type"], "array");
+        assert_eq!(schema["properties"]["is_deep_thinking"]["type"], "boolean");
+        assert_eq!(schema["properties"]["reasoning_content"]["nullable"], true);
+        assert!(schema["required"].as_array().unwrap().contains(&json!("is_deep_thinking")));
+    }
+
+    #[test]
+    fn test_unknown_type_is_rejected() {
+        assert!(describe_type_schema("NotAType".to_string()).is_err());
+    }
+}
@@ -4,8 +4,15 @@
 use tauri::State;
 use serde::{Serialize, Deserialize};
 use serde_json::{Value, json};
-use rquickjs::{Context, Ctx, Value as JSValue, Object, Array, Function, Filter};
-use crate::state::{SharedState, Skill, SkillParameter, SkillParameterType};
+use rquickjs::{Context, Ctx, Value as JSValue, Object, Array, Function, Filter, CatchResultExt};
+use crate::state::{SharedState, AppState, Skill, SkillParameter, SkillParameterType, SkillRun, SkillSigner, SecurityFinding, Message, new_message_id};
+use crate::services::persistence::PersistenceService;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use base64::Engine;
 
 /// Skill execution result
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +23,97 @@ pub struct SkillResult {
     pub execution_time_ms: u64,
 }
 
+/// Globals injected into the skill runtime that parameters must not shadow
+const RESERVED_PARAM_NAMES: &[&str] = &["params", "log", "getParam", "fetch"];
+
+/// Severity of a skill lint finding
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    Error,
+    Warning,
+}
+
+/// Skill lint finding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintWarning {
+    pub severity: LintSeverity,
+    pub parameter: String,
+    pub message: String,
+}
+
+/// Persistent JS contexts shared by skill pipeline steps, keyed by session token
+/// Held behind `Arc` because cloning an rquickjs `Context` directly double-frees it on drop
+static SKILL_SESSIONS: OnceLock<Mutex<HashMap<String, Arc<Context>>>> = OnceLock::new();
+
+fn skill_sessions() -> &'static Mutex<HashMap<String, Arc<Context>>> {
+    SKILL_SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Default JS stack limit for skills (QuickJS's own default)
+const DEFAULT_JS_MAX_STACK_SIZE: usize = 256 * 1024;
+
+/// Allowed range for JS stack limits; the upper bound stays well under a 2 MiB thread stack
+const MIN_JS_MAX_STACK_SIZE: usize = 64 * 1024;
+const MAX_JS_MAX_STACK_SIZE: usize = 1024 * 1024;
+
+static JS_MAX_STACK_SIZE: OnceLock<AtomicUsize> = OnceLock::new();
+
+/// Current JS stack limit for skills without their own override
+fn js_max_stack_size() -> usize {
+    JS_MAX_STACK_SIZE
+        .get_or_init(|| AtomicUsize::new(DEFAULT_JS_MAX_STACK_SIZE))
+        .load(Ordering::Relaxed)
+}
+
+fn validate_js_max_stack_size(max_stack_size: usize) -> Result<usize, String> {
+    if !(MIN_JS_MAX_STACK_SIZE..=MAX_JS_MAX_STACK_SIZE).contains(&max_stack_size) {
+        return Err(format!(
+            "Max stack size must be between {} and {} bytes",
+            MIN_JS_MAX_STACK_SIZE, MAX_JS_MAX_STACK_SIZE
+        ));
+    }
+    Ok(max_stack_size)
+}
+
+/// Most skill executions kept in the in-memory log; older entries are dropped first
+const MAX_SKILL_EXECUTIONS: usize = 1000;
+
+/// Most runs kept in a skill's input/output log; older runs are dropped first
+const MAX_SKILL_RUNS: usize = 50;
+
+/// One recorded skill execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillExecution {
+    pub skill_id: String,
+    pub timestamp: u64,
+    pub execution_time_ms: u64,
+    pub success: bool,
+}
+
+/// Execution history for this app session, oldest first
+static SKILL_EXECUTIONS: OnceLock<Mutex<Vec<SkillExecution>>> = OnceLock::new();
+
+fn skill_executions() -> &'static Mutex<Vec<SkillExecution>> {
+    SKILL_EXECUTIONS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Append a finished execution to the log and pass the result through
+fn record_skill_execution(skill_id: &str, result: SkillResult) -> SkillResult {
+    if let Ok(mut executions) = skill_executions().lock() {
+        if executions.len() >= MAX_SKILL_EXECUTIONS {
+            executions.remove(0);
+        }
+        executions.push(SkillExecution {
+            skill_id: skill_id.to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            execution_time_ms: result.execution_time_ms,
+            success: result.success,
+        });
+    }
+    result
+}
+
 /// Category with skill count
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SkillCategory {
@@ -30,6 +128,16 @@ pub async fn execute_skill(
     shared_state: State<'_, SharedState>,
     skill_id: String,
     params: Value,
+    session_token: Option<String>,
+) -> Result<SkillResult, String> {
+    run_skill(&shared_state, &skill_id, params, session_token.as_deref())
+}
+
+fn run_skill(
+    shared_state: &SharedState,
+    skill_id: &str,
+    params: Value,
+    session_token: Option<&str>,
 ) -> Result<SkillResult, String> {
     let start_time = std::time::Instant::now();
 
@@ -39,9 +147,11 @@ pub async fn execute_skill(
 
     let skill = match skill {
         Some(s) => s,
-        None => return Err(format!("Skill '{}' not found", skill_id)),
+        None => return Err(shared_state.read(|state| state.not_found_message("skill", skill_id))),
     };
 
+    let params = apply_parameter_defaults(&skill.parameters, params);
+
     // Validate parameters against skill schema
     let mut errors: Vec<String> = Vec::new();
     for param in &skill.parameters {
@@ -76,40 +186,461 @@ pub async fn execute_skill(
     }
 
     if !errors.is_empty() {
-        return Ok(SkillResult {
+        return Ok(finish_skill_run(shared_state, &skill, params, SkillResult {
             success: false,
             output: Value::Null,
             error: Some(errors.join(", ")),
             execution_time_ms: start_time.elapsed().as_millis() as u64,
-        });
+        }));
     }
 
     // Execute the skill code
-    let execution_result = execute_javascript(&skill.code, &params);
+    let max_stack_size = skill.max_stack_size.unwrap_or_else(js_max_stack_size);
+    let execution_result = execute_javascript_in_session(&skill.code, &params, session_token, max_stack_size);
 
     let execution_time_ms = start_time.elapsed().as_millis() as u64;
 
-    match execution_result {
-        Ok(result) => Ok(SkillResult {
+    let execution_result = execution_result
+        .and_then(|result| check_skill_output(result, skill.output_schema.as_ref()));
+
+    let result = match execution_result {
+        Ok(result) => SkillResult {
             success: true,
             output: result,
             error: None,
             execution_time_ms,
-        }),
-        Err(e) => Ok(SkillResult {
+        },
+        Err(e) => SkillResult {
             success: false,
             output: Value::Null,
             error: Some(e),
             execution_time_ms,
-        }),
+        },
+    };
+    Ok(finish_skill_run(shared_state, &skill, params, result))
+}
+
+/// Run a skill and append its output to a chat session as a message with `role`
+/// String output is used as-is; any other value is pretty-printed JSON
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn execute_skill_to_message(
+    shared_state: State<'_, SharedState>,
+    skill_id: String,
+    params: Value,
+    session_id: String,
+    role: String,
+) -> Result<Message, String> {
+    skill_output_to_message(&shared_state, &skill_id, params, &session_id, role)
+}
+
+fn skill_output_to_message(
+    shared_state: &SharedState,
+    skill_id: &str,
+    params: Value,
+    session_id: &str,
+    role: String,
+) -> Result<Message, String> {
+    if !shared_state.read(|state| state.sessions.contains_key(session_id)) {
+        return Err(format!("Session '{}' not found", session_id));
+    }
+
+    let result = run_skill(shared_state, skill_id, params, None)?;
+    if !result.success {
+        return Err(result.error.unwrap_or_else(|| format!("Skill '{}' failed", skill_id)));
     }
+
+    let content = match result.output {
+        Value::String(text) => text,
+        other => serde_json::to_string_pretty(&other)
+            .map_err(|e| format!("Failed to serialize skill output: {}", e))?,
+    };
+    let message = Message::new(new_message_id(), role, content);
+
+    shared_state.write(|state| {
+        let session = state.sessions.get_mut(session_id)
+            .ok_or_else(|| format!("Session '{}' not found", session_id))?;
+        session.messages.push(message.clone());
+        session.updated_at = chrono::Utc::now().timestamp_millis() as u64;
+        Ok(message)
+    })
 }
 
-/// Execute JavaScript code with given parameters
-fn execute_javascript(code: &str, params: &Value) -> Result<Value, String> {
+/// Parse a parameter's stored default according to its type
+/// Defaults are kept as strings, so a `Number` default of "5" becomes the JSON number 5
+fn typed_default(param: &SkillParameter) -> Result<Option<Value>, String> {
+    let Some(raw) = param.default.as_deref() else {
+        return Ok(None);
+    };
+
+    let value = match param.param_type {
+        SkillParameterType::String => Value::String(raw.to_string()),
+        SkillParameterType::Number => serde_json::from_str::<Value>(raw.trim())
+            .ok()
+            .filter(Value::is_number)
+            .ok_or_else(|| format!("Default '{}' of parameter '{}' is not a number", raw, param.name))?,
+        SkillParameterType::Boolean => match raw.trim().to_lowercase().as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => return Err(format!("Default '{}' of parameter '{}' is not a boolean", raw, param.name)),
+        },
+        SkillParameterType::Array => serde_json::from_str::<Value>(raw)
+            .ok()
+            .filter(Value::is_array)
+            .ok_or_else(|| format!("Default of parameter '{}' is not a JSON array", param.name))?,
+        SkillParameterType::Object => serde_json::from_str::<Value>(raw)
+            .ok()
+            .filter(Value::is_object)
+            .ok_or_else(|| format!("Default of parameter '{}' is not a JSON object", param.name))?,
+    };
+    Ok(Some(value))
+}
+
+/// Fill in typed defaults for parameters missing from `params`
+/// Defaults that don't parse are skipped, leaving the parameter missing
+fn apply_parameter_defaults(parameters: &[SkillParameter], params: Value) -> Value {
+    let mut params = match params {
+        Value::Object(map) => map,
+        Value::Null => serde_json::Map::new(),
+        other => return other,
+    };
+    for param in parameters {
+        if params.contains_key(&param.name) {
+            continue;
+        }
+        if let Ok(Some(value)) = typed_default(param) {
+            params.insert(param.name.clone(), value);
+        }
+    }
+    Value::Object(params)
+}
+
+/// Outcome of normalizing stored parameter defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedSkillDefaults {
+    /// Number of defaults rewritten to their canonical form
+    pub updated: usize,
+    /// Defaults that don't parse as their parameter's type (left unchanged)
+    pub invalid: Vec<String>,
+}
+
+/// Rewrite every skill parameter default in the canonical text of its typed value
+/// e.g. a `Number` default " 5 " becomes "5" and a `Boolean` "TRUE" becomes "true"
+#[tauri::command]
+#[allow(dead_code)]
+pub fn normalize_skill_defaults(
+    shared_state: State<'_, SharedState>,
+) -> Result<NormalizedSkillDefaults, String> {
+    Ok(shared_state.write(normalize_skill_defaults_in_state))
+}
+
+fn normalize_skill_defaults_in_state(state: &mut AppState) -> NormalizedSkillDefaults {
+    let mut report = NormalizedSkillDefaults { updated: 0, invalid: Vec::new() };
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+
+    for skill in &mut state.skills {
+        let mut changed = false;
+        for param in &mut skill.parameters {
+            let canonical = match typed_default(param) {
+                Ok(Some(Value::String(text))) => text,
+                Ok(Some(value)) => value.to_string(),
+                Ok(None) => continue,
+                Err(e) => {
+                    report.invalid.push(format!("{}: {}", skill.name, e));
+                    continue;
+                }
+            };
+            if param.default.as_deref() != Some(canonical.as_str()) {
+                param.default = Some(canonical);
+                report.updated += 1;
+                changed = true;
+            }
+        }
+        if changed {
+            skill.updated_at = now;
+        }
+    }
+    report
+}
+
+/// Record a finished execution in the history, and in the skill's run log when enabled
+fn finish_skill_run(shared_state: &SharedState, skill: &Skill, params: Value, result: SkillResult) -> SkillResult {
+    if skill.log_runs {
+        let run = SkillRun {
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            input: params,
+            output: result.output.clone(),
+            success: result.success,
+            duration_ms: result.execution_time_ms,
+            error: result.error.clone(),
+        };
+        shared_state.write(|state| {
+            let runs = state.skill_runs.entry(skill.id.clone()).or_default();
+            if runs.len() >= MAX_SKILL_RUNS {
+                runs.remove(0);
+            }
+            runs.push(run);
+        });
+    }
+    record_skill_execution(&skill.id, result)
+}
+
+/// Get the JS stack limit in bytes used by skills without their own override
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_js_max_stack_size() -> usize {
+    js_max_stack_size()
+}
+
+/// Set the JS stack limit in bytes used by skills without their own override
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_js_max_stack_size(max_stack_size: usize) -> Result<usize, String> {
+    let max_stack_size = validate_js_max_stack_size(max_stack_size)?;
+    JS_MAX_STACK_SIZE
+        .get_or_init(|| AtomicUsize::new(DEFAULT_JS_MAX_STACK_SIZE))
+        .store(max_stack_size, Ordering::Relaxed);
+    Ok(max_stack_size)
+}
+
+/// Set or clear a skill's JS stack limit override
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_skill_max_stack_size(
+    shared_state: State<'_, SharedState>,
+    skill_id: String,
+    max_stack_size: Option<usize>,
+) -> Result<Skill, String> {
+    let max_stack_size = max_stack_size.map(validate_js_max_stack_size).transpose()?;
+    shared_state.write(|state| {
+        let skill = state.skills.iter_mut()
+            .find(|s| s.id == skill_id)
+            .ok_or_else(|| format!("Skill '{}' not found", skill_id))?;
+        skill.max_stack_size = max_stack_size;
+        skill.updated_at = chrono::Utc::now().timestamp_millis() as u64;
+        Ok(skill.clone())
+    })
+}
+
+/// Enable or disable input/output logging for a skill
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_skill_run_logging(
+    shared_state: State<'_, SharedState>,
+    skill_id: String,
+    enabled: bool,
+) -> Result<Skill, String> {
+    shared_state.write(|state| {
+        let skill = state.skills.iter_mut()
+            .find(|s| s.id == skill_id)
+            .ok_or_else(|| format!("Skill '{}' not found", skill_id))?;
+        skill.log_runs = enabled;
+        Ok(skill.clone())
+    })
+}
+
+/// Get a skill's logged runs, newest first; a `limit` of 0 returns every run
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_skill_runs(
+    shared_state: State<'_, SharedState>,
+    skill_id: String,
+    limit: usize,
+) -> Vec<SkillRun> {
+    shared_state.read(|state| recent_skill_runs(state, &skill_id, limit))
+}
+
+fn recent_skill_runs(state: &AppState, skill_id: &str, limit: usize) -> Vec<SkillRun> {
+    let runs = state.skill_runs.get(skill_id).map(Vec::as_slice).unwrap_or_default();
+    let limit = if limit == 0 { runs.len() } else { limit };
+    runs.iter().rev().take(limit).cloned().collect()
+}
+
+/// Delete a skill's logged runs, returning how many were removed
+#[tauri::command]
+#[allow(dead_code)]
+pub fn clear_skill_runs(
+    shared_state: State<'_, SharedState>,
+    skill_id: String,
+) -> usize {
+    shared_state.write(|state| state.skill_runs.remove(&skill_id).map_or(0, |runs| runs.len()))
+}
+
+/// Export the skill execution log as CSV, optionally for a single skill
+/// Returns the number of rows written
+#[tauri::command]
+#[allow(dead_code)]
+pub fn export_skill_executions_csv(path: String, skill_id: Option<String>) -> Result<usize, String> {
+    let executions = skill_executions().lock()
+        .map_err(|_| "Skill execution log lock poisoned".to_string())?
+        .clone();
+    let (csv, rows) = skill_executions_csv(&executions, skill_id.as_deref());
+    std::fs::write(&path, csv)
+        .map_err(|e| format!("Failed to write CSV file: {}", e))?;
+    Ok(rows)
+}
+
+/// Render executions as CSV with a header row, returning the text and the data row count
+fn skill_executions_csv(executions: &[SkillExecution], skill_id: Option<&str>) -> (String, usize) {
+    let mut csv = String::from("skill_id,timestamp,execution_time_ms,success\n");
+    let mut rows = 0;
+    for execution in executions.iter().filter(|e| skill_id.is_none_or(|id| e.skill_id == id)) {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&execution.skill_id),
+            execution.timestamp,
+            execution.execution_time_ms,
+            execution.success,
+        ));
+        rows += 1;
+    }
+    (csv, rows)
+}
+
+/// Quote a CSV field when it contains a delimiter, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Reject a skill result that does not conform to its declared output schema
+fn check_skill_output(result: Value, schema: Option<&Value>) -> Result<Value, String> {
+    let Some(schema) = schema else {
+        return Ok(result);
+    };
+
+    let mut errors = Vec::new();
+    validate_json_schema(&result, schema, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(format!("Output schema validation failed: {}", errors.join(", ")))
+    }
+}
+
+/// Validate a value against the JSON Schema keywords skills use:
+/// `type`, `enum`, `required`, `properties` and `items`
+fn validate_json_schema(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        let matches = types.is_empty() || types.iter().any(|t| match *t {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.as_f64().is_some_and(|n| n.fract() == 0.0),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        });
+        if !matches {
+            errors.push(format!("{} must be of type {}", path, types.join(" | ")));
+            return;
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            errors.push(format!("{} must be one of {}", path, Value::Array(allowed.clone())));
+        }
+    }
+
+    if let Some(obj) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !obj.contains_key(key) {
+                    errors.push(format!("{} is missing required property '{}'", path, key));
+                }
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (key, property_schema) in properties {
+                if let Some(property) = obj.get(key) {
+                    validate_json_schema(property, property_schema, &format!("{}.{}", path, key), errors);
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_json_schema(item, item_schema, &format!("{}[{}]", path, i), errors);
+        }
+    }
+}
+
+/// Create a fresh JS runtime and context
+fn new_js_context() -> Result<Context, String> {
     let rt = rquickjs::Runtime::new().map_err(|e| format!("Failed to create JS runtime: {}", e))?;
-    let ctx = Context::full(&rt).map_err(|e| format!("Failed to create JS context: {}", e))?;
+    Context::full(&rt).map_err(|e| format!("Failed to create JS context: {}", e))
+}
+
+/// Create and run a throwaway JS context so one-time runtime init happens before the first skill
+/// Returns the warmup time in milliseconds; fails if the runtime evaluates incorrectly
+#[tauri::command]
+#[allow(dead_code)]
+pub fn warmup_skill_runtime() -> Result<u64, String> {
+    let start_time = std::time::Instant::now();
+    let result = execute_javascript("({ ok: params.a + 1 })", &json!({ "a": 1 }))?;
+    if result != json!({ "ok": 2 }) {
+        return Err(format!("JS runtime warmup returned unexpected result: {}", result));
+    }
+    Ok(start_time.elapsed().as_millis() as u64)
+}
+
+/// Execute JavaScript code with given parameters in a fully isolated context
+fn execute_javascript(code: &str, params: &Value) -> Result<Value, String> {
+    run_javascript(&new_js_context()?, code, params, js_max_stack_size())
+}
+
+/// Execute JavaScript code, reusing the session's context so pipeline steps share `globalThis`
+/// Without a token this runs in a fresh context, like `execute_javascript`
+fn execute_javascript_in_session(
+    code: &str,
+    params: &Value,
+    session_token: Option<&str>,
+    max_stack_size: usize,
+) -> Result<Value, String> {
+    let Some(token) = session_token else {
+        return run_javascript(&new_js_context()?, code, params, max_stack_size);
+    };
+
+    let ctx = {
+        let mut sessions = skill_sessions().lock()
+            .map_err(|_| "Skill session lock poisoned".to_string())?;
+        match sessions.get(token) {
+            Some(ctx) => ctx.clone(),
+            None => {
+                let ctx = Arc::new(new_js_context()?);
+                sessions.insert(token.to_string(), ctx.clone());
+                ctx
+            }
+        }
+    };
+
+    run_javascript(&ctx, code, params, max_stack_size)
+}
 
+/// End a skill pipeline session, discarding its shared context
+#[tauri::command]
+#[allow(dead_code)]
+pub fn end_skill_session(session_token: String) -> Result<bool, String> {
+    let mut sessions = skill_sessions().lock()
+        .map_err(|_| "Skill session lock poisoned".to_string())?;
+    Ok(sessions.remove(&session_token).is_some())
+}
+
+/// Run code in an existing context with `params` and the helper functions installed
+/// The stack limit is applied per run, so a shared session context follows each skill's limit
+fn run_javascript(ctx: &Context, code: &str, params: &Value, max_stack_size: usize) -> Result<Value, String> {
+    ctx.runtime().set_max_stack_size(max_stack_size);
     ctx.with(|ctx| {
         // Convert params to JS object (clone ctx for the conversion)
         let params_obj = convert_json_to_js(ctx.clone(), params)
@@ -125,7 +656,8 @@ fn execute_javascript(code: &str, params: &Value) -> Result<Value, String> {
 
         // Execute the code
         let result: JSValue = ctx.eval(code)
-            .map_err(|e| format!("Execution error: {}", e))?;
+            .catch(&ctx)
+            .map_err(|e| js_execution_error(&e.to_string(), max_stack_size))?;
 
         // Convert result back to JSON
         let json_result = convert_js_to_json(ctx, result)
@@ -135,6 +667,15 @@ fn execute_javascript(code: &str, params: &Value) -> Result<Value, String> {
     })
 }
 
+/// Describe a thrown exception, calling out stack exhaustion explicitly
+fn js_execution_error(message: &str, max_stack_size: usize) -> String {
+    if message.contains("stack overflow") {
+        format!("Stack overflow: skill exceeded the {} KiB JS stack limit", max_stack_size / 1024)
+    } else {
+        format!("Execution error: {}", message)
+    }
+}
+
 /// Convert JSON value to rquickjs value
 fn convert_json_to_js<'js>(ctx: Ctx<'js>, value: &Value) -> Result<JSValue<'js>, String> {
     match value {
@@ -149,7 +690,9 @@ fn convert_json_to_js<'js>(ctx: Ctx<'js>, value: &Value) -> Result<JSValue<'js>,
                 Ok(JSValue::new_null(ctx))
             }
         }
-        Value::String(s) => ctx.eval(s.as_str()).map_err(|e| format!("{}", e)),
+        Value::String(s) => rquickjs::String::from_str(ctx, s)
+            .map(Into::into)
+            .map_err(|e| format!("{}", e)),
         Value::Array(arr) => {
             let js_arr = Array::new(ctx.clone()).map_err(|e| format!("{}", e))?;
 
@@ -171,8 +714,18 @@ fn convert_json_to_js<'js>(ctx: Ctx<'js>, value: &Value) -> Result<JSValue<'js>,
     }
 }
 
+/// Marker substituted for a reference back to an object being converted
+const CIRCULAR_MARKER: &str = "[Circular]";
+
 /// Convert rquickjs value to JSON value
 fn convert_js_to_json<'js>(ctx: Ctx<'js>, value: JSValue<'js>) -> Result<Value, String> {
+    convert_js_to_json_inner(ctx, value, &mut Vec::new())
+}
+
+/// `ancestors` holds the arrays/objects on the current path; rquickjs compares
+/// object values by identity, so a repeat means a cycle. Shared but acyclic
+/// references are converted at each occurrence.
+fn convert_js_to_json_inner<'js>(ctx: Ctx<'js>, value: JSValue<'js>, ancestors: &mut Vec<JSValue<'js>>) -> Result<Value, String> {
     if value.is_null() || value.is_undefined() {
         return Ok(Value::Null);
     }
@@ -197,26 +750,33 @@ fn convert_js_to_json<'js>(ctx: Ctx<'js>, value: JSValue<'js>) -> Result<Value,
         }
         return Ok(json!(""));
     }
+    if (value.is_array() || value.is_object()) && ancestors.contains(&value) {
+        return Ok(json!(CIRCULAR_MARKER));
+    }
     if value.is_array() {
+        ancestors.push(value.clone());
         let arr = value.into_array().unwrap();
         let len = arr.len();
         let mut result = Vec::new();
 
         for i in 0..len {
             let item: JSValue = arr.get(i).map_err(|e| format!("{}", e))?;
-            result.push(convert_js_to_json(ctx.clone(), item)?);
+            result.push(convert_js_to_json_inner(ctx.clone(), item, ancestors)?);
         }
+        ancestors.pop();
         return Ok(json!(result));
     }
     if value.is_object() {
+        ancestors.push(value.clone());
         let obj = value.into_object().unwrap();
         let mut obj_map = serde_json::Map::new();
 
         for key_result in obj.own_keys::<String>(Filter::new().string().symbol()) {
             let prop = key_result.map_err(|e| format!("{}", e))?;
             let val: JSValue = obj.get(&prop).map_err(|e| format!("{}", e))?;
-            obj_map.insert(prop, convert_js_to_json(ctx.clone(), val)?);
+            obj_map.insert(prop, convert_js_to_json_inner(ctx.clone(), val, ancestors)?);
         }
+        ancestors.pop();
         return Ok(Value::Object(obj_map));
     }
 
@@ -258,10 +818,241 @@ fn add_helper_functions(globals: &Object) -> Result<(), String> {
         getParam;
     "#).map_err(|e| format!("Failed to create getParam function: {}", e))?;
 
-    globals.set("getParam", get_param_func)
-        .map_err(|e| format!("Failed to set getParam: {}", e))?;
+    globals.set("getParam", get_param_func)
+        .map_err(|e| format!("Failed to set getParam: {}", e))?;
+
+    Ok(())
+}
+
+/// Indentation unit used when normalizing skill code
+const SKILL_INDENT: &str = "    ";
+
+/// Formatted skill source returned to the editor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormattedSkillCode {
+    pub code: String,
+    pub changed: bool,
+    pub warning: Option<String>,
+}
+
+/// Lexical state while scanning JavaScript source line by line
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JsScanState {
+    Code,
+    SingleQuote,
+    DoubleQuote,
+    Template,
+    LineComment,
+    BlockComment,
+}
+
+/// Check that code parses as a function body without executing it
+fn check_javascript_syntax(code: &str) -> Result<(), String> {
+    let rt = rquickjs::Runtime::new().map_err(|e| format!("Failed to create JS runtime: {}", e))?;
+    let ctx = Context::full(&rt).map_err(|e| format!("Failed to create JS context: {}", e))?;
+
+    ctx.with(|ctx| {
+        ctx.globals().set("__skill_source", code)
+            .map_err(|e| format!("Failed to set source: {}", e))?;
+
+        ctx.eval::<JSValue, _>("new Function(__skill_source)")
+            .catch(&ctx)
+            .map(|_| ())
+            .map_err(|e| format!("Syntax error: {}", e))
+    })
+}
+
+/// Scan one line, updating the lexical state and bracket depth
+fn scan_js_line(line: &str, state: &mut JsScanState, depth: &mut usize) {
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match *state {
+            JsScanState::Code => match c {
+                '\'' => *state = JsScanState::SingleQuote,
+                '"' => *state = JsScanState::DoubleQuote,
+                '`' => *state = JsScanState::Template,
+                '/' if chars.peek() == Some(&'/') => *state = JsScanState::LineComment,
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    *state = JsScanState::BlockComment;
+                }
+                '{' | '[' | '(' => *depth += 1,
+                '}' | ']' | ')' => *depth = depth.saturating_sub(1),
+                _ => {}
+            },
+            JsScanState::SingleQuote | JsScanState::DoubleQuote | JsScanState::Template => {
+                let quote = match *state {
+                    JsScanState::SingleQuote => '\'',
+                    JsScanState::DoubleQuote => '"',
+                    _ => '`',
+                };
+                if c == '\\' {
+                    chars.next();
+                } else if c == quote {
+                    *state = JsScanState::Code;
+                }
+            }
+            JsScanState::LineComment => {}
+            JsScanState::BlockComment => {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    *state = JsScanState::Code;
+                }
+            }
+        }
+    }
+
+    // Only template literals and block comments span lines
+    if matches!(*state, JsScanState::SingleQuote | JsScanState::DoubleQuote | JsScanState::LineComment) {
+        *state = JsScanState::Code;
+    }
+}
+
+/// Normalize JavaScript source: LF line endings, no trailing whitespace,
+/// bracket-based indentation and at most one consecutive blank line
+fn normalize_js_source(code: &str) -> String {
+    let source = code.replace("\r\n", "\n").replace('\r', "\n");
+    let mut lines: Vec<String> = Vec::new();
+    let mut state = JsScanState::Code;
+    let mut depth = 0usize;
+
+    for raw_line in source.split('\n') {
+        // Lines continuing a template literal or block comment are kept verbatim
+        if state != JsScanState::Code {
+            lines.push(raw_line.to_string());
+            scan_js_line(raw_line, &mut state, &mut depth);
+            continue;
+        }
+
+        let trimmed = raw_line.trim();
+        if trimmed.is_empty() {
+            if lines.last().is_some_and(|l| !l.is_empty()) {
+                lines.push(String::new());
+            }
+            continue;
+        }
+
+        let leading_closers = trimmed.chars()
+            .take_while(|c| matches!(c, '}' | ']' | ')'))
+            .count();
+        let indent = depth.saturating_sub(leading_closers);
+        lines.push(format!("{}{}", SKILL_INDENT.repeat(indent), trimmed));
+        scan_js_line(trimmed, &mut state, &mut depth);
+    }
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+/// Format skill code before saving; returns the original with a warning if it does not parse
+#[tauri::command]
+#[allow(dead_code)]
+pub fn format_skill_code(code: String) -> FormattedSkillCode {
+    if let Err(e) = check_javascript_syntax(&code) {
+        return FormattedSkillCode {
+            code,
+            changed: false,
+            warning: Some(format!("Code was not formatted: {}", e)),
+        };
+    }
+
+    let formatted = normalize_js_source(&code);
+    let changed = formatted != code;
+
+    FormattedSkillCode {
+        code: formatted,
+        changed,
+        warning: None,
+    }
+}
+
+/// Lint a skill's parameter schema for duplicate names and reserved-name collisions
+#[tauri::command]
+#[allow(dead_code)]
+pub fn lint_skill(skill: Skill) -> Vec<LintWarning> {
+    lint_skill_parameters(&skill.parameters)
+}
+
+fn lint_skill_parameters(parameters: &[SkillParameter]) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+
+    for param in parameters {
+        if !seen.insert(param.name.as_str()) {
+            warnings.push(LintWarning {
+                severity: LintSeverity::Error,
+                parameter: param.name.clone(),
+                message: format!("Duplicate parameter name '{}'", param.name),
+            });
+        }
+        if RESERVED_PARAM_NAMES.contains(&param.name.as_str()) {
+            warnings.push(LintWarning {
+                severity: LintSeverity::Warning,
+                parameter: param.name.clone(),
+                message: format!("Parameter '{}' shadows a skill runtime global", param.name),
+            });
+        }
+    }
+
+    warnings
+}
+
+/// Risky code patterns as (pattern name, regex, message); a leading `(?:^|[^\w.$])`
+/// keeps property accesses such as `params.process` from matching
+const SKILL_SECURITY_RULES: &[(&str, &str, &str)] = &[
+    ("require", r"(?:^|[^\w.$])require\s*\(", "Loads a module with require()"),
+    ("import", r"(?:^|[^\w.$])import\s*\(|^\s*import\b", "Imports a module"),
+    ("process", r"(?:^|[^\w.$])process\s*[.\[]", "Accesses the Node.js process object"),
+    ("eval", r"(?:^|[^\w.$])eval\s*\(", "Evaluates dynamically built code"),
+    ("Function", r"(?:^|[^\w.$])new\s+Function\s*\(", "Builds a function from a string"),
+    ("infinite-loop", r"(?:^|[^\w.$])(?:while\s*\(\s*(?:true|1)\s*\)|for\s*\(\s*;\s*;\s*\))", "Loop has no exit condition and may never finish"),
+];
+
+static SKILL_SECURITY_PATTERNS: Lazy<Vec<(&str, Regex, &str)>> = Lazy::new(|| {
+    SKILL_SECURITY_RULES.iter()
+        .map(|(pattern, regex, message)| (*pattern, Regex::new(regex).expect("invalid skill security rule"), *message))
+        .collect()
+});
+
+/// Scan skill code for obviously dangerous patterns
+/// This is a lint to warn about risky shared skills, not a sandbox
+#[tauri::command]
+#[allow(dead_code)]
+pub fn scan_skill_security(code: String) -> Vec<SecurityFinding> {
+    scan_skill_code(&code)
+}
+
+fn scan_skill_code(code: &str) -> Vec<SecurityFinding> {
+    code.lines()
+        .enumerate()
+        .flat_map(|(index, line)| {
+            SKILL_SECURITY_PATTERNS.iter()
+                .filter(|(_, regex, _)| regex.is_match(line))
+                .map(move |(pattern, _, message)| SecurityFinding {
+                    line: index + 1,
+                    pattern: pattern.to_string(),
+                    message: message.to_string(),
+                })
+        })
+        .collect()
+}
 
-    Ok(())
+/// Reject parameter schemas with lint errors (warnings are allowed)
+fn validate_skill_parameters(parameters: &[SkillParameter]) -> Result<(), String> {
+    let errors: Vec<String> = lint_skill_parameters(parameters).into_iter()
+        .filter(|w| w.severity == LintSeverity::Error)
+        .map(|w| w.message)
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid skill parameters: {}", errors.join("; ")))
+    }
 }
 
 // ============================================
@@ -294,7 +1085,7 @@ pub fn get_skill(
     shared_state.read(|state| {
         match state.skills.iter().find(|s| s.id == skill_id) {
             Some(skill) => Ok(skill.clone()),
-            None => Err(format!("Skill '{}' not found", skill_id)),
+            None => Err(state.not_found_message("skill", &skill_id)),
         }
     })
 }
@@ -309,7 +1100,10 @@ pub fn create_skill(
     category: String,
     parameters: Vec<SkillParameter>,
     code: String,
+    output_schema: Option<Value>,
 ) -> Result<Skill, String> {
+    validate_skill_parameters(&parameters)?;
+
     let skill_id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().timestamp_millis() as u64;
 
@@ -319,10 +1113,15 @@ pub fn create_skill(
         description,
         category,
         parameters,
+        security_findings: scan_skill_code(&code),
         code,
         enabled: true,
         created_at: now,
         updated_at: now,
+        output_schema,
+        signed_by: None,
+        log_runs: false,
+        max_stack_size: None,
     };
 
     shared_state.write(|state| {
@@ -345,7 +1144,12 @@ pub fn update_skill(
     parameters: Option<Vec<SkillParameter>>,
     code: Option<String>,
     enabled: Option<bool>,
+    output_schema: Option<Value>,
 ) -> Result<Skill, String> {
+    if let Some(p) = &parameters {
+        validate_skill_parameters(p)?;
+    }
+
     let mut updated = None;
 
     shared_state.write(|state| {
@@ -354,8 +1158,13 @@ pub fn update_skill(
             if let Some(d) = description { skill.description = d; }
             if let Some(c) = category { skill.category = c; }
             if let Some(p) = parameters { skill.parameters = p; }
-            if let Some(c) = code { skill.code = c; }
+            if let Some(c) = code {
+                skill.security_findings = scan_skill_code(&c);
+                skill.code = c;
+            }
             if let Some(e) = enabled { skill.enabled = e; }
+            // An explicit JSON null removes the output schema
+            if let Some(s) = output_schema { skill.output_schema = Some(s).filter(|s| !s.is_null()); }
             skill.updated_at = chrono::Utc::now().timestamp_millis() as u64;
             updated = Some(skill.clone());
         }
@@ -363,7 +1172,7 @@ pub fn update_skill(
 
     match updated {
         Some(s) => Ok(s),
-        None => Err(format!("Skill '{}' not found", skill_id)),
+        None => Err(shared_state.read(|state| state.not_found_message("skill", &skill_id))),
     }
 }
 
@@ -380,12 +1189,13 @@ pub fn delete_skill(
         let initial_len = state.skills.len();
         state.skills.retain(|s| s.id != skill_id);
         removed = state.skills.len() < initial_len;
+        state.skill_runs.remove(&skill_id);
     });
 
     if removed {
         Ok(true)
     } else {
-        Err(format!("Skill '{}' not found", skill_id))
+        Err(shared_state.read(|state| state.not_found_message("skill", &skill_id)))
     }
 }
 
@@ -430,7 +1240,7 @@ pub fn toggle_skill(
     if toggled {
         Ok(true)
     } else {
-        Err(format!("Skill '{}' not found", skill_id))
+        Err(shared_state.read(|state| state.not_found_message("skill", &skill_id)))
     }
 }
 
@@ -441,24 +1251,181 @@ pub fn import_skill(
     shared_state: State<'_, SharedState>,
     skill_json: Value,
 ) -> Result<Skill, String> {
+    shared_state.write(|state| import_skill_into_state(state, skill_json))
+}
+
+fn import_skill_into_state(state: &mut AppState, skill_json: Value) -> Result<Skill, String> {
     let mut skill: Skill = serde_json::from_value(skill_json)
         .map_err(|e| format!("Invalid skill JSON: {}", e))?;
+    validate_skill_parameters(&skill.parameters)?;
 
-    if skill.id.is_empty() || shared_state.read(|state| state.skills.iter().any(|s| s.id == skill.id)) {
+    if skill.id.is_empty() || state.skills.iter().any(|s| s.id == skill.id) {
         skill.id = uuid::Uuid::new_v4().to_string();
     }
 
     let now = chrono::Utc::now().timestamp_millis() as u64;
     skill.created_at = now;
     skill.updated_at = now;
+    skill.category = normalize_category(&skill.category, &state.skills);
+    skill.security_findings = scan_skill_code(&skill.code);
 
-    shared_state.write(|state| {
-        state.skills.push(skill.clone());
-    });
-
+    state.skills.push(skill.clone());
     Ok(skill)
 }
 
+/// Trim a category and reuse the spelling of an existing category that matches case-insensitively
+fn normalize_category(category: &str, skills: &[Skill]) -> String {
+    let category = category.trim();
+    skills.iter()
+        .map(|s| s.category.as_str())
+        .find(|existing| existing.to_lowercase() == category.to_lowercase())
+        .unwrap_or(category)
+        .to_string()
+}
+
+/// Merge categories that differ only in case or surrounding whitespace
+/// Returns the number of skills whose category was changed
+#[tauri::command]
+#[allow(dead_code)]
+pub fn consolidate_categories(shared_state: State<'_, SharedState>) -> Result<usize, String> {
+    Ok(shared_state.write(|state| consolidate_skill_categories(&mut state.skills)))
+}
+
+/// Retag every skill with its category's canonical form: the most used spelling,
+/// ties going to the one seen first
+fn consolidate_skill_categories(skills: &mut [Skill]) -> usize {
+    let mut spellings: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+    for skill in skills.iter() {
+        let category = skill.category.trim();
+        let counts = spellings.entry(category.to_lowercase()).or_default();
+        match counts.iter_mut().find(|(spelling, _)| spelling == category) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((category.to_string(), 1)),
+        }
+    }
+
+    let canonical: HashMap<String, String> = spellings.into_iter()
+        .map(|(key, counts)| {
+            let best = counts.iter()
+                .enumerate()
+                .max_by_key(|(index, (_, count))| (*count, std::cmp::Reverse(*index)))
+                .map(|(_, (spelling, _))| spelling.clone())
+                .unwrap_or_default();
+            (key, best)
+        })
+        .collect();
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let mut retagged = 0;
+    for skill in skills.iter_mut() {
+        let target = &canonical[&skill.category.trim().to_lowercase()];
+        if &skill.category != target {
+            skill.category = target.clone();
+            skill.updated_at = now;
+            retagged += 1;
+        }
+    }
+    retagged
+}
+
+/// Skills sharing the same code and parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateSkillGroup {
+    pub content_hash: String,
+    /// Oldest first
+    pub skill_ids: Vec<String>,
+}
+
+/// Find skills with identical code and parameters, e.g. the same skill imported twice
+#[tauri::command]
+#[allow(dead_code)]
+pub fn find_duplicate_skills(shared_state: State<'_, SharedState>) -> Vec<DuplicateSkillGroup> {
+    shared_state.read(|state| duplicate_skill_groups(&state.skills))
+}
+
+/// Hash of a skill's code and parameters; name, category and metadata are ignored
+fn skill_content_hash(skill: &Skill) -> String {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    skill.code.hash(&mut hasher);
+    serde_json::to_string(&skill.parameters).unwrap_or_default().hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn duplicate_skill_groups(skills: &[Skill]) -> Vec<DuplicateSkillGroup> {
+    let mut groups: Vec<(String, Vec<&Skill>)> = Vec::new();
+    for skill in skills {
+        let hash = skill_content_hash(skill);
+        match groups.iter_mut().find(|(h, _)| *h == hash) {
+            Some((_, members)) => members.push(skill),
+            None => groups.push((hash, vec![skill])),
+        }
+    }
+
+    groups.into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(content_hash, mut members)| {
+            members.sort_by_key(|s| s.created_at);
+            DuplicateSkillGroup {
+                content_hash,
+                skill_ids: members.into_iter().map(|s| s.id.clone()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Merge duplicates into `keep_id` and delete them
+/// Every removed skill must have the same content as the kept one; their run logs are
+/// folded into the kept skill, which stays enabled if any of them was
+#[tauri::command]
+#[allow(dead_code)]
+pub fn merge_duplicate_skills(
+    shared_state: State<'_, SharedState>,
+    keep_id: String,
+    remove_ids: Vec<String>,
+) -> Result<Skill, String> {
+    shared_state.write(|state| merge_duplicate_skills_in_state(state, &keep_id, &remove_ids))
+}
+
+fn merge_duplicate_skills_in_state(state: &mut AppState, keep_id: &str, remove_ids: &[String]) -> Result<Skill, String> {
+    let kept = state.skills.iter()
+        .find(|s| s.id == keep_id)
+        .ok_or_else(|| state.not_found_message("skill", keep_id))?;
+    let hash = skill_content_hash(kept);
+
+    let mut removed = Vec::new();
+    for id in remove_ids.iter().filter(|id| id.as_str() != keep_id) {
+        let skill = state.skills.iter()
+            .find(|s| &s.id == id)
+            .ok_or_else(|| state.not_found_message("skill", id))?;
+        if skill_content_hash(skill) != hash {
+            return Err(format!("Skill '{}' differs from '{}' and cannot be merged", skill.name, kept.name));
+        }
+        removed.push(skill.clone());
+    }
+
+    let mut runs: Vec<SkillRun> = state.skill_runs.remove(keep_id).unwrap_or_default();
+    for skill in &removed {
+        runs.extend(state.skill_runs.remove(&skill.id).unwrap_or_default());
+    }
+    runs.sort_by_key(|run| run.timestamp);
+    let excess = runs.len().saturating_sub(MAX_SKILL_RUNS);
+    runs.drain(..excess);
+    if !runs.is_empty() {
+        state.skill_runs.insert(keep_id.to_string(), runs);
+    }
+
+    state.skills.retain(|s| !removed.iter().any(|r| r.id == s.id));
+    let kept = state.skills.iter_mut()
+        .find(|s| s.id == keep_id)
+        .ok_or_else(|| format!("Skill '{}' not found", keep_id))?;
+    kept.enabled |= removed.iter().any(|s| s.enabled);
+    kept.log_runs |= removed.iter().any(|s| s.log_runs);
+    kept.updated_at = chrono::Utc::now().timestamp_millis() as u64;
+    Ok(kept.clone())
+}
+
 /// Export skill to JSON
 #[tauri::command]
 #[allow(dead_code)]
@@ -469,7 +1436,7 @@ pub fn export_skill(
     let skill = shared_state.read(|state| {
         match state.skills.iter().find(|s| s.id == skill_id) {
             Some(s) => Ok(s.clone()),
-            None => Err(format!("Skill '{}' not found", skill_id)),
+            None => Err(state.not_found_message("skill", &skill_id)),
         }
     })?;
 
@@ -530,12 +1497,63 @@ pub struct SkillStats {
     pub avg_execution_time_ms: f64,
 }
 
+/// Number of recorded executions of a skill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillUsage {
+    pub skill_id: String,
+    pub name: String,
+    pub count: usize,
+}
+
+/// Get the most executed skills, most executions first
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_top_skills(
+    shared_state: State<'_, SharedState>,
+    limit: usize,
+) -> Vec<SkillUsage> {
+    let executions = skill_executions().lock()
+        .map(|executions| executions.clone())
+        .unwrap_or_default();
+    shared_state.read(|state| rank_skills_by_usage(&state.skills, &executions, limit))
+}
+
+/// Skills with recorded executions ranked by count, ties by name
+fn rank_skills_by_usage(skills: &[Skill], executions: &[SkillExecution], limit: usize) -> Vec<SkillUsage> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for execution in executions {
+        *counts.entry(execution.skill_id.as_str()).or_insert(0) += 1;
+    }
+
+    let mut ranked: Vec<SkillUsage> = skills.iter()
+        .filter_map(|s| {
+            counts.get(s.id.as_str()).map(|&count| SkillUsage {
+                skill_id: s.id.clone(),
+                name: s.name.clone(),
+                count,
+            })
+        })
+        .collect();
+    ranked.sort_by_key(|s| (std::cmp::Reverse(s.count), s.name.clone()));
+    ranked.truncate(limit);
+    ranked
+}
+
 /// Get skill statistics
 #[tauri::command]
 #[allow(dead_code)]
 pub fn get_skill_stats(
     shared_state: State<'_, SharedState>,
 ) -> SkillStats {
+    let (total_executions, total_time_ms) = skill_executions().lock()
+        .map(|executions| (executions.len(), executions.iter().map(|e| e.execution_time_ms).sum::<u64>()))
+        .unwrap_or((0, 0));
+    let avg_execution_time_ms = if total_executions == 0 {
+        0.0
+    } else {
+        total_time_ms as f64 / total_executions as f64
+    };
+
     shared_state.read(|state| {
         let total_skills = state.skills.len();
         let enabled_skills = state.skills.iter().filter(|s| s.enabled).count();
@@ -556,8 +1574,8 @@ pub fn get_skill_stats(
             enabled_skills,
             disabled_skills,
             categories,
-            total_executions: 0, // TODO: Track executions in state
-            avg_execution_time_ms: 0.0, // TODO: Track execution times
+            total_executions,
+            avg_execution_time_ms,
         }
     })
 }
@@ -621,9 +1639,11 @@ pub async fn install_skill_from_zip(
         let now = chrono::Utc::now().timestamp_millis() as u64;
         skill.created_at = now;
         skill.updated_at = now;
+        skill.security_findings = scan_skill_code(&skill.code);
         
         // Add skill
         shared_state.write(|state| {
+            skill.category = normalize_category(&skill.category, &state.skills);
             state.skills.push(skill.clone());
         });
         
@@ -633,6 +1653,151 @@ pub async fn install_skill_from_zip(
     Ok(installed_skills)
 }
 
+/// Get the trusted signer for skill imports
+#[tauri::command]
+#[allow(dead_code)]
+pub fn get_skill_signer(shared_state: State<'_, SharedState>) -> Option<SkillSigner> {
+    shared_state.read(|state| state.config.skill_signer.clone())
+}
+
+/// Set or clear the trusted signer for skill imports and save it with the config
+#[tauri::command]
+#[allow(dead_code)]
+pub fn set_skill_signer(
+    shared_state: State<'_, SharedState>,
+    persistence: State<'_, PersistenceService>,
+    signer: Option<SkillSigner>,
+) -> Result<(), String> {
+    set_skill_signer_in_state(&shared_state, signer)?;
+    persistence.persist_settings()
+}
+
+fn set_skill_signer_in_state(shared_state: &SharedState, signer: Option<SkillSigner>) -> Result<(), String> {
+    if let Some(signer) = &signer {
+        let key = base64::engine::general_purpose::STANDARD.decode(&signer.public_key)
+            .map_err(|e| format!("Invalid public key encoding: {}", e))?;
+        if key.len() != 32 {
+            return Err("Ed25519 public key must be 32 bytes".to_string());
+        }
+    }
+
+    shared_state.write(|state| state.config.skill_signer = signer);
+    Ok(())
+}
+
+/// Verify a base64 Ed25519 signature over the raw skill payload
+/// Returns the signer identity when verified, None when unsigned and not required
+fn verify_skill_signature(
+    payload: &[u8],
+    signature: Option<&str>,
+    signer: Option<&SkillSigner>,
+    require_signature: bool,
+) -> Result<Option<String>, String> {
+    let signature = match signature.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(signature) => signature,
+        None if require_signature => return Err("Skill is unsigned but a signature is required".to_string()),
+        None => return Ok(None),
+    };
+
+    let Some(signer) = signer else {
+        return if require_signature {
+            Err("No trusted skill signer is configured".to_string())
+        } else {
+            Ok(None)
+        };
+    };
+
+    let engine = base64::engine::general_purpose::STANDARD;
+    let public_key = engine.decode(&signer.public_key)
+        .map_err(|e| format!("Invalid public key encoding: {}", e))?;
+    let signature = engine.decode(signature)
+        .map_err(|e| format!("Invalid signature encoding: {}", e))?;
+
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key)
+        .verify(payload, &signature)
+        .map_err(|_| "Skill signature verification failed".to_string())?;
+
+    Ok(Some(signer.identity.clone()))
+}
+
+/// Import a skill from a URL (e.g. a raw gist), verifying its Ed25519 signature
+#[tauri::command]
+#[allow(dead_code)]
+pub async fn import_signed_skill(
+    shared_state: State<'_, SharedState>,
+    url: String,
+    signature: Option<String>,
+    require_signature: bool,
+) -> Result<Skill, String> {
+    let response = crate::commands::config::http_client()
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch skill: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch skill: HTTP {}", response.status()));
+    }
+    let max_bytes = crate::commands::config::max_result_bytes();
+    let body = read_body_capped(response, max_bytes).await?;
+    if body.truncated {
+        return Err(format!("Skill payload exceeds the {} byte limit", max_bytes));
+    }
+    let payload = body.bytes;
+
+    let signer = shared_state.read(|state| state.config.skill_signer.clone());
+    let signed_by = verify_skill_signature(&payload, signature.as_deref(), signer.as_ref(), require_signature)?;
+
+    let skill_json: Value = serde_json::from_slice(&payload)
+        .map_err(|e| format!("Invalid skill JSON: {}", e))?;
+    let mut skill = import_skill(shared_state.clone(), skill_json)?;
+
+    skill.signed_by = signed_by;
+    shared_state.write(|state| {
+        if let Some(stored) = state.skills.iter_mut().find(|s| s.id == skill.id) {
+            stored.signed_by = skill.signed_by.clone();
+        }
+    });
+
+    Ok(skill)
+}
+
+/// HTTP response body read up to the max-bytes cap
+#[derive(Debug, Clone)]
+pub struct CappedBody {
+    pub bytes: Vec<u8>,
+    pub truncated: bool,
+}
+
+impl CappedBody {
+    /// Body as text, with the truncation marker appended when it was cut off
+    pub fn text(&self) -> String {
+        let text = String::from_utf8_lossy(&self.bytes);
+        if self.truncated {
+            crate::commands::config::truncate_with_marker(&text, text.len())
+        } else {
+            text.into_owned()
+        }
+    }
+}
+
+/// Read a response body chunk by chunk, stopping once `max_bytes` is reached
+/// so an oversized body is never held in memory whole
+pub async fn read_body_capped(mut response: reqwest::Response, max_bytes: usize) -> Result<CappedBody, String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk()
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?
+    {
+        let remaining = max_bytes - bytes.len();
+        if chunk.len() > remaining {
+            bytes.extend_from_slice(&chunk[..remaining]);
+            return Ok(CappedBody { bytes, truncated: true });
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(CappedBody { bytes, truncated: false })
+}
+
 /// Reindex all skills (refresh categories and metadata)
 #[tauri::command]
 #[allow(dead_code)]
@@ -653,3 +1818,550 @@ pub fn reindex_skills(
     
     Ok(count)
 }
+
+/// Compute a 0-1 similarity score between two skills' code
+/// Uses token-based Jaccard similarity to surface near-duplicate skills
+#[tauri::command]
+#[allow(dead_code)]
+pub fn skill_code_similarity(
+    shared_state: State<'_, SharedState>,
+    skill_id_a: String,
+    skill_id_b: String,
+) -> Result<f64, String> {
+    let (code_a, code_b) = shared_state.read(|state| {
+        let find = |id: &str| {
+            state.skills.iter()
+                .find(|s| s.id == id)
+                .map(|s| s.code.clone())
+                .ok_or_else(|| state.not_found_message("skill", id))
+        };
+        Ok::<_, String>((find(&skill_id_a)?, find(&skill_id_b)?))
+    })?;
+
+    Ok(code_similarity(&code_a, &code_b))
+}
+
+/// Split code into identifier, number and punctuation tokens
+fn code_tokens(code: &str) -> std::collections::HashSet<String> {
+    let mut tokens = std::collections::HashSet::new();
+    let mut current = String::new();
+
+    for c in code.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '$' {
+            current.push(c);
+            continue;
+        }
+        if !current.is_empty() {
+            tokens.insert(std::mem::take(&mut current));
+        }
+        if !c.is_whitespace() {
+            tokens.insert(c.to_string());
+        }
+    }
+    if !current.is_empty() {
+        tokens.insert(current);
+    }
+
+    tokens
+}
+
+/// Jaccard similarity of the token sets of two code snippets
+fn code_similarity(a: &str, b: &str) -> f64 {
+    let tokens_a = code_tokens(a);
+    let tokens_b = code_tokens(b);
+
+    if tokens_a.is_empty() && tokens_b.is_empty() {
+        return 1.0;
+    }
+
+    let intersection = tokens_a.intersection(&tokens_b).count();
+    let union = tokens_a.union(&tokens_b).count();
+    intersection as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::mock_http::{MockHttpServer, MockResponse};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use crate::state::ChatSession;
+
+    fn test_signer() -> (Ed25519KeyPair, SkillSigner) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let signer = SkillSigner {
+            identity: "pixel-team".to_string(),
+            public_key: base64::engine::general_purpose::STANDARD.encode(key_pair.public_key().as_ref()),
+        };
+        (key_pair, signer)
+    }
+
+    #[test]
+    fn test_skill_sessions_share_globals_per_token() {
+        let set = "globalThis.counter = (globalThis.counter || 0) + params.step; counter";
+        let get = "typeof counter === 'undefined' ? null : counter";
+
+        assert_eq!(execute_javascript_in_session(set, &json!({ "step": 1 }), Some("pipeline_a"), DEFAULT_JS_MAX_STACK_SIZE).unwrap(), json!(1));
+        assert_eq!(execute_javascript_in_session(set, &json!({ "step": 2 }), Some("pipeline_a"), DEFAULT_JS_MAX_STACK_SIZE).unwrap(), json!(3));
+        assert_eq!(execute_javascript_in_session(get, &json!({}), Some("pipeline_b"), DEFAULT_JS_MAX_STACK_SIZE).unwrap(), Value::Null);
+        assert_eq!(execute_javascript_in_session(get, &json!({}), None, DEFAULT_JS_MAX_STACK_SIZE).unwrap(), Value::Null);
+
+        assert!(end_skill_session("pipeline_a".to_string()).unwrap());
+        assert!(end_skill_session("pipeline_b".to_string()).unwrap());
+        assert_eq!(execute_javascript_in_session(get, &json!({}), Some("pipeline_a"), DEFAULT_JS_MAX_STACK_SIZE).unwrap(), Value::Null);
+        end_skill_session("pipeline_a".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_verify_skill_signature_accepts_valid_signature() {
+        let (key_pair, signer) = test_signer();
+        let payload = br#"{"name":"Add","code":"return params.a + params.b;"}"#;
+        let signature = base64::engine::general_purpose::STANDARD.encode(key_pair.sign(payload).as_ref());
+
+        let signed_by = verify_skill_signature(payload, Some(&signature), Some(&signer), true).unwrap();
+        assert_eq!(signed_by.as_deref(), Some("pixel-team"));
+    }
+
+    #[test]
+    fn test_verify_skill_signature_rejects_tampered_payload() {
+        let (key_pair, signer) = test_signer();
+        let payload = br#"{"name":"Add","code":"return params.a + params.b;"}"#;
+        let signature = base64::engine::general_purpose::STANDARD.encode(key_pair.sign(payload).as_ref());
+
+        let tampered = br#"{"name":"Add","code":"return fetch('evil');"}"#;
+        assert!(verify_skill_signature(tampered, Some(&signature), Some(&signer), true).is_err());
+        assert!(verify_skill_signature(tampered, Some(&signature), Some(&signer), false).is_err());
+    }
+
+    #[test]
+    fn test_skill_signer_persists_with_config() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let state_path = temp_dir.path().join("state.bin");
+        let shared_state = SharedState::new();
+        let persistence = PersistenceService::with_path(shared_state.inner.clone(), state_path.clone());
+        persistence.load().unwrap();
+
+        let (_, signer) = test_signer();
+        let invalid = SkillSigner { public_key: "c2hvcnQ=".to_string(), ..signer.clone() };
+        assert!(set_skill_signer_in_state(&shared_state, Some(invalid)).is_err());
+        set_skill_signer_in_state(&shared_state, Some(signer.clone())).unwrap();
+        persistence.persist_settings().unwrap();
+
+        let restarted = SharedState::new();
+        PersistenceService::with_path(restarted.inner.clone(), state_path).load().unwrap();
+        assert_eq!(restarted.read(|state| state.config.skill_signer.clone()), Some(signer));
+    }
+
+    #[test]
+    fn test_verify_skill_signature_optional_mode() {
+        let (_, signer) = test_signer();
+        let payload = br#"{"name":"Add"}"#;
+
+        assert_eq!(verify_skill_signature(payload, None, Some(&signer), false).unwrap(), None);
+        assert_eq!(verify_skill_signature(payload, None, None, false).unwrap(), None);
+        assert!(verify_skill_signature(payload, None, Some(&signer), true).is_err());
+    }
+
+    #[test]
+    fn test_format_skill_code_reindents_valid_code() {
+        let code = "function add(a, b) {  \r\nreturn a + b;\n}\n\n\n\nreturn add(params.a, params.b);\n".to_string();
+        let result = format_skill_code(code);
+
+        assert!(result.changed);
+        assert!(result.warning.is_none());
+        assert_eq!(
+            result.code,
+            "function add(a, b) {\n    return a + b;\n}\n\nreturn add(params.a, params.b);"
+        );
+    }
+
+    #[test]
+    fn test_format_skill_code_keeps_invalid_code() {
+        let code = "function broken( {\nreturn 1;".to_string();
+        let result = format_skill_code(code.clone());
+
+        assert!(!result.changed);
+        assert_eq!(result.code, code);
+        assert!(result.warning.is_some());
+    }
+
+    #[test]
+    fn test_normalize_ignores_brackets_in_strings() {
+        let code = "const s = \"{[(\";\nreturn s;";
+        assert_eq!(normalize_js_source(code), code);
+    }
+
+    #[test]
+    fn test_code_similarity_scores() {
+        let original = "function run(input) {\n    const total = input.a + input.b;\n    return { total };\n}";
+        let drifted = "function run(input) {\n    const total = input.a + input.b; // sum\n    return { total };\n}";
+        let unrelated = "const url = 'https://example.com';\nfetch(url).then(r => r.json());";
+
+        assert!(code_similarity(original, drifted) > 0.8);
+        assert!(code_similarity(original, unrelated) < 0.3);
+        assert_eq!(code_similarity(original, original), 1.0);
+    }
+
+    #[test]
+    fn test_lint_skill_parameters() {
+        let param = |name: &str| SkillParameter {
+            name: name.to_string(),
+            param_type: SkillParameterType::String,
+            description: String::new(),
+            required: false,
+            default: None,
+        };
+
+        let warnings = lint_skill_parameters(&[param("params"), param("text")]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, LintSeverity::Warning);
+        assert_eq!(warnings[0].parameter, "params");
+        assert!(validate_skill_parameters(&[param("params")]).is_ok());
+
+        let warnings = lint_skill_parameters(&[param("text"), param("text")]);
+        assert!(warnings.iter().any(|w| w.severity == LintSeverity::Error));
+        assert!(validate_skill_parameters(&[param("text"), param("text")]).is_err());
+    }
+
+    #[test]
+    fn test_skill_output_schema() {
+        let schema = json!({ "type": "object", "required": ["result"] });
+
+        let output = execute_javascript("({ result: params.a + params.b })", &json!({ "a": 1, "b": 2 })).unwrap();
+        let checked = check_skill_output(output, Some(&schema)).unwrap();
+        assert_eq!(checked["result"], 3);
+
+        let output = execute_javascript("params.a + params.b", &json!({ "a": 1, "b": 2 })).unwrap();
+        let err = check_skill_output(output, Some(&schema)).unwrap_err();
+        assert!(err.contains("Output schema validation failed"));
+        assert!(err.contains("$ must be of type object"));
+    }
+
+    #[tokio::test]
+    async fn test_read_body_capped_truncates_oversized_body() {
+        let server = MockHttpServer::start(|_| MockResponse::new("200 OK", "text/plain", "x".repeat(64 * 1024))).await;
+
+        let url = server.url();
+        let response = reqwest::get(&url).await.unwrap();
+        let body = read_body_capped(response, 100).await.unwrap();
+        assert!(body.truncated);
+        assert_eq!(body.bytes.len(), 100);
+        assert!(body.text().ends_with(crate::commands::config::TRUNCATION_MARKER));
+
+        let response = reqwest::get(&url).await.unwrap();
+        let body = read_body_capped(response, 1024 * 1024).await.unwrap();
+        assert!(!body.truncated);
+        assert_eq!(body.bytes.len(), 64 * 1024);
+    }
+
+    #[test]
+    fn test_export_skill_executions_csv() {
+        for (code, skill_id) in [("1 + 1", "csv_skill_a"), ("throw new Error('boom')", "csv_skill_b"), ("2 * 3", "csv_skill_a")] {
+            let start = std::time::Instant::now();
+            let output = execute_javascript(code, &json!({}));
+            record_skill_execution(skill_id, SkillResult {
+                success: output.is_ok(),
+                output: output.clone().unwrap_or(Value::Null),
+                error: output.err(),
+                execution_time_ms: start.elapsed().as_millis() as u64,
+            });
+        }
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("executions.csv");
+        let rows = export_skill_executions_csv(path.to_string_lossy().to_string(), Some("csv_skill_a".to_string())).unwrap();
+        assert_eq!(rows, 2);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "skill_id,timestamp,execution_time_ms,success");
+        assert_eq!(lines.len(), 3);
+        for line in &lines[1..] {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields[0], "csv_skill_a");
+            assert!(fields[1].parse::<u64>().unwrap() > 0);
+            assert!(fields[2].parse::<u64>().is_ok());
+            assert_eq!(fields[3], "true");
+        }
+
+        let (csv, rows) = skill_executions_csv(&skill_executions().lock().unwrap(), Some("csv_skill_b"));
+        assert_eq!(rows, 1);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",false"));
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+    }
+
+    #[test]
+    fn test_consolidate_categories() {
+        let skill_json = |name: &str, category: &str| json!({
+            "id": "",
+            "name": name,
+            "description": "",
+            "category": category,
+            "parameters": [],
+            "code": "1",
+            "enabled": true,
+            "created_at": 0,
+            "updated_at": 0,
+        });
+
+        // Skills saved before import normalization keep their original casing
+        let mut state = AppState::default();
+        for (name, category) in [("a", "Productivity"), ("b", "productivity"), ("c", "Productivity "), ("d", "Writing")] {
+            let mut skill: Skill = serde_json::from_value(skill_json(name, category)).unwrap();
+            skill.id = name.to_string();
+            state.skills.push(skill);
+        }
+
+        assert_eq!(consolidate_skill_categories(&mut state.skills), 2);
+        assert!(state.skills[..3].iter().all(|s| s.category == "Productivity"));
+        assert_eq!(state.skills[3].category, "Writing");
+        assert_eq!(consolidate_skill_categories(&mut state.skills), 0);
+
+        let imported = import_skill_into_state(&mut state, skill_json("e", "PRODUCTIVITY")).unwrap();
+        assert_eq!(imported.category, "Productivity");
+        let imported = import_skill_into_state(&mut state, skill_json("f", " research ")).unwrap();
+        assert_eq!(imported.category, "research");
+    }
+
+    #[test]
+    fn test_merge_duplicate_skills() {
+        let skill_json = |name: &str, code: &str| json!({
+            "id": "",
+            "name": name,
+            "description": "",
+            "category": "Utility",
+            "parameters": [],
+            "code": code,
+            "enabled": true,
+            "created_at": 0,
+            "updated_at": 0,
+        });
+        let mut state = AppState::default();
+        let first = import_skill_into_state(&mut state, skill_json("Upper", "params.text.toUpperCase()")).unwrap();
+        let copy = import_skill_into_state(&mut state, skill_json("Upper (imported)", "params.text.toUpperCase()")).unwrap();
+        let other = import_skill_into_state(&mut state, skill_json("Lower", "params.text.toLowerCase()")).unwrap();
+        state.skill_runs.insert(copy.id.clone(), vec![SkillRun {
+            timestamp: 1,
+            input: json!({ "text": "a" }),
+            output: json!("A"),
+            success: true,
+            duration_ms: 1,
+            error: None,
+        }]);
+
+        let groups = duplicate_skill_groups(&state.skills);
+        assert_eq!(groups.len(), 1);
+        let mut ids = groups[0].skill_ids.clone();
+        ids.sort();
+        let mut expected = vec![first.id.clone(), copy.id.clone()];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        assert!(merge_duplicate_skills_in_state(&mut state, &first.id, std::slice::from_ref(&other.id)).is_err());
+        let kept = merge_duplicate_skills_in_state(&mut state, &first.id, std::slice::from_ref(&copy.id)).unwrap();
+        assert_eq!(kept.id, first.id);
+        assert_eq!(state.skills.len(), 2);
+        assert!(state.skills.iter().all(|s| s.id != copy.id));
+        assert_eq!(state.skill_runs[&first.id].len(), 1);
+        assert!(duplicate_skill_groups(&state.skills).is_empty());
+    }
+
+    #[test]
+    fn test_warmup_skill_runtime() {
+        let elapsed_ms = warmup_skill_runtime().unwrap();
+        assert!(elapsed_ms < 10_000);
+    }
+
+    #[test]
+    fn test_top_skills_ranked_by_executions() {
+        let skill = |id: &str, name: &str| -> Skill {
+            serde_json::from_value(json!({
+                "id": id, "name": name, "description": "", "category": "", "parameters": [],
+                "code": "", "enabled": true, "created_at": 0, "updated_at": 0,
+            })).unwrap()
+        };
+        let execution = |skill_id: &str| SkillExecution {
+            skill_id: skill_id.to_string(),
+            timestamp: 0,
+            execution_time_ms: 1,
+            success: true,
+        };
+
+        let skills = vec![skill("a", "Alpha"), skill("b", "Beta"), skill("c", "Gamma"), skill("d", "Delta")];
+        let executions = vec![execution("b"), execution("c"), execution("b"), execution("a"), execution("c"), execution("b"), execution("deleted")];
+
+        let top = rank_skills_by_usage(&skills, &executions, 10);
+        let ids: Vec<&str> = top.iter().map(|s| s.skill_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "c", "a"]);
+        assert_eq!(top[0].count, 3);
+        assert_eq!(rank_skills_by_usage(&skills, &executions, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_skill_runs_logged_with_inputs_and_outputs() {
+        let shared_state = SharedState::new();
+        shared_state.write(|state| {
+            state.skills.push(Skill {
+                id: "adder".to_string(),
+                code: "params.a + params.b".to_string(),
+                log_runs: true,
+                ..Default::default()
+            });
+            state.skills.push(Skill {
+                id: "quiet".to_string(),
+                code: "1".to_string(),
+                ..Default::default()
+            });
+        });
+
+        run_skill(&shared_state, "adder", json!({ "a": 1, "b": 2 }), None).unwrap();
+        run_skill(&shared_state, "adder", json!({ "a": 5, "b": 5 }), None).unwrap();
+        run_skill(&shared_state, "quiet", json!({}), None).unwrap();
+
+        let runs = shared_state.read(|state| recent_skill_runs(state, "adder", 0));
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].input, json!({ "a": 5, "b": 5 }));
+        assert_eq!(runs[0].output, json!(10));
+        assert_eq!(runs[1].input, json!({ "a": 1, "b": 2 }));
+        assert_eq!(runs[1].output, json!(3));
+        assert!(runs.iter().all(|r| r.success));
+        assert_eq!(shared_state.read(|state| recent_skill_runs(state, "adder", 1)).len(), 1);
+        assert!(shared_state.read(|state| recent_skill_runs(state, "quiet", 0)).is_empty());
+    }
+
+    #[test]
+    fn test_number_default_injected_as_number() {
+        let param = |name: &str, param_type, default: &str| SkillParameter {
+            name: name.to_string(),
+            param_type,
+            description: String::new(),
+            required: false,
+            default: Some(default.to_string()),
+        };
+        let shared_state = SharedState::new();
+        shared_state.write(|state| {
+            state.skills.push(Skill {
+                id: "counter".to_string(),
+                name: "Counter".to_string(),
+                code: "typeof params.count === 'number' && params.count + 1 === 6 && params.verbose === true && params.label === 'n'".to_string(),
+                parameters: vec![
+                    param("count", SkillParameterType::Number, " 5 "),
+                    param("verbose", SkillParameterType::Boolean, "TRUE"),
+                    param("label", SkillParameterType::String, "n"),
+                    param("limit", SkillParameterType::Number, "many"),
+                ],
+                ..Default::default()
+            });
+        });
+
+        let parameters = shared_state.read(|state| state.skills[0].parameters.clone());
+        assert_eq!(
+            apply_parameter_defaults(&parameters, json!({ "label": "x" })),
+            json!({ "count": 5, "verbose": true, "label": "x" })
+        );
+
+        let result = run_skill(&shared_state, "counter", json!({}), None).unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, json!(true));
+
+        let report = shared_state.write(normalize_skill_defaults_in_state);
+        assert_eq!(report.updated, 2);
+        assert_eq!(report.invalid.len(), 1);
+        let defaults: Vec<_> = shared_state.read(|state| {
+            state.skills[0].parameters.iter().map(|p| p.default.clone().unwrap()).collect()
+        });
+        assert_eq!(defaults, vec!["5", "true", "n", "many"]);
+    }
+
+    #[test]
+    fn test_deep_recursion_reports_stack_overflow() {
+        let shared_state = SharedState::new();
+        shared_state.write(|state| {
+            state.skills.push(Skill {
+                id: "recursive".to_string(),
+                code: "function depth(n) { return n === 0 ? 0 : 1 + depth(n - 1); } depth(params.n)".to_string(),
+                ..Default::default()
+            });
+        });
+
+        let result = run_skill(&shared_state, "recursive", json!({ "n": 10 }), None).unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output, json!(10));
+
+        let result = run_skill(&shared_state, "recursive", json!({ "n": 1_000_000 }), None).unwrap();
+        assert!(!result.success);
+        let error = result.error.unwrap();
+        assert!(error.starts_with("Stack overflow"), "{}", error);
+
+        // A skill can get a smaller limit of its own
+        shared_state.write(|state| state.skills[0].max_stack_size = Some(MIN_JS_MAX_STACK_SIZE));
+        let error = run_skill(&shared_state, "recursive", json!({ "n": 1_000_000 }), None).unwrap().error.unwrap();
+        assert!(error.contains("64 KiB"), "{}", error);
+
+        assert!(validate_js_max_stack_size(1024).is_err());
+        assert!(validate_js_max_stack_size(64 * 1024 * 1024).is_err());
+    }
+
+    #[test]
+    fn test_skill_output_appended_as_message() {
+        let shared_state = SharedState::new();
+        shared_state.write(|state| {
+            state.skills.push(Skill {
+                id: "summary".to_string(),
+                code: "({ sum: params.a + params.b, items: [params.a, params.b] })".to_string(),
+                ..Default::default()
+            });
+            state.sessions.insert("s1".to_string(), ChatSession::new("s1".to_string(), "Chat".to_string()));
+        });
+
+        let message = skill_output_to_message(&shared_state, "summary", json!({ "a": 1, "b": 2 }), "s1", "assistant".to_string()).unwrap();
+        let expected = serde_json::to_string_pretty(&json!({ "sum": 3, "items": [1, 2] })).unwrap();
+        assert_eq!(message.role, "assistant");
+        assert_eq!(message.content, expected);
+
+        let messages = shared_state.read(|state| state.sessions["s1"].messages.clone());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, message.id);
+        assert_eq!(messages[0].content, expected);
+
+        assert!(skill_output_to_message(&shared_state, "summary", json!({}), "missing", "assistant".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_self_referential_output_uses_circular_marker() {
+        let shared_state = SharedState::new();
+        shared_state.write(|state| {
+            state.skills.push(Skill {
+                id: "cyclic".to_string(),
+                code: "let shared = { n: 1 }; let o = { name: 'root', a: shared, b: shared, list: [] }; o.self = o; o.list.push(o.list, o); o".to_string(),
+                ..Default::default()
+            });
+        });
+
+        let result = run_skill(&shared_state, "cyclic", json!({}), None).unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.output["name"], "root");
+        assert_eq!(result.output["self"], CIRCULAR_MARKER);
+        assert_eq!(result.output["list"], json!([CIRCULAR_MARKER, CIRCULAR_MARKER]));
+        assert_eq!(result.output["a"], json!({ "n": 1 }));
+        assert_eq!(result.output["b"], json!({ "n": 1 }));
+    }
+
+    #[test]
+    fn test_scan_skill_security() {
+        let risky = "const fs = require('fs');\nlet n = params.count;\nwhile (true) { n++; }\nreturn process.env.HOME;";
+        let findings = scan_skill_code(risky);
+        let found: Vec<(usize, &str)> = findings.iter().map(|f| (f.line, f.pattern.as_str())).collect();
+        assert_eq!(found, vec![(1, "require"), (3, "infinite-loop"), (4, "process")]);
+
+        let benign = "const total = params.items.reduce((sum, x) => sum + x, 0);\nconst process_id = params.process.id;\nfor (let i = 0; i < 3; i++) { log(i); }\n({ total, process_id })";
+        assert!(scan_skill_code(benign).is_empty());
+
+        let mut state = AppState::default();
+        let imported = import_skill_into_state(&mut state, json!({
+            "id": "", "name": "Reader", "description": "", "category": "Files",
+            "parameters": [], "code": risky, "enabled": true, "created_at": 0, "updated_at": 0,
+        })).unwrap();
+        assert_eq!(imported.security_findings, findings);
+    }
+}
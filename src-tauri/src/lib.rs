@@ -62,14 +62,37 @@ pub fn configure_app<R: tauri::Runtime>(builder: tauri::Builder<R>) -> tauri::Bu
             commands::get_active_sessions,
             commands::stream_chat_completions,
             commands::cancel_chat_stream,
+            commands::get_active_streams,
+            commands::get_last_turn_timing,
+            commands::scan_for_prompt_injection,
+            commands::get_loop_detection_threshold,
+            commands::set_loop_detection_threshold,
+            commands::stream_completion_to_file,
+            commands::preview_chat_request,
+            commands::complete_chat,
             commands::get_session,
             commands::update_session,
+            commands::set_session_temperature,
+            commands::set_session_token_budget,
             commands::search_sessions,
+            commands::prefilter_sessions,
             commands::clear_session_history,
+            commands::edit_message,
+            commands::delete_message,
+            commands::remove_empty_messages,
             commands::duplicate_session,
+            commands::fork_session,
+            commands::get_session_tree,
             // Chat reasoning commands
             commands::get_session_reasoning_messages,
             commands::get_reasoning_message,
+            commands::get_all_reasoning_messages,
+            commands::get_session_reasoning_stats,
+            commands::get_session_reading_stats,
+            commands::export_session_html_file,
+            commands::export_session_markdown,
+            commands::export_session_reasoning_markdown,
+            commands::export_reasoning_report,
             // Chat new commands
             commands::get_session_history,
             // MCP commands
@@ -78,27 +101,51 @@ pub fn configure_app<R: tauri::Runtime>(builder: tauri::Builder<R>) -> tauri::Bu
             commands::create_mcp_server,
             commands::update_mcp_server,
             commands::delete_mcp_server,
+            commands::export_mcp_servers_json,
+            commands::import_mcp_servers_from_json,
             commands::start_mcp_server,
             commands::stop_mcp_server,
             commands::get_mcp_server_tools,
             commands::call_mcp_tool,
+            commands::cancel_mcp_tool_call,
+            commands::get_mcp_server_timeout,
+            commands::get_mcp_next_rpc_id,
+            commands::set_mcp_server_timeout,
             commands::test_mcp_server_connection,
+            commands::precheck_mcp_args,
+            commands::get_mcp_server_logs,
             commands::list_mcp_resources,
             commands::read_mcp_resource,
+            commands::read_mcp_resource_streaming,
             commands::list_mcp_prompts,
             commands::get_mcp_prompt,
             commands::get_mcp_server_status_info,
             // MCP new commands
             commands::restart_mcp_server,
             commands::get_mcp_stats,
+            commands::get_mcp_retry_count,
+            commands::set_mcp_retry_count,
             // Skills commands
             commands::execute_skill,
+            commands::execute_skill_to_message,
+            commands::end_skill_session,
+            commands::set_skill_run_logging,
+            commands::get_js_max_stack_size,
+            commands::set_js_max_stack_size,
+            commands::set_skill_max_stack_size,
+            commands::get_skill_runs,
+            commands::clear_skill_runs,
+            commands::warmup_skill_runtime,
             commands::get_skills,
             commands::get_skill,
             commands::create_skill,
             commands::update_skill,
             commands::delete_skill,
             commands::get_skill_categories,
+            commands::consolidate_categories,
+            commands::find_duplicate_skills,
+            commands::merge_duplicate_skills,
+            commands::normalize_skill_defaults,
             commands::toggle_skill,
             commands::import_skill,
             commands::export_skill,
@@ -106,18 +153,31 @@ pub fn configure_app<R: tauri::Runtime>(builder: tauri::Builder<R>) -> tauri::Bu
             commands::search_skills,
             // Skills new commands
             commands::get_skill_stats,
+            commands::get_top_skills,
+            commands::export_skill_executions_csv,
             commands::install_skill_from_zip,
             commands::reindex_skills,
+            commands::skill_code_similarity,
+            commands::get_skill_signer,
+            commands::set_skill_signer,
+            commands::import_signed_skill,
+            commands::lint_skill,
+            commands::scan_skill_security,
+            commands::format_skill_code,
             // Provider commands
             commands::get_providers,
             commands::get_provider,
+            commands::get_provider_default_model,
             commands::create_provider,
             commands::update_provider,
             commands::delete_provider,
+            commands::dedupe_providers,
+            commands::clear_provider_key,
             commands::set_default_provider,
             commands::validate_provider,
             commands::get_models,
             commands::get_model,
+            commands::fetch_provider_models,
             commands::create_model,
             commands::update_model,
             commands::delete_model,
@@ -126,25 +186,71 @@ pub fn configure_app<R: tauri::Runtime>(builder: tauri::Builder<R>) -> tauri::Bu
             // Provider new commands
             commands::test_provider_config,
             commands::validate_model_availability,
+            commands::validate_all_models,
+            commands::supports_streaming,
+            commands::measure_provider_ttfb,
+            commands::diagnose_endpoint,
+            commands::get_top_providers,
+            commands::recommend_default_model,
+            commands::preview_provider_headers,
             // Excalidraw commands
             commands::save_excalidraw_scene,
             commands::load_excalidraw_scene,
             commands::list_excalidraw_scenes,
             commands::delete_excalidraw_scene,
+            commands::rename_excalidraw_scene,
             commands::export_excalidraw_scene,
             commands::import_excalidraw_scene,
+            commands::validate_excalidraw_scene,
+            commands::validate_excalidraw_scene_file,
+            commands::repair_excalidraw_scene,
+            commands::merge_excalidraw_scenes,
+            // Schema commands
+            commands::describe_type_schema,
+            // Config commands
+            commands::get_effective_config,
+            commands::get_max_result_bytes,
+            commands::set_max_result_bytes,
+            commands::get_proxy_url,
+            commands::set_proxy_url,
+            commands::get_stream_retry,
+            commands::set_stream_retry,
+            commands::get_window_state,
+            commands::suggest_closest_name,
+            commands::get_close_to_tray,
+            commands::set_close_to_tray,
             // Renderer commands
             services::renderer_cmd_wrapper::render_markdown,
+            services::renderer_cmd_wrapper::render_markdown_batch,
             services::renderer_cmd_wrapper::process_custom_syntax,
             services::renderer_cmd_wrapper::highlight_code_sync,
+            services::renderer_cmd_wrapper::highlight_code_with_line_numbers,
+            services::renderer_cmd_wrapper::detect_code_language,
+            services::renderer_cmd_wrapper::markdown_to_plaintext,
+            services::renderer_cmd_wrapper::list_supported_languages,
+            services::renderer_cmd_wrapper::get_renderer_capabilities,
+            services::renderer_cmd_wrapper::reload_highlighter_assets,
             // Persistence commands
             services::persistence_cmd_wrapper::save_state,
             services::persistence_cmd_wrapper::load_state,
             services::persistence_cmd_wrapper::create_backup,
+            services::persistence_cmd_wrapper::get_last_backup_time,
             services::persistence_cmd_wrapper::get_state_size,
             services::persistence_cmd_wrapper::export_state_json,
             services::persistence_cmd_wrapper::import_state_json,
+            services::persistence_cmd_wrapper::diff_states,
             services::persistence_cmd_wrapper::clear_state,
+            services::persistence_cmd_wrapper::undo_last_state_operation,
+            services::persistence_cmd_wrapper::get_last_save_info,
+            services::persistence_cmd_wrapper::begin_bulk_operation,
+            services::persistence_cmd_wrapper::end_bulk_operation,
+            services::persistence_cmd_wrapper::compact_state,
+            services::persistence_cmd_wrapper::save_state_encrypted,
+            services::persistence_cmd_wrapper::load_state_encrypted,
+            services::persistence_cmd_wrapper::rekey_state,
+            services::persistence_cmd_wrapper::save_session_file,
+            services::persistence_cmd_wrapper::list_session_files,
+            services::persistence_cmd_wrapper::delete_session_file,
         ])
 }
 
@@ -219,6 +325,9 @@ mod tests {
             base_url: "https://api.openai.com/v1".to_string(),
             api_key: "sk-xxx".to_string(),
             enabled: true,
+            custom_headers: std::collections::HashMap::new(),
+            default_model_id: None,
+            supports_streaming: None,
         };
         
         let serialized = serde_json::to_string(&provider).unwrap();
@@ -260,6 +369,11 @@ mod tests {
             enabled: true,
             created_at: 1234567890,
             updated_at: 1234567890,
+            output_schema: None,
+            signed_by: None,
+            log_runs: false,
+            security_findings: Vec::new(),
+            max_stack_size: None,
         };
         
         let serialized = serde_json::to_string(&skill).unwrap();
@@ -276,6 +390,9 @@ mod tests {
             command: "npx".to_string(),
             args: vec!["-y".to_string(), "@modelcontextprotocol/server-filesystem".to_string()],
             env: std::collections::HashMap::new(),
+            timeout_ms: None,
+            url: None,
+            cwd: None,
         };
         
         let serialized = serde_json::to_string(&server).unwrap();
@@ -294,6 +411,12 @@ mod tests {
             notifications: false,
             active_model_id: Some("model_1".to_string()),
             active_provider_id: Some("provider_1".to_string()),
+            backup_interval_hours: Some(24),
+            proxy_url: None,
+            stream_retry: 0,
+            window_state: None,
+            close_to_tray: true,
+            skill_signer: None,
         };
         
         let serialized = serde_json::to_string(&config).unwrap();
@@ -506,6 +629,9 @@ mod tests {
                 command: "echo".to_string(),
                 args: vec!["test".to_string()],
                 env: HashMap::new(),
+                timeout_ms: None,
+                url: None,
+                cwd: None,
             });
         });
         
@@ -541,6 +667,11 @@ mod tests {
                 enabled: true,
                 created_at: 1234567890,
                 updated_at: 1234567890,
+                output_schema: None,
+                signed_by: None,
+                log_runs: false,
+                security_findings: Vec::new(),
+                max_stack_size: None,
             });
         });
         
@@ -12,6 +12,7 @@ mod commands;
 mod services;
 
 use state::{PixelState, AppHandleHolder, LegacyAppConfig, SharedState, McpServerManager};
+use services::persistence::PersistenceService;
 use std::sync::Arc;
 
 fn main() {
@@ -26,19 +27,38 @@ fn main() {
             commands::get_active_sessions,
             commands::stream_chat_completions,
             commands::cancel_chat_stream,
+            commands::get_active_streams,
+            commands::get_last_turn_timing,
+            commands::scan_for_prompt_injection,
+            commands::get_loop_detection_threshold,
+            commands::set_loop_detection_threshold,
+            commands::stream_completion_to_file,
+            commands::preview_chat_request,
+            commands::complete_chat,
             commands::enable_deep_thinking,
             commands::get_deep_thinking_status,
             commands::parse_reasoning_content_cmd,
             commands::stream_chat_completions_with_thinking,
             commands::get_providers,
             commands::get_provider,
+            commands::get_provider_default_model,
             commands::create_provider,
             commands::update_provider,
             commands::delete_provider,
+            commands::dedupe_providers,
+            commands::clear_provider_key,
             commands::set_default_provider,
             commands::validate_provider,
+            commands::validate_all_models,
+            commands::supports_streaming,
+            commands::measure_provider_ttfb,
+            commands::diagnose_endpoint,
+            commands::get_top_providers,
+            commands::recommend_default_model,
+            commands::preview_provider_headers,
             commands::get_models,
             commands::get_model,
+            commands::fetch_provider_models,
             commands::create_model,
             commands::update_model,
             commands::delete_model,
@@ -46,50 +66,136 @@ fn main() {
             commands::get_default_model_config,
             commands::get_session,
             commands::update_session,
+            commands::set_session_temperature,
+            commands::set_session_token_budget,
             commands::search_sessions,
+            commands::prefilter_sessions,
             commands::clear_session_history,
+            commands::edit_message,
+            commands::delete_message,
+            commands::remove_empty_messages,
             commands::duplicate_session,
+            commands::fork_session,
+            commands::get_session_tree,
+            commands::export_reasoning_report,
+            commands::get_session_reasoning_stats,
+            commands::get_session_reading_stats,
+            commands::get_all_reasoning_messages,
+            commands::export_session_html_file,
+            commands::export_session_markdown,
+            commands::export_session_reasoning_markdown,
             commands::get_mcp_servers,
             commands::get_mcp_server,
             commands::create_mcp_server,
             commands::update_mcp_server,
             commands::delete_mcp_server,
+            commands::export_mcp_servers_json,
+            commands::import_mcp_servers_from_json,
             commands::start_mcp_server,
             commands::stop_mcp_server,
             commands::get_mcp_server_tools,
             commands::test_mcp_server_connection,
+            commands::precheck_mcp_args,
+            commands::get_mcp_server_logs,
+            commands::read_mcp_resource_streaming,
             commands::call_mcp_tool,
+            commands::cancel_mcp_tool_call,
+            commands::get_mcp_server_timeout,
+            commands::get_mcp_next_rpc_id,
+            commands::set_mcp_server_timeout,
+            commands::get_mcp_retry_count,
+            commands::set_mcp_retry_count,
             commands::get_skills,
             commands::get_skill,
             commands::create_skill,
             commands::update_skill,
             commands::delete_skill,
             commands::execute_skill,
+            commands::execute_skill_to_message,
+            commands::end_skill_session,
+            commands::set_skill_run_logging,
+            commands::get_js_max_stack_size,
+            commands::set_js_max_stack_size,
+            commands::set_skill_max_stack_size,
+            commands::get_skill_runs,
+            commands::clear_skill_runs,
+            commands::get_top_skills,
+            commands::warmup_skill_runtime,
+            commands::export_skill_executions_csv,
             commands::get_skill_categories,
+            commands::consolidate_categories,
+            commands::find_duplicate_skills,
+            commands::merge_duplicate_skills,
+            commands::normalize_skill_defaults,
             commands::toggle_skill,
             commands::import_skill,
             commands::export_skill,
             commands::get_skills_by_category,
             commands::search_skills,
+            commands::format_skill_code,
+            commands::skill_code_similarity,
+            commands::get_skill_signer,
+            commands::set_skill_signer,
+            commands::import_signed_skill,
+            commands::lint_skill,
+            commands::scan_skill_security,
             commands::save_excalidraw_scene,
             commands::load_excalidraw_scene,
             commands::list_excalidraw_scenes,
             commands::delete_excalidraw_scene,
+            commands::rename_excalidraw_scene,
             commands::export_excalidraw_scene,
             commands::import_excalidraw_scene,
+            commands::validate_excalidraw_scene,
+            commands::validate_excalidraw_scene_file,
+            commands::repair_excalidraw_scene,
+            commands::merge_excalidraw_scenes,
+            commands::describe_type_schema,
+            commands::get_effective_config,
+            commands::get_max_result_bytes,
+            commands::set_max_result_bytes,
+            commands::get_proxy_url,
+            commands::set_proxy_url,
+            commands::get_stream_retry,
+            commands::set_stream_retry,
+            commands::get_window_state,
+            commands::suggest_closest_name,
+            commands::get_close_to_tray,
+            commands::set_close_to_tray,
             commands::save_excalidraw_image,
             commands::save_excalidraw_image_raw,
             commands::list_excalidraw_exports,
+            commands::list_all_exports,
             services::renderer_cmd_wrapper::render_markdown,
+            services::renderer_cmd_wrapper::render_markdown_batch,
             services::renderer_cmd_wrapper::process_custom_syntax,
             services::renderer_cmd_wrapper::highlight_code_sync,
+            services::renderer_cmd_wrapper::highlight_code_with_line_numbers,
+            services::renderer_cmd_wrapper::detect_code_language,
+            services::renderer_cmd_wrapper::markdown_to_plaintext,
+            services::renderer_cmd_wrapper::list_supported_languages,
+            services::renderer_cmd_wrapper::get_renderer_capabilities,
+            services::renderer_cmd_wrapper::reload_highlighter_assets,
             services::persistence_cmd_wrapper::save_state,
             services::persistence_cmd_wrapper::load_state,
             services::persistence_cmd_wrapper::create_backup,
+            services::persistence_cmd_wrapper::get_last_backup_time,
             services::persistence_cmd_wrapper::get_state_size,
             services::persistence_cmd_wrapper::export_state_json,
             services::persistence_cmd_wrapper::import_state_json,
+            services::persistence_cmd_wrapper::diff_states,
             services::persistence_cmd_wrapper::clear_state,
+            services::persistence_cmd_wrapper::undo_last_state_operation,
+            services::persistence_cmd_wrapper::get_last_save_info,
+            services::persistence_cmd_wrapper::begin_bulk_operation,
+            services::persistence_cmd_wrapper::end_bulk_operation,
+            services::persistence_cmd_wrapper::compact_state,
+            services::persistence_cmd_wrapper::save_state_encrypted,
+            services::persistence_cmd_wrapper::load_state_encrypted,
+            services::persistence_cmd_wrapper::rekey_state,
+            services::persistence_cmd_wrapper::save_session_file,
+            services::persistence_cmd_wrapper::list_session_files,
+            services::persistence_cmd_wrapper::delete_session_file,
         ])
         .setup(|app| {
             // Initialize state
@@ -98,12 +204,29 @@ fn main() {
                 app_handle: AppHandleHolder::new(app.handle().clone()),
             };
             app.manage(pixel_state);
-            app.manage(SharedState::new());
+            let shared_state = SharedState::new();
+            let persistence = PersistenceService::new(shared_state.inner.clone());
+            // Encrypted or unreadable files stay unloaded until the frontend loads them
+            let _ = persistence.load();
+            let proxy_url = shared_state.read(|state| state.config.proxy_url.clone());
+            let _ = commands::config::apply_proxy_url(proxy_url.as_deref());
+            persistence.start_backup_schedule();
+            app.manage(persistence);
+            app.manage(shared_state);
             app.manage(McpServerManager::default());
 
-            // Setup main window
+            // Pay the JS runtime's one-time init cost before the first skill runs
+            std::thread::spawn(|| {
+                let _ = commands::warmup_skill_runtime();
+            });
+
+            // Setup main window, restoring the geometry saved on the last run
             if let Some(window) = app.get_webview_window("main") {
                 let _ = window.set_title("Pixel-Client");
+                let saved = app.state::<SharedState>().read(|state| state.config.window_state);
+                if let Some(saved) = saved {
+                    commands::restore_window_state(&window, saved);
+                }
             }
 
             // Create tray menu items
@@ -132,6 +255,7 @@ fn main() {
                             }
                         }
                         "quit" => {
+                            let _ = app.state::<PersistenceService>().persist_settings();
                             app.exit(0);
                         }
                         _ => {}
@@ -158,10 +282,23 @@ fn main() {
             Ok(())
         })
         .on_window_event(|window, event| {
-            // Intercept close request: hide to tray instead of closing
-            if let WindowEvent::CloseRequested { api, .. } = event {
-                api.prevent_close();
-                let _ = window.hide();
+            match event {
+                // Intercept close request: hide to tray unless close-to-tray is turned off
+                WindowEvent::CloseRequested { api, .. } => {
+                    // Window geometry is only recorded in memory as it changes
+                    let _ = window.state::<PersistenceService>().persist_settings();
+                    let close_to_tray = window.state::<SharedState>().read(|state| state.config.close_to_tray);
+                    commands::handle_close_requested(
+                        close_to_tray,
+                        || api.prevent_close(),
+                        || { let _ = window.hide(); },
+                        || window.app_handle().exit(0),
+                    );
+                }
+                WindowEvent::Moved(_) | WindowEvent::Resized(_) if window.label() == "main" => {
+                    commands::record_window_state(window);
+                }
+                _ => {}
             }
         })
         .run(tauri::generate_context!())
@@ -0,0 +1,216 @@
+//! Frozen copies of the state structs as they were persisted in older format versions, and
+//! their migration into the current structs
+//!
+//! Format 0 predates the format header. bincode is positional, so these must never change:
+//! a field added to `AppState` or one of its children shifts every byte after it. Types
+//! whose layout has not changed since a format are reused from `crate::state`.
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::state::{
+    AceConfig, AppConfig, AppState, ChatSession, DeepThinkingConfig, LLMModel, LLMProvider,
+    McpServer, Message, ReasoningBlock, Skill, SkillParameter,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfigV0 {
+    pub theme: String,
+    pub language: String,
+    pub font_size: u16,
+    pub auto_save: bool,
+    pub notifications: bool,
+    pub active_model_id: Option<String>,
+    pub active_provider_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageV0 {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+    pub timestamp: u64,
+    pub model_id: Option<String>,
+    pub attachments: Vec<String>,
+    pub images: Vec<String>,
+    pub reasoning_content: Option<String>,
+    pub reasoning_blocks: Vec<ReasoningBlock>,
+    pub token_usage: Option<usize>,
+    pub is_deep_thinking: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatSessionV0 {
+    pub id: String,
+    pub title: String,
+    pub messages: Vec<MessageV0>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub model_id: Option<String>,
+    pub deep_thinking_config: DeepThinkingConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMProviderV0 {
+    pub id: String,
+    pub name: String,
+    pub provider_type: String,
+    pub base_url: String,
+    pub api_key: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpServerV0 {
+    pub id: String,
+    pub server_type: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillV0 {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: String,
+    pub parameters: Vec<SkillParameter>,
+    pub code: String,
+    pub enabled: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppStateV0 {
+    pub config: AppConfigV0,
+    pub sessions: HashMap<String, ChatSessionV0>,
+    pub current_session_id: Option<String>,
+    pub providers: Vec<LLMProviderV0>,
+    pub models: Vec<LLMModel>,
+    pub mcp_servers: Vec<McpServerV0>,
+    pub skills: Vec<SkillV0>,
+    pub ace_config: AceConfig,
+    pub theme: String,
+    pub language: String,
+}
+
+impl From<AppConfigV0> for AppConfig {
+    fn from(config: AppConfigV0) -> Self {
+        Self {
+            theme: config.theme,
+            language: config.language,
+            font_size: config.font_size,
+            auto_save: config.auto_save,
+            notifications: config.notifications,
+            active_model_id: config.active_model_id,
+            active_provider_id: config.active_provider_id,
+            ..AppConfig::default()
+        }
+    }
+}
+
+impl From<MessageV0> for Message {
+    fn from(message: MessageV0) -> Self {
+        Self {
+            id: message.id,
+            role: message.role,
+            content: message.content,
+            timestamp: message.timestamp,
+            model_id: message.model_id,
+            attachments: message.attachments,
+            images: message.images,
+            reasoning_content: message.reasoning_content,
+            reasoning_blocks: message.reasoning_blocks,
+            token_usage: message.token_usage,
+            prompt_tokens: None,
+            completion_tokens: None,
+            is_deep_thinking: message.is_deep_thinking,
+        }
+    }
+}
+
+impl From<ChatSessionV0> for ChatSession {
+    fn from(session: ChatSessionV0) -> Self {
+        let mut migrated = ChatSession::new(session.id, session.title);
+        migrated.messages = session.messages.into_iter().map(Message::from).collect();
+        migrated.created_at = session.created_at;
+        migrated.updated_at = session.updated_at;
+        migrated.model_id = session.model_id;
+        migrated.deep_thinking_config = session.deep_thinking_config;
+        migrated
+    }
+}
+
+impl From<LLMProviderV0> for LLMProvider {
+    fn from(provider: LLMProviderV0) -> Self {
+        Self {
+            id: provider.id,
+            name: provider.name,
+            provider_type: provider.provider_type,
+            base_url: provider.base_url,
+            api_key: provider.api_key,
+            enabled: provider.enabled,
+            custom_headers: HashMap::new(),
+            default_model_id: None,
+            supports_streaming: None,
+        }
+    }
+}
+
+impl From<McpServerV0> for McpServer {
+    fn from(server: McpServerV0) -> Self {
+        Self {
+            id: server.id,
+            server_type: server.server_type,
+            command: server.command,
+            args: server.args,
+            env: server.env,
+            timeout_ms: None,
+            url: None,
+            cwd: None,
+        }
+    }
+}
+
+impl From<SkillV0> for Skill {
+    fn from(skill: SkillV0) -> Self {
+        Self {
+            id: skill.id,
+            name: skill.name,
+            description: skill.description,
+            category: skill.category,
+            parameters: skill.parameters,
+            code: skill.code,
+            enabled: skill.enabled,
+            created_at: skill.created_at,
+            updated_at: skill.updated_at,
+            ..Skill::default()
+        }
+    }
+}
+
+impl From<AppStateV0> for AppState {
+    fn from(state: AppStateV0) -> Self {
+        Self {
+            config: state.config.into(),
+            sessions: state.sessions.into_iter().map(|(id, session)| (id, session.into())).collect(),
+            current_session_id: state.current_session_id,
+            providers: state.providers.into_iter().map(LLMProvider::from).collect(),
+            models: state.models,
+            mcp_servers: state.mcp_servers.into_iter().map(McpServer::from).collect(),
+            skills: state.skills.into_iter().map(Skill::from).collect(),
+            ace_config: state.ace_config,
+            theme: state.theme,
+            language: state.language,
+            ..AppState::default()
+        }
+    }
+}
+
+/// Deserialize a decompressed format 0 payload and migrate it into the current structs
+pub fn decode_v0(decompressed: &[u8]) -> Result<AppState, String> {
+    let legacy: AppStateV0 = bincode::deserialize(decompressed)
+        .map_err(|e| format!("Failed to deserialize format 0 state: {}", e))?;
+    Ok(legacy.into())
+}
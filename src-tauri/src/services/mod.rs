@@ -3,13 +3,14 @@
 
 pub mod renderer;
 pub mod persistence;
+mod legacy_state;
 
 // Re-export renderer commands with proper Tauri command wrappers
 pub mod renderer_cmd_wrapper;
 #[allow(unused_imports)]
-pub use renderer_cmd_wrapper::{render_markdown, process_custom_syntax, highlight_code_sync};
+pub use renderer_cmd_wrapper::{render_markdown, render_markdown_batch, process_custom_syntax, highlight_code_sync, highlight_code_with_line_numbers, detect_code_language, markdown_to_plaintext, list_supported_languages};
 
 // Re-export persistence commands with proper Tauri command wrappers
 pub mod persistence_cmd_wrapper;
 #[allow(unused_imports)]
-pub use persistence_cmd_wrapper::{save_state, load_state, create_backup, get_state_size, export_state_json, import_state_json, clear_state};
+pub use persistence_cmd_wrapper::{save_state, load_state, create_backup, get_last_backup_time, get_state_size, export_state_json, import_state_json, diff_states, clear_state, undo_last_state_operation, get_last_save_info, begin_bulk_operation, end_bulk_operation, compact_state, save_state_encrypted, load_state_encrypted, rekey_state, save_session_file, list_session_files, delete_session_file};
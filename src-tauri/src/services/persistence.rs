@@ -5,20 +5,103 @@ use std::io::{Read, Write};
 use std::path::PathBuf;
 use bincode;
 use zstd;
-use crate::state::AppState;
+use crate::state::{AppState, ChatSession};
+use super::legacy_state;
+use serde::{Serialize, Deserialize};
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, SystemTime};
+use std::num::NonZeroU32;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
 
 /// Default persistence file name
 const STATE_FILE: &str = "pixel_client_state.bin";
 
+/// Directory holding one persisted file per session
+const SESSIONS_DIR: &str = "pixel_client_sessions";
+
+/// Extension of per-session files
+const SESSION_FILE_EXT: &str = "bin";
+
 /// Default compression level
 const COMPRESSION_LEVEL: i32 = 3;
 
+/// Header identifying an encrypted state file
+const ENCRYPTED_STATE_MAGIC: &[u8] = b"PXENC1";
+
+/// Header identifying a state payload, followed by `STATE_FORMAT_VERSION` (u16, little endian)
+const STATE_FORMAT_MAGIC: &[u8] = b"PXST";
+
+/// Version of the bincode+zstd payload layout; bump when it changes incompatibly and
+/// keep a frozen copy of the old layout in `legacy_state`
+const STATE_FORMAT_VERSION: u16 = 1;
+
+/// Start of every zstd frame; format 0 payloads, written before the header existed, begin with it
+const ZSTD_FRAME_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// Prefix of the error returned for payloads this version cannot read
+const INCOMPATIBLE_STATE_FORMAT: &str = "Incompatible state format";
+
+/// Prefix of the error returned for payloads written in another format version
+const UNSUPPORTED_STATE_VERSION: &str = "Unsupported state format version";
+
+/// Error returned when a plain load meets an encrypted state file
+const STATE_FILE_ENCRYPTED: &str = "State file is encrypted; load it with its secret";
+
+/// Error returned when the service would write state it never read over the state file
+const STATE_NOT_LOADED: &str = "State file has not been loaded; refusing to overwrite it";
+
+/// Length of the random salt used for key derivation
+const KEY_SALT_LEN: usize = 16;
+
+/// PBKDF2-HMAC-SHA256 iterations used to derive the state key from a secret
+const KEY_DERIVATION_ITERATIONS: u32 = 100_000;
+
 /// Auto-save interval (30 seconds)
 #[allow(dead_code)]
 const AUTO_SAVE_INTERVAL: Duration = Duration::from_secs(30);
 
+/// How often the backup schedule checks whether a backup is due
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Epoch milliseconds of the last successful save (0 = not saved since startup)
+static LAST_SAVE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Epoch milliseconds of the last successful backup (0 = none since startup)
+static LAST_BACKUP_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Last save information for the "last saved" indicator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastSaveInfo {
+    pub last_save_epoch_ms: Option<u64>,
+    pub seconds_ago: Option<u64>,
+    pub auto_save_enabled: bool,
+}
+
+/// State read at startup, and the backup it came from when the state file was unreadable
+#[derive(Debug, Clone)]
+pub struct LoadedState {
+    pub state: AppState,
+    pub restored_from: Option<PathBuf>,
+}
+
+/// State file sizes around a compaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactionResult {
+    pub size_before: u64,
+    pub size_after: u64,
+    pub backup_path: String,
+}
+
+/// Convert a system time to epoch milliseconds
+fn epoch_ms(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 /// Get the default state file path
 fn get_state_file_path() -> Option<PathBuf> {
     // Use current directory for development
@@ -27,25 +110,17 @@ fn get_state_file_path() -> Option<PathBuf> {
     Some(path)
 }
 
-/// Save state to file with compression
-pub fn save_state(state: &AppState) -> Result<(), String> {
-    let path = get_state_file_path()
-        .ok_or("Failed to get state file path".to_string())?;
-    
-    // Serialize state
-    let serialized = bincode::serialize(state)
-        .map_err(|e| format!("Failed to serialize state: {}", e))?;
-    
-    // Compress with zstd
-    let compressed = zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL)
-        .map_err(|e| format!("Failed to compress state: {}", e))?;
+/// Save state to the given path and record the save time
+fn save_state_to_path(state: &AppState, path: &PathBuf) -> Result<(), String> {
+    // Serialize and compress behind the format header
+    let compressed = encode_state(state)?;
     
     // Write to file
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&path)
+        .open(path)
         .map_err(|e| format!("Failed to open state file: {}", e))?;
     
     file.write_all(&compressed)
@@ -54,20 +129,44 @@ pub fn save_state(state: &AppState) -> Result<(), String> {
     file.flush()
         .map_err(|e| format!("Failed to flush state file: {}", e))?;
     
+    LAST_SAVE_MS.store(epoch_ms(SystemTime::now()), Ordering::SeqCst);
+    
     Ok(())
 }
 
 /// Load state from file with decompression
 pub fn load_state() -> Result<AppState, String> {
+    load_state_with_recovery().map(|loaded| loaded.state)
+}
+
+/// Load state from file; a file written in a format version this one cannot read is
+/// replaced by the newest readable backup, which the result names
+pub fn load_state_with_recovery() -> Result<LoadedState, String> {
     let path = get_state_file_path()
         .ok_or("Failed to get state file path".to_string())?;
     
+    load_state_recovering_at_path(&path)
+}
+
+fn load_state_recovering_at_path(path: &PathBuf) -> Result<LoadedState, String> {
+    match load_state_from_path(path) {
+        Ok(state) => Ok(LoadedState { state, restored_from: None }),
+        Err(e) if e.starts_with(UNSUPPORTED_STATE_VERSION) => {
+            let (backup_path, state) = load_latest_backup(&backup_dir_for(path)).ok_or(e)?;
+            Ok(LoadedState { state, restored_from: Some(backup_path) })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Load state from a file; a missing or empty file is a fresh default state
+fn load_state_from_path(path: &PathBuf) -> Result<AppState, String> {
     if !path.exists() {
         return Ok(AppState::default());
     }
     
     // Read compressed data
-    let mut file = File::open(&path)
+    let mut file = File::open(path)
         .map_err(|e| format!("Failed to open state file: {}", e))?;
     
     let mut compressed = Vec::new();
@@ -77,48 +176,143 @@ pub fn load_state() -> Result<AppState, String> {
     if compressed.is_empty() {
         return Ok(AppState::default());
     }
+    if compressed.starts_with(ENCRYPTED_STATE_MAGIC) {
+        return Err(STATE_FILE_ENCRYPTED.to_string());
+    }
     
-    // Decompress
-    let decompressed = zstd::decode_all(std::io::Cursor::new(compressed))
-        .map_err(|e| format!("Failed to decompress state: {}", e))?;
-    
-    // Deserialize
-    let state = bincode::deserialize(&decompressed)
-        .map_err(|e| format!("Failed to deserialize state: {}", e))?;
-    
+    let mut state = decode_state(&compressed)?;
+    state.migrate_legacy_messages();
     Ok(state)
 }
 
-/// Create backup of current state
+/// The newest backup in `dir` that can still be read, with its path
+fn load_latest_backup(dir: &std::path::Path) -> Option<(PathBuf, AppState)> {
+    let prefix = format!("{}.", STATE_FILE);
+    let mut backups: Vec<(u64, PathBuf)> = std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let timestamp = name.strip_prefix(&prefix)?.strip_suffix(".bak")?.parse().ok()?;
+            Some((timestamp, entry.path()))
+        })
+        .collect();
+    backups.sort_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+
+    backups.into_iter().find_map(|(_, path)| {
+        let data = std::fs::read(&path).ok()?;
+        let mut state = decode_state(&data).ok()?;
+        state.migrate_legacy_messages();
+        Some((path, state))
+    })
+}
+
+/// Create backup of the state file as it is on disk
 pub fn create_backup() -> Result<(), String> {
-    let state = load_state()?;
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+    
+    backup_state_file(&path, &backup_dir_for(&path))?;
+    Ok(())
+}
+
+/// Directory holding the backups of the state file at `path`
+fn backup_dir_for(path: &std::path::Path) -> PathBuf {
+    path.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Copy the state file at `path` byte for byte into a timestamped backup in `dir`, so an
+/// encrypted file stays encrypted, and record the backup time
+fn backup_state_file(path: &PathBuf, dir: &std::path::Path) -> Result<PathBuf, String> {
+    if !path.exists() {
+        return Err("No state file to back up".to_string());
+    }
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("Failed to read state file: {}", e))?;
+    write_backup(&contents, dir)
+}
+
+/// Write `contents` of the state file as a timestamped backup into `dir` and record the backup time
+fn write_backup(contents: &[u8], dir: &std::path::Path) -> Result<PathBuf, String> {
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
     
     let backup_name = format!("{}.{}.bak", STATE_FILE, timestamp);
-    let backup_path = PathBuf::from(&backup_name);
-    
-    // Serialize state
-    let serialized = bincode::serialize(&state)
-        .map_err(|e| format!("Failed to serialize backup: {}", e))?;
-    
-    // Compress
-    let compressed = zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL)
-        .map_err(|e| format!("Failed to compress backup: {}", e))?;
+    let backup_path = dir.join(&backup_name);
     
     // Write backup
     let mut file = File::create(&backup_path)
         .map_err(|e| format!("Failed to create backup: {}", e))?;
     
-    file.write_all(&compressed)
+    file.write_all(contents)
         .map_err(|e| format!("Failed to write backup: {}", e))?;
     
     // Clean old backups
     clean_old_backups()?;
     
-    Ok(())
+    LAST_BACKUP_MS.store(epoch_ms(SystemTime::now()), Ordering::SeqCst);
+    
+    Ok(backup_path)
+}
+
+/// Epoch milliseconds of the last manual or scheduled backup since startup
+pub fn get_last_backup_time() -> Option<u64> {
+    let last_backup_ms = LAST_BACKUP_MS.load(Ordering::SeqCst);
+    (last_backup_ms > 0).then_some(last_backup_ms)
+}
+
+/// Decides when a scheduled backup is due, with the clock passed in by the caller
+struct BackupScheduler {
+    last_backup: SystemTime,
+    last_fingerprint: Option<u64>,
+}
+
+impl BackupScheduler {
+    /// Start the schedule; the first backup is due one interval after `now`
+    fn new(now: SystemTime) -> Self {
+        Self {
+            last_backup: now,
+            last_fingerprint: None,
+        }
+    }
+
+    /// Back up the state file `contents` when `interval_hours` have elapsed and the file
+    /// changed since the last backup. Returns whether a backup was made
+    fn tick<F>(&mut self, now: SystemTime, interval_hours: Option<u32>, contents: &[u8], backup: F) -> Result<bool, String>
+    where
+        F: FnOnce(&[u8]) -> Result<(), String>,
+    {
+        let Some(hours) = interval_hours.filter(|h| *h > 0) else {
+            return Ok(false);
+        };
+        let interval = Duration::from_secs(u64::from(hours) * 3600);
+        if now.duration_since(self.last_backup).unwrap_or(Duration::ZERO) < interval {
+            return Ok(false);
+        }
+
+        let fingerprint = file_fingerprint(contents);
+        if self.last_fingerprint == Some(fingerprint) {
+            return Ok(false);
+        }
+
+        backup(contents)?;
+        self.last_backup = now;
+        self.last_fingerprint = Some(fingerprint);
+        Ok(true)
+    }
+}
+
+/// Hash of the state file contents, used to detect changes between backups
+fn file_fingerprint(contents: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
 }
 
 /// Clean old backup files
@@ -152,50 +346,551 @@ pub fn export_state_json() -> Result<String, String> {
 }
 
 /// Import state from JSON format
+/// The replaced state file is kept in the undo slot
 pub fn import_state_json(json: String) -> Result<(), String> {
-    let state: AppState = serde_json::from_str(&json)
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+    
+    import_state_json_at_path(&json, &path)
+}
+
+fn import_state_json_at_path(json: &str, path: &PathBuf) -> Result<(), String> {
+    let mut state: AppState = serde_json::from_str(json)
         .map_err(|e| format!("Failed to deserialize state from JSON: {}", e))?;
+    state.migrate_legacy_messages();
     
-    save_state(&state)?;
-    Ok(())
+    snapshot_for_undo(path)?;
+    save_state_to_path(&state, path)
+}
+
+/// Undo slot next to the state file, holding the file as it was before the last
+/// import or clear; an empty slot file stands for "no state file"
+fn undo_slot_path(path: &std::path::Path) -> PathBuf {
+    let mut undo = path.as_os_str().to_owned();
+    undo.push(".undo");
+    PathBuf::from(undo)
+}
+
+/// Copy the state file into the undo slot, replacing the previous snapshot
+fn snapshot_for_undo(path: &PathBuf) -> Result<(), String> {
+    let current = if path.exists() {
+        std::fs::read(path).map_err(|e| format!("Failed to read state file: {}", e))?
+    } else {
+        Vec::new()
+    };
+    write_file_atomically(&undo_slot_path(path), &current)
+}
+
+/// Restore the state file saved before the last import or clear
+/// The slot is consumed, so a second undo fails
+pub fn undo_last_state_operation() -> Result<(), String> {
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+    
+    undo_last_state_operation_at_path(&path)
+}
+
+fn undo_last_state_operation_at_path(path: &PathBuf) -> Result<(), String> {
+    let undo_path = undo_slot_path(path);
+    if !undo_path.exists() {
+        return Err("Nothing to undo".to_string());
+    }
+    
+    let snapshot = std::fs::read(&undo_path)
+        .map_err(|e| format!("Failed to read undo slot: {}", e))?;
+    if snapshot.is_empty() {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove state file: {}", e))?;
+        }
+    } else {
+        write_file_atomically(path, &snapshot)?;
+    }
+    
+    std::fs::remove_file(&undo_path)
+        .map_err(|e| format!("Failed to remove undo slot: {}", e))
+}
+
+/// Ids of entities added, removed or changed between two states
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// Differences between the current state and another one, by entity id
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateDiff {
+    pub sessions: EntityDiff,
+    pub providers: EntityDiff,
+    pub models: EntityDiff,
+    pub skills: EntityDiff,
+}
+
+/// Compare `current` against `other`; "added" entities exist only in `other`
+pub fn diff_states(current: &AppState, other_json: &str) -> Result<StateDiff, String> {
+    let other: AppState = serde_json::from_str(other_json)
+        .map_err(|e| format!("Failed to deserialize state from JSON: {}", e))?;
+
+    Ok(StateDiff {
+        sessions: diff_entities(current.sessions.iter(), other.sessions.iter())?,
+        providers: diff_entities(
+            current.providers.iter().map(|p| (&p.id, p)),
+            other.providers.iter().map(|p| (&p.id, p)),
+        )?,
+        models: diff_entities(
+            current.models.iter().map(|m| (&m.id, m)),
+            other.models.iter().map(|m| (&m.id, m)),
+        )?,
+        skills: diff_entities(
+            current.skills.iter().map(|s| (&s.id, s)),
+            other.skills.iter().map(|s| (&s.id, s)),
+        )?,
+    })
+}
+
+/// Diff two collections of entities keyed by id, comparing their JSON forms
+fn diff_entities<'a, T: Serialize + 'a>(
+    current: impl Iterator<Item = (&'a String, &'a T)>,
+    other: impl Iterator<Item = (&'a String, &'a T)>,
+) -> Result<EntityDiff, String> {
+    let to_json = |entity: &T| serde_json::to_value(entity)
+        .map_err(|e| format!("Failed to serialize state to JSON: {}", e));
+
+    let current: std::collections::BTreeMap<&String, serde_json::Value> = current
+        .map(|(id, entity)| Ok((id, to_json(entity)?)))
+        .collect::<Result<_, String>>()?;
+    let other: std::collections::BTreeMap<&String, serde_json::Value> = other
+        .map(|(id, entity)| Ok((id, to_json(entity)?)))
+        .collect::<Result<_, String>>()?;
+
+    let mut diff = EntityDiff::default();
+    for (id, value) in &other {
+        match current.get(id) {
+            None => diff.added.push((*id).clone()),
+            Some(existing) if existing != value => diff.changed.push((*id).clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = current.keys()
+        .filter(|id| !other.contains_key(*id))
+        .map(|id| (*id).clone())
+        .collect();
+    Ok(diff)
 }
 
 /// Clear all state data
+/// The removed state file is kept in the undo slot
 pub fn clear_state() -> Result<(), String> {
     let path = get_state_file_path()
         .ok_or("Failed to get state file path".to_string())?;
     
+    clear_state_at_path(&path)
+}
+
+fn clear_state_at_path(path: &PathBuf) -> Result<(), String> {
+    snapshot_for_undo(path)?;
     if path.exists() {
-        std::fs::remove_file(&path)
+        std::fs::remove_file(path)
             .map_err(|e| format!("Failed to remove state file: {}", e))?;
     }
     
     Ok(())
 }
 
+/// Serialize and compress state into the on-disk payload, behind the format header
+fn encode_state(state: &AppState) -> Result<Vec<u8>, String> {
+    let serialized = bincode::serialize(state)
+        .map_err(|e| format!("Failed to serialize state: {}", e))?;
+    let compressed = zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL)
+        .map_err(|e| format!("Failed to compress state: {}", e))?;
+
+    let mut payload = Vec::with_capacity(STATE_FORMAT_MAGIC.len() + 2 + compressed.len());
+    payload.extend_from_slice(STATE_FORMAT_MAGIC);
+    payload.extend_from_slice(&STATE_FORMAT_VERSION.to_le_bytes());
+    payload.extend_from_slice(&compressed);
+    Ok(payload)
+}
+
+/// Check the format header of an on-disk payload and return its format version and the
+/// compressed state after it. Payloads saved before the header existed are bare zstd
+/// frames and are reported as format 0
+fn strip_state_header(payload: &[u8]) -> Result<(u16, &[u8]), String> {
+    if payload.starts_with(ZSTD_FRAME_MAGIC) {
+        return Ok((0, payload));
+    }
+    let Some(rest) = payload.strip_prefix(STATE_FORMAT_MAGIC) else {
+        return Err(format!("{}: the file is not a Pixel-Client state file", INCOMPATIBLE_STATE_FORMAT));
+    };
+    let (version, compressed) = match rest {
+        [low, high, compressed @ ..] => (u16::from_le_bytes([*low, *high]), compressed),
+        _ => return Err(format!("{}: the header is truncated", INCOMPATIBLE_STATE_FORMAT)),
+    };
+    if version == 0 || version > STATE_FORMAT_VERSION {
+        return Err(format!(
+            "{}: the file uses format version {}, this version reads {}",
+            UNSUPPORTED_STATE_VERSION, version, STATE_FORMAT_VERSION
+        ));
+    }
+    Ok((version, compressed))
+}
+
+/// Decompress and deserialize an on-disk payload
+/// Payloads of older formats are read with the structs of their format and migrated
+fn decode_state(payload: &[u8]) -> Result<AppState, String> {
+    let (version, compressed) = strip_state_header(payload)?;
+    let decompressed = zstd::decode_all(std::io::Cursor::new(compressed))
+        .map_err(|e| format!("Failed to decompress state: {}", e))?;
+    match version {
+        0 => legacy_state::decode_v0(&decompressed),
+        _ => bincode::deserialize(&decompressed)
+            .map_err(|e| format!("Failed to deserialize state: {}", e)),
+    }
+}
+
+/// Derive the state encryption key from a secret and salt
+fn derive_state_key(secret: &str, salt: &[u8]) -> Result<LessSafeKey, String> {
+    let iterations = NonZeroU32::new(KEY_DERIVATION_ITERATIONS)
+        .ok_or("Invalid key derivation iterations")?;
+    let mut key_bytes = [0u8; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, secret.as_bytes(), &mut key_bytes);
+
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key_bytes)
+        .map_err(|_| "Failed to create encryption key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt a payload as `magic | salt | nonce | ciphertext+tag`
+fn encrypt_state_bytes(plain: &[u8], secret: &str) -> Result<Vec<u8>, String> {
+    if secret.is_empty() {
+        return Err("Encryption secret cannot be empty".to_string());
+    }
+
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; KEY_SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt).map_err(|_| "Failed to generate salt".to_string())?;
+    rng.fill(&mut nonce).map_err(|_| "Failed to generate nonce".to_string())?;
+
+    let key = derive_state_key(secret, &salt)?;
+    let mut ciphertext = plain.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(ENCRYPTED_STATE_MAGIC), &mut ciphertext)
+        .map_err(|_| "Failed to encrypt state".to_string())?;
+
+    let mut output = Vec::with_capacity(ENCRYPTED_STATE_MAGIC.len() + KEY_SALT_LEN + NONCE_LEN + ciphertext.len());
+    output.extend_from_slice(ENCRYPTED_STATE_MAGIC);
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Decrypt a payload written by `encrypt_state_bytes`, verifying the secret
+fn decrypt_state_bytes(data: &[u8], secret: &str) -> Result<Vec<u8>, String> {
+    let body = data.strip_prefix(ENCRYPTED_STATE_MAGIC)
+        .ok_or("State file is not encrypted")?;
+    if body.len() < KEY_SALT_LEN + NONCE_LEN {
+        return Err("Encrypted state file is truncated".to_string());
+    }
+
+    let (salt, rest) = body.split_at(KEY_SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| "Invalid nonce in state file".to_string())?;
+
+    let key = derive_state_key(secret, salt)?;
+    let mut buffer = ciphertext.to_vec();
+    let plain = key.open_in_place(nonce, Aad::from(ENCRYPTED_STATE_MAGIC), &mut buffer)
+        .map_err(|_| "Invalid secret or corrupted state file".to_string())?;
+    Ok(plain.to_vec())
+}
+
+/// Save state to the default path encrypted with a secret
+pub fn save_encrypted_state(state: &AppState, secret: &str) -> Result<(), String> {
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+
+    save_encrypted_state_to_path(state, &path, secret)
+}
+
+/// Load state from the default path encrypted with a secret
+pub fn load_encrypted_state(secret: &str) -> Result<AppState, String> {
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+
+    if !path.exists() {
+        return Ok(AppState::default());
+    }
+    load_encrypted_state_from_path(&path, secret)
+}
+
+/// Save state encrypted with a secret
+fn save_encrypted_state_to_path(state: &AppState, path: &PathBuf, secret: &str) -> Result<(), String> {
+    let encrypted = encrypt_state_bytes(&encode_state(state)?, secret)?;
+    write_file_atomically(path, &encrypted)?;
+    LAST_SAVE_MS.store(epoch_ms(SystemTime::now()), Ordering::SeqCst);
+    Ok(())
+}
+
+/// Load state encrypted with a secret
+fn load_encrypted_state_from_path(path: &PathBuf, secret: &str) -> Result<AppState, String> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Failed to read state file: {}", e))?;
+    decode_state(&decrypt_state_bytes(&data, secret)?)
+}
+
+/// Whether the file at `path` starts with the encryption header
+fn is_encrypted_state_file(path: &PathBuf) -> bool {
+    let mut header = [0u8; 6];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| header == ENCRYPTED_STATE_MAGIC)
+        .unwrap_or(false)
+}
+
+/// Write via a temp file and rename so the original is never left half-written
+fn write_file_atomically(path: &PathBuf, data: &[u8]) -> Result<(), String> {
+    let mut temp_name = path.file_name()
+        .ok_or("Invalid state file path")?
+        .to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = File::create(&temp_path)
+        .map_err(|e| format!("Failed to create temp state file: {}", e))?;
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp_path);
+            format!("Failed to write temp state file: {}", e)
+        })?;
+
+    std::fs::rename(&temp_path, path)
+        .map_err(|e| format!("Failed to replace state file: {}", e))
+}
+
+/// Re-encrypt the state file with a new secret after verifying the old one
+pub fn rekey_state(old_secret: &str, new_secret: &str) -> Result<(), String> {
+    let path = get_state_file_path()
+        .ok_or("Failed to get state file path".to_string())?;
+
+    rekey_state_at_path(&path, old_secret, new_secret)
+}
+
+fn rekey_state_at_path(path: &PathBuf, old_secret: &str, new_secret: &str) -> Result<(), String> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Failed to read state file: {}", e))?;
+    let plain = decrypt_state_bytes(&data, old_secret)?;
+
+    // Make sure the payload is a valid state before replacing the file
+    decode_state(&plain)?;
+
+    let encrypted = encrypt_state_bytes(&plain, new_secret)?;
+    write_file_atomically(path, &encrypted)
+}
+
+/// Per-session file information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFileInfo {
+    pub session_id: String,
+    pub size: u64,
+    pub modified_epoch_ms: u64,
+}
+
+/// Get the per-session files directory
+fn get_sessions_dir() -> PathBuf {
+    PathBuf::from(SESSIONS_DIR)
+}
+
+/// Path of a session's file; rejects ids that could escape the directory
+fn session_file_path(dir: &std::path::Path, session_id: &str) -> Result<PathBuf, String> {
+    if session_id.is_empty() || session_id.contains(['/', '\\']) || session_id.contains("..") {
+        return Err(format!("Invalid session id '{}'", session_id));
+    }
+    Ok(dir.join(format!("{}.{}", session_id, SESSION_FILE_EXT)))
+}
+
+/// Persist a single session to its own file
+pub fn save_session_file(session: &ChatSession) -> Result<(), String> {
+    save_session_file_in(&get_sessions_dir(), session)
+}
+
+fn save_session_file_in(dir: &std::path::Path, session: &ChatSession) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create sessions directory: {}", e))?;
+
+    let serialized = bincode::serialize(session)
+        .map_err(|e| format!("Failed to serialize session: {}", e))?;
+    let compressed = zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL)
+        .map_err(|e| format!("Failed to compress session: {}", e))?;
+
+    write_file_atomically(&session_file_path(dir, &session.id)?, &compressed)
+}
+
+/// List persisted session files
+pub fn list_session_files() -> Result<Vec<SessionFileInfo>, String> {
+    list_session_files_in(&get_sessions_dir())
+}
+
+fn list_session_files_in(dir: &std::path::Path) -> Result<Vec<SessionFileInfo>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read sessions directory: {}", e))?
+    {
+        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SESSION_FILE_EXT) {
+            continue;
+        }
+
+        let metadata = entry.metadata()
+            .map_err(|e| format!("Failed to read session file metadata: {}", e))?;
+        files.push(SessionFileInfo {
+            session_id: path.file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            size: metadata.len(),
+            modified_epoch_ms: metadata.modified().map(epoch_ms).unwrap_or(0),
+        });
+    }
+
+    files.sort_by_key(|f| std::cmp::Reverse(f.modified_epoch_ms));
+    Ok(files)
+}
+
+/// Delete a session's file; returns whether a file was removed
+pub fn delete_session_file(session_id: &str) -> Result<bool, String> {
+    delete_session_file_in(&get_sessions_dir(), session_id)
+}
+
+fn delete_session_file_in(dir: &std::path::Path, session_id: &str) -> Result<bool, String> {
+    let path = session_file_path(dir, session_id)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    std::fs::remove_file(&path)
+        .map_err(|e| format!("Failed to delete session file: {}", e))?;
+    Ok(true)
+}
+
+/// Get the time of the last successful manual or automatic save
+pub fn get_last_save_info(auto_save_enabled: bool) -> LastSaveInfo {
+    let last_save_ms = LAST_SAVE_MS.load(Ordering::SeqCst);
+    let last_save_epoch_ms = (last_save_ms > 0).then_some(last_save_ms);
+    let seconds_ago = last_save_epoch_ms
+        .map(|ms| epoch_ms(SystemTime::now()).saturating_sub(ms) / 1000);
+
+    LastSaveInfo {
+        last_save_epoch_ms,
+        seconds_ago,
+        auto_save_enabled,
+    }
+}
+
 /// Persistence service wrapper for auto-save
+/// The in-memory state mirrors the state file: it is loaded at startup and replaced by
+/// every state the frontend loads or saves, so saves made from memory never write back
+/// an older state than the file holds
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct PersistenceService {
     state: Arc<RwLock<AppState>>,
+    state_path: PathBuf,
     last_save: Arc<RwLock<SystemTime>>,
     auto_save_enabled: Arc<RwLock<bool>>,
+    bulk_depth: Arc<AtomicUsize>,
+    save_count: Arc<AtomicU64>,
+    /// Memory holds the persisted state; until then nothing is written from it
+    loaded: Arc<AtomicBool>,
+    /// A frontend save arrived during a bulk operation and is written when it ends
+    save_deferred: Arc<AtomicBool>,
 }
 
 #[allow(dead_code)]
 impl PersistenceService {
     pub fn new(state: Arc<RwLock<AppState>>) -> Self {
+        Self::with_path(state, get_state_file_path().unwrap_or_else(|| PathBuf::from(STATE_FILE)))
+    }
+
+    /// Create a service that saves to a custom path
+    pub fn with_path(state: Arc<RwLock<AppState>>, state_path: PathBuf) -> Self {
         Self {
             state,
+            state_path,
             last_save: Arc::new(RwLock::new(SystemTime::UNIX_EPOCH)),
             auto_save_enabled: Arc::new(RwLock::new(true)),
+            bulk_depth: Arc::new(AtomicUsize::new(0)),
+            save_count: Arc::new(AtomicU64::new(0)),
+            loaded: Arc::new(AtomicBool::new(false)),
+            save_deferred: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Read the state file into memory, replacing what was there. Returns the backup the
+    /// state came from when the file had to be replaced by one
+    /// On failure memory is left as it was and saves from it stay refused
+    pub fn load(&self) -> Result<Option<PathBuf>, String> {
+        let result = load_state_recovering_at_path(&self.state_path);
+        let loaded = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.loaded.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        };
+        *self.state.write().map_err(|e| format!("Write lock error: {}", e))? = loaded.state;
+        self.loaded.store(true, Ordering::SeqCst);
+        Ok(loaded.restored_from)
+    }
+
+    /// Take `state` as the current state without writing it, e.g. after the frontend
+    /// loaded or saved it itself
+    pub fn replace_state(&self, state: AppState) -> Result<(), String> {
+        *self.state.write().map_err(|e| format!("Write lock error: {}", e))? = state;
+        self.loaded.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Carry the settings the backend manages over from memory into a state sent by the
+    /// frontend, once memory holds the persisted state
+    pub fn keep_backend_settings(&self, state: &mut AppState) -> Result<(), String> {
+        if self.loaded.load(Ordering::SeqCst) {
+            let current = self.state.read().map_err(|e| format!("Read lock error: {}", e))?;
+            state.config.keep_backend_settings(&current.config);
+        }
+        Ok(())
+    }
+
+    /// Save a state sent by the frontend; during a bulk operation it is held in memory
+    /// and written once when the operation ends
+    pub fn save_state(&self, mut state: AppState) -> Result<(), String> {
+        self.keep_backend_settings(&mut state)?;
+        self.replace_state(state)?;
+        if self.is_bulk_operation_active() {
+            self.save_deferred.store(true, Ordering::SeqCst);
+            return Ok(());
         }
+        self.save_now()
+    }
+
+    /// Current in-memory state
+    pub fn snapshot(&self) -> Result<AppState, String> {
+        self.state.read()
+            .map(|state| state.clone())
+            .map_err(|e| format!("Read lock error: {}", e))
     }
 
     /// Check if auto-save is needed and perform save
     pub fn check_and_save(&self) -> Result<(), String> {
         let auto_save = *self.auto_save_enabled.read().map_err(|e| format!("Read lock error: {}", e))?;
-        if !auto_save {
+        if !auto_save || self.is_bulk_operation_active() {
             return Ok(());
         }
 
@@ -203,14 +898,97 @@ impl PersistenceService {
         let now = SystemTime::now();
 
         if now.duration_since(last_save).unwrap_or(Duration::ZERO) >= AUTO_SAVE_INTERVAL {
-            let state = self.state.read().map_err(|e| format!("Read lock error: {}", e))?.clone();
-            save_state(&state)?;
-            *self.last_save.write().map_err(|e| format!("Write lock error: {}", e))? = now;
+            self.save_now()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether memory may be written over the state file: it must hold the persisted
+    /// state, and an encrypted file must not be replaced by a plain one
+    fn ensure_writable(&self) -> Result<(), String> {
+        if !self.loaded.load(Ordering::SeqCst) {
+            return Err(STATE_NOT_LOADED.to_string());
         }
+        if is_encrypted_state_file(&self.state_path) {
+            return Err("State file is encrypted; save it with its secret".to_string());
+        }
+        Ok(())
+    }
 
+    /// Save the current state immediately
+    pub fn save_now(&self) -> Result<(), String> {
+        self.ensure_writable()?;
+        let state = self.state.read().map_err(|e| format!("Read lock error: {}", e))?.clone();
+        save_state_to_path(&state, &self.state_path)?;
+        *self.last_save.write().map_err(|e| format!("Write lock error: {}", e))? = SystemTime::now();
+        self.save_count.fetch_add(1, Ordering::SeqCst);
         Ok(())
     }
 
+    /// Write settings the backend changed in memory to the state file
+    /// An encrypted file cannot be written without its secret, so the settings stay in
+    /// memory and go out with the frontend's next encrypted save
+    pub fn persist_settings(&self) -> Result<(), String> {
+        if is_encrypted_state_file(&self.state_path) {
+            return Ok(());
+        }
+        self.save_now()
+    }
+
+    /// Rewrite the state file from memory right away, after backing up the file as it was,
+    /// so space freed by deletions is reclaimed without waiting for auto-save
+    pub fn compact(&self) -> Result<CompactionResult, String> {
+        self.ensure_writable()?;
+        let size_before = std::fs::metadata(&self.state_path).map(|m| m.len()).unwrap_or(0);
+        let backup_path = backup_state_file(&self.state_path, &backup_dir_for(&self.state_path))?;
+        self.save_now()?;
+
+        let size_after = std::fs::metadata(&self.state_path)
+            .map_err(|e| format!("Failed to get state file metadata: {}", e))?
+            .len();
+        Ok(CompactionResult {
+            size_before,
+            size_after,
+            backup_path: backup_path.to_string_lossy().to_string(),
+        })
+    }
+
+    /// Hold saves until the matching `end_bulk_operation`; returns the nesting depth
+    pub fn begin_bulk_operation(&self) -> usize {
+        self.bulk_depth.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Leave a bulk operation; the outermost one writes the last save held during it, if
+    /// any. Returns the remaining depth
+    pub fn end_bulk_operation(&self) -> Result<usize, String> {
+        let remaining = self.bulk_depth
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| depth.checked_sub(1))
+            .map_err(|_| "No bulk operation in progress".to_string())?
+            - 1;
+
+        if remaining == 0 && self.save_deferred.swap(false, Ordering::SeqCst) {
+            self.save_now()?;
+        }
+
+        Ok(remaining)
+    }
+
+    /// Whether a bulk operation is holding saves
+    pub fn is_bulk_operation_active(&self) -> bool {
+        self.bulk_depth.load(Ordering::SeqCst) > 0
+    }
+
+    /// Number of saves performed by this service
+    pub fn save_count(&self) -> u64 {
+        self.save_count.load(Ordering::SeqCst)
+    }
+
+    /// Whether auto-save is currently enabled
+    pub fn is_auto_save_enabled(&self) -> bool {
+        self.auto_save_enabled.read().map(|guard| *guard).unwrap_or(false)
+    }
+
     /// Enable or disable auto-save
     pub fn set_auto_save(&self, enabled: bool) {
         if let Ok(mut guard) = self.auto_save_enabled.write() {
@@ -218,36 +996,39 @@ impl PersistenceService {
         }
         // If write fails, just ignore
     }
+
+    /// Start the background task backing up the state file every `config.backup_interval_hours`
+    /// The file is copied as it is on disk, so an encrypted file is backed up encrypted
+    pub fn start_backup_schedule(&self) {
+        let state = self.state.clone();
+        let state_path = self.state_path.clone();
+        let backup_dir = backup_dir_for(&self.state_path);
+
+        std::thread::spawn(move || {
+            let mut scheduler = BackupScheduler::new(SystemTime::now());
+            loop {
+                std::thread::sleep(BACKUP_CHECK_INTERVAL);
+                let Ok(interval_hours) = state.read().map(|s| s.config.backup_interval_hours) else {
+                    continue;
+                };
+                let Ok(contents) = std::fs::read(&state_path) else {
+                    continue;
+                };
+                let _ = scheduler.tick(SystemTime::now(), interval_hours, &contents, |c| write_backup(c, &backup_dir).map(|_| ()));
+            }
+        });
+    }
 }
 
 // Helper functions for testing with custom paths
 #[cfg(test)]
 fn save_state_at_path(state: &AppState, path: &PathBuf) -> Result<(), String> {
-    let serialized = bincode::serialize(state)
-        .map_err(|e| format!("Failed to serialize: {}", e))?;
-    let compressed = zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL)
-        .map_err(|e| format!("Failed to compress: {}", e))?;
-    let mut file = File::create(path).map_err(|e| format!("Failed to create: {}", e))?;
-    file.write_all(&compressed).map_err(|e| format!("Failed to write: {}", e))?;
-    Ok(())
+    save_state_to_path(state, path)
 }
 
 #[cfg(test)]
 fn load_state_at_path(path: &PathBuf) -> Result<AppState, String> {
-    if !path.exists() {
-        return Ok(AppState::default());
-    }
-    let mut file = File::open(path).map_err(|e| format!("Failed to open: {}", e))?;
-    let mut compressed = Vec::new();
-    file.read_to_end(&mut compressed).map_err(|e| format!("Failed to read: {}", e))?;
-    if compressed.is_empty() {
-        return Ok(AppState::default());
-    }
-    let decompressed = zstd::decode_all(std::io::Cursor::new(compressed))
-        .map_err(|e| format!("Failed to decompress: {}", e))?;
-    let state = bincode::deserialize(&decompressed)
-        .map_err(|e| format!("Failed to deserialize: {}", e))?;
-    Ok(state)
+    load_state_from_path(path)
 }
 
 #[cfg(test)]
@@ -301,4 +1082,472 @@ mod tests {
 
         assert_eq!(imported.theme, "json_test");
     }
+
+    #[test]
+    fn test_last_save_updates_after_save() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+
+        save_state_at_path(&AppState::default(), &state_path).unwrap();
+        let first = get_last_save_info(true).last_save_epoch_ms.unwrap();
+
+        std::thread::sleep(Duration::from_millis(5));
+        save_state_at_path(&AppState::default(), &state_path).unwrap();
+        let info = get_last_save_info(true);
+
+        assert!(info.last_save_epoch_ms.unwrap() > first);
+        assert_eq!(info.seconds_ago, Some(0));
+        assert!(info.auto_save_enabled);
+    }
+
+    #[test]
+    fn test_state_format_header_checked_on_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let state = AppState { theme: "headered".to_string(), ..Default::default() };
+
+        save_state_at_path(&state, &state_path).unwrap();
+        let saved = std::fs::read(&state_path).unwrap();
+        assert!(saved.starts_with(b"PXST"));
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "headered");
+
+        let mut wrong_magic = saved.clone();
+        wrong_magic[..4].copy_from_slice(b"XXXX");
+        std::fs::write(&state_path, &wrong_magic).unwrap();
+        let err = load_state_at_path(&state_path).unwrap_err();
+        assert!(err.starts_with("Incompatible state format"), "{}", err);
+
+        // A file that is not a state file at all is reported, not replaced by a backup
+        write_backup(&encode_state(&state).unwrap(), temp_dir.path()).unwrap();
+        assert!(load_state_recovering_at_path(&state_path).unwrap_err().starts_with("Incompatible state format"));
+
+        let mut newer = saved;
+        newer[4..6].copy_from_slice(&(STATE_FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&state_path, &newer).unwrap();
+        assert!(load_state_at_path(&state_path).unwrap_err().contains(&format!("format version {}", STATE_FORMAT_VERSION + 1)));
+
+        // The newest readable backup stands in for a file from another format version
+        let loaded = load_state_recovering_at_path(&state_path).unwrap();
+        assert_eq!(loaded.state.theme, "headered");
+        let restored_from = loaded.restored_from.unwrap();
+        assert!(restored_from.to_string_lossy().ends_with(".bak"));
+        assert!(load_state_recovering_at_path(&temp_dir.path().join("missing.bin")).unwrap().restored_from.is_none());
+    }
+
+    #[test]
+    fn test_encrypted_state_file_is_reported_not_replaced() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let state = AppState { theme: "secret".to_string(), ..Default::default() };
+        write_backup(&encode_state(&AppState::default()).unwrap(), temp_dir.path()).unwrap();
+        save_encrypted_state_to_path(&state, &state_path, "secret").unwrap();
+
+        let err = load_state_recovering_at_path(&state_path).unwrap_err();
+        assert_eq!(err, STATE_FILE_ENCRYPTED);
+        assert_eq!(load_encrypted_state_from_path(&state_path, "secret").unwrap().theme, "secret");
+    }
+
+    #[test]
+    fn test_headerless_file_migrates_from_format_0() {
+        use legacy_state::{AppConfigV0, AppStateV0, ChatSessionV0, LLMProviderV0, MessageV0};
+
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let message = MessageV0 {
+            id: "msg_1".to_string(),
+            role: "user".to_string(),
+            content: "hello".to_string(),
+            timestamp: 42,
+            model_id: None,
+            attachments: Vec::new(),
+            images: Vec::new(),
+            reasoning_content: None,
+            reasoning_blocks: Vec::new(),
+            token_usage: Some(3),
+            is_deep_thinking: false,
+        };
+        let session = ChatSessionV0 {
+            id: "session_1".to_string(),
+            title: "Old chat".to_string(),
+            messages: vec![message],
+            created_at: 1,
+            updated_at: 2,
+            model_id: Some("model_1".to_string()),
+            deep_thinking_config: Default::default(),
+        };
+        let legacy = AppStateV0 {
+            config: AppConfigV0 {
+                theme: "light".to_string(),
+                language: "en".to_string(),
+                font_size: 14,
+                auto_save: false,
+                notifications: true,
+                active_model_id: Some("model_1".to_string()),
+                active_provider_id: None,
+            },
+            sessions: [(session.id.clone(), session)].into_iter().collect(),
+            current_session_id: Some("session_1".to_string()),
+            providers: vec![LLMProviderV0 {
+                id: "provider_1".to_string(),
+                name: "OpenAI".to_string(),
+                provider_type: "openai".to_string(),
+                base_url: "https://api.openai.com/v1".to_string(),
+                api_key: "sk-old".to_string(),
+                enabled: true,
+            }],
+            models: Vec::new(),
+            mcp_servers: Vec::new(),
+            skills: Vec::new(),
+            ace_config: Default::default(),
+            theme: "light".to_string(),
+            language: "en".to_string(),
+        };
+        let serialized = bincode::serialize(&legacy).unwrap();
+        assert!(bincode::deserialize::<AppState>(&serialized).is_err());
+        std::fs::write(&state_path, zstd::encode_all(std::io::Cursor::new(serialized), COMPRESSION_LEVEL).unwrap()).unwrap();
+
+        let loaded = load_state_at_path(&state_path).unwrap();
+        assert_eq!(loaded.config.font_size, 14);
+        assert!(!loaded.config.auto_save);
+        assert!(loaded.config.close_to_tray);
+        assert_eq!(loaded.current_session_id.as_deref(), Some("session_1"));
+        let session = &loaded.sessions["session_1"];
+        assert_eq!((session.title.as_str(), session.created_at, session.updated_at), ("Old chat", 1, 2));
+        assert_eq!(session.messages[0].content, "hello");
+        assert_eq!(session.messages[0].token_usage, Some(3));
+        assert!(session.token_budget.is_none());
+        assert_eq!(loaded.providers[0].api_key, "sk-old");
+        assert!(loaded.providers[0].custom_headers.is_empty());
+
+        // The next save writes the current format
+        save_state_at_path(&loaded, &state_path).unwrap();
+        assert!(std::fs::read(&state_path).unwrap().starts_with(STATE_FORMAT_MAGIC));
+        assert_eq!(load_state_at_path(&state_path).unwrap().sessions["session_1"].title, "Old chat");
+    }
+
+    #[test]
+    fn test_rekey_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let state = AppState {
+            theme: "encrypted".to_string(),
+            ..Default::default()
+        };
+
+        save_encrypted_state_to_path(&state, &state_path, "old secret").unwrap();
+        assert!(rekey_state_at_path(&state_path, "wrong secret", "new secret").is_err());
+
+        rekey_state_at_path(&state_path, "old secret", "new secret").unwrap();
+
+        let loaded = load_encrypted_state_from_path(&state_path, "new secret").unwrap();
+        assert_eq!(loaded.theme, "encrypted");
+        assert!(load_encrypted_state_from_path(&state_path, "old secret").is_err());
+        assert!(load_state_at_path(&state_path).is_err());
+        assert!(!temp_dir.path().join(format!("{}.tmp", STATE_FILE)).exists());
+    }
+
+    #[test]
+    fn test_session_files_list_and_delete() {
+        let temp_dir = TempDir::new().unwrap();
+        let sessions_dir = temp_dir.path().join(SESSIONS_DIR);
+
+        let first = ChatSession::new("session_a".to_string(), "First".to_string());
+        let second = ChatSession::new("session_b".to_string(), "Second".to_string());
+        save_session_file_in(&sessions_dir, &first).unwrap();
+        save_session_file_in(&sessions_dir, &second).unwrap();
+
+        let mut ids: Vec<String> = list_session_files_in(&sessions_dir).unwrap()
+            .into_iter()
+            .map(|f| f.session_id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["session_a", "session_b"]);
+        assert!(list_session_files_in(&sessions_dir).unwrap().iter().all(|f| f.size > 0));
+
+        assert!(delete_session_file_in(&sessions_dir, "session_a").unwrap());
+        assert!(!delete_session_file_in(&sessions_dir, "session_a").unwrap());
+        let remaining = list_session_files_in(&sessions_dir).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].session_id, "session_b");
+
+        assert!(delete_session_file_in(&sessions_dir, "../pixel_client_state").is_err());
+    }
+
+    #[test]
+    fn test_deleting_session_file_keeps_the_session() {
+        let temp_dir = TempDir::new().unwrap();
+        let sessions_dir = temp_dir.path().join(SESSIONS_DIR);
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let session = ChatSession::new("session_a".to_string(), "First".to_string());
+        let mut state = AppState::default();
+        state.sessions.insert(session.id.clone(), session.clone());
+        let persistence = PersistenceService::with_path(Arc::new(RwLock::new(AppState::default())), state_path.clone());
+        persistence.save_state(state).unwrap();
+
+        save_session_file_in(&sessions_dir, &session).unwrap();
+        assert!(delete_session_file_in(&sessions_dir, "session_a").unwrap());
+        persistence.save_now().unwrap();
+
+        assert!(persistence.snapshot().unwrap().sessions.contains_key("session_a"));
+        assert!(load_state_at_path(&state_path).unwrap().sessions.contains_key("session_a"));
+    }
+
+    #[test]
+    fn test_backup_schedule_waits_for_interval_and_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let hour = Duration::from_secs(3600);
+        let mut scheduler = BackupScheduler::new(start);
+        let backup_dir = temp_dir.path().join("backups");
+        std::fs::create_dir(&backup_dir).unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        save_encrypted_state_to_path(&AppState::default(), &state_path, "secret").unwrap();
+        let contents = std::fs::read(&state_path).unwrap();
+        let backup = |c: &[u8]| write_backup(c, &backup_dir).map(|_| ());
+
+        assert!(!scheduler.tick(start + hour / 2, Some(1), &contents, backup).unwrap());
+        assert!(scheduler.tick(start + hour, Some(1), &contents, backup).unwrap());
+        assert!(get_last_backup_time().is_some());
+        let backups = || std::fs::read_dir(&backup_dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(backups().len(), 1);
+
+        // The backup is the file as it was on disk, still encrypted
+        let backed_up = backups().remove(0);
+        assert_eq!(std::fs::read(&backed_up).unwrap(), contents);
+        assert_eq!(load_encrypted_state_from_path(&backed_up, "secret").unwrap().theme, "dark");
+
+        // Interval elapsed again but nothing changed
+        assert!(!scheduler.tick(start + hour * 3, Some(1), &contents, backup).unwrap());
+
+        let changed = encode_state(&AppState { theme: "changed".to_string(), ..Default::default() }).unwrap();
+        assert!(scheduler.tick(start + hour * 3, Some(1), &changed, backup).unwrap());
+
+        // Disabled schedule never backs up
+        let changed_again = encode_state(&AppState { theme: "changed again".to_string(), ..Default::default() }).unwrap();
+        assert!(!scheduler.tick(start + hour * 10, None, &changed_again, backup).unwrap());
+    }
+
+    #[test]
+    fn test_backup_copies_the_state_file_bytes() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        assert!(backup_state_file(&state_path, temp_dir.path()).is_err());
+
+        save_encrypted_state_to_path(&AppState::default(), &state_path, "secret").unwrap();
+        let backup_path = backup_state_file(&state_path, temp_dir.path()).unwrap();
+        assert_eq!(std::fs::read(&backup_path).unwrap(), std::fs::read(&state_path).unwrap());
+    }
+
+    #[test]
+    fn test_skill_output_schema_survives_encoding() {
+        let schema = serde_json::json!({ "type": "object", "required": ["total"] });
+        let mut state = AppState::default();
+        state.skills.push(crate::state::Skill {
+            output_schema: Some(schema.clone()),
+            ..Default::default()
+        });
+        state.skills.push(crate::state::Skill::default());
+
+        state.skill_runs.insert("skill_1".to_string(), vec![crate::state::SkillRun {
+            timestamp: 1,
+            input: serde_json::json!({ "a": 1 }),
+            output: serde_json::json!({ "total": 1 }),
+            success: true,
+            duration_ms: 2,
+            error: None,
+        }]);
+
+        let decoded = decode_state(&encode_state(&state).unwrap()).unwrap();
+        assert_eq!(decoded.skills[0].output_schema, Some(schema));
+        assert_eq!(decoded.skills[1].output_schema, None);
+        assert_eq!(decoded.skill_runs["skill_1"][0].input, serde_json::json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn test_diff_states_reports_added_and_changed() {
+        let mut current = AppState::default();
+        current.sessions.insert(
+            "session_1".to_string(),
+            ChatSession::new("session_1".to_string(), "Original".to_string()),
+        );
+
+        let mut other = current.clone();
+        other.sessions.get_mut("session_1").unwrap().title = "Renamed".to_string();
+        other.providers.push(crate::state::LLMProvider {
+            id: "provider_1".to_string(),
+            name: "OpenAI".to_string(),
+            provider_type: "openai".to_string(),
+            base_url: "https://api.openai.com/v1".to_string(),
+            api_key: String::new(),
+            enabled: true,
+            custom_headers: std::collections::HashMap::new(),
+            default_model_id: None,
+            supports_streaming: None,
+        });
+
+        let diff = diff_states(&current, &serde_json::to_string(&other).unwrap()).unwrap();
+        assert_eq!(diff.providers.added, vec!["provider_1"]);
+        assert_eq!(diff.sessions.changed, vec!["session_1"]);
+        assert!(diff.sessions.added.is_empty() && diff.sessions.removed.is_empty());
+        assert_eq!(diff.models, EntityDiff::default());
+
+        let reverse = diff_states(&other, &serde_json::to_string(&current).unwrap()).unwrap();
+        assert_eq!(reverse.providers.removed, vec!["provider_1"]);
+    }
+
+    #[test]
+    fn test_bulk_operation_saves_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let service = PersistenceService::with_path(Arc::new(RwLock::new(AppState::default())), state_path.clone());
+
+        // Nested bulk operations
+        assert_eq!(service.begin_bulk_operation(), 1);
+        assert_eq!(service.begin_bulk_operation(), 2);
+
+        for i in 0..5 {
+            let state = AppState { theme: format!("theme_{}", i), ..Default::default() };
+            service.save_state(state).unwrap();
+        }
+        assert!(!state_path.exists());
+
+        assert_eq!(service.end_bulk_operation().unwrap(), 1);
+        assert_eq!(service.save_count(), 0);
+        assert_eq!(service.end_bulk_operation().unwrap(), 0);
+        assert_eq!(service.save_count(), 1);
+        assert!(service.end_bulk_operation().is_err());
+
+        let loaded = load_state_at_path(&state_path).unwrap();
+        assert_eq!(loaded.theme, "theme_4");
+
+        // A bulk operation without saves in it writes nothing
+        service.begin_bulk_operation();
+        service.end_bulk_operation().unwrap();
+        assert_eq!(service.save_count(), 1);
+    }
+
+    #[test]
+    fn test_service_never_writes_state_it_did_not_load() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let on_disk = AppState { theme: "on_disk".to_string(), ..Default::default() };
+        save_state_at_path(&on_disk, &state_path).unwrap();
+
+        let service = PersistenceService::with_path(Arc::new(RwLock::new(AppState::default())), state_path.clone());
+        assert_eq!(service.save_now().unwrap_err(), STATE_NOT_LOADED);
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "on_disk");
+
+        assert!(service.load().unwrap().is_none());
+        assert_eq!(service.snapshot().unwrap().theme, "on_disk");
+        service.save_now().unwrap();
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "on_disk");
+
+        // An encrypted file is neither loaded nor replaced by a plain one
+        save_encrypted_state_to_path(&on_disk, &state_path, "secret").unwrap();
+        assert_eq!(service.load().unwrap_err(), STATE_FILE_ENCRYPTED);
+        assert_eq!(service.save_now().unwrap_err(), STATE_NOT_LOADED);
+        service.replace_state(on_disk).unwrap();
+        assert!(service.save_now().is_err());
+        assert_eq!(load_encrypted_state_from_path(&state_path, "secret").unwrap().theme, "on_disk");
+    }
+
+    #[test]
+    fn test_window_state_persists_across_frontend_saves() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let state = Arc::new(RwLock::new(AppState::default()));
+        let service = PersistenceService::with_path(state.clone(), state_path.clone());
+        service.load().unwrap();
+        let frontend_copy = service.snapshot().unwrap();
+
+        // Recorded on move/resize, written on close
+        let geometry = crate::state::WindowState { x: 10, y: 20, width: 800, height: 600, maximized: false };
+        state.write().unwrap().config.window_state = Some(geometry);
+        service.persist_settings().unwrap();
+        assert_eq!(load_state_at_path(&state_path).unwrap().config.window_state, Some(geometry));
+
+        // A later save of the frontend's older copy keeps it
+        service.save_state(frontend_copy).unwrap();
+        let restarted = PersistenceService::with_path(Arc::new(RwLock::new(AppState::default())), state_path.clone());
+        restarted.load().unwrap();
+        assert_eq!(restarted.snapshot().unwrap().config.window_state, Some(geometry));
+
+        // An encrypted file is left for the frontend's next encrypted save
+        save_encrypted_state_to_path(&AppState::default(), &state_path, "secret").unwrap();
+        service.persist_settings().unwrap();
+        assert!(is_encrypted_state_file(&state_path));
+    }
+
+    #[test]
+    fn test_compact_shrinks_file_after_deleting_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let mut initial = AppState::default();
+        for i in 0..50 {
+            let mut session = ChatSession::new(format!("session_{}", i), format!("Session {}", i));
+            for j in 0..20 {
+                session.messages.push(crate::state::Message::new(
+                    format!("msg_{}_{}", i, j),
+                    "user".to_string(),
+                    format!("message {} of session {} with some filler text {}", j, i, i * j),
+                ));
+            }
+            initial.sessions.insert(session.id.clone(), session);
+        }
+        let state = Arc::new(RwLock::new(AppState::default()));
+        let service = PersistenceService::with_path(state.clone(), state_path.clone());
+        service.save_state(initial).unwrap();
+
+        state.write().unwrap().sessions.retain(|id, _| id == "session_0");
+        let result = service.compact().unwrap();
+
+        assert!(result.size_after < result.size_before);
+        assert_eq!(result.size_after, std::fs::metadata(&state_path).unwrap().len());
+        assert_eq!(load_state_at_path(&state_path).unwrap().sessions.len(), 1);
+        // The backup holds the file from before the deletions
+        let backup = std::fs::read(&result.backup_path).unwrap();
+        assert_eq!(backup.len() as u64, result.size_before);
+        assert_eq!(decode_state(&backup).unwrap().sessions.len(), 50);
+
+        // A service that never loaded the file leaves it alone
+        let unloaded = PersistenceService::with_path(Arc::new(RwLock::new(AppState::default())), state_path.clone());
+        assert_eq!(unloaded.compact().unwrap_err(), STATE_NOT_LOADED);
+        assert_eq!(load_state_at_path(&state_path).unwrap().sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_undo_restores_state_before_import_and_clear() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_path = temp_dir.path().join(STATE_FILE);
+        let original = AppState {
+            theme: "original".to_string(),
+            ..Default::default()
+        };
+        save_state_at_path(&original, &state_path).unwrap();
+
+        let imported = AppState {
+            theme: "imported".to_string(),
+            ..Default::default()
+        };
+        import_state_json_at_path(&export_state_to_json(&imported).unwrap(), &state_path).unwrap();
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "imported");
+
+        undo_last_state_operation_at_path(&state_path).unwrap();
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "original");
+        assert_eq!(undo_last_state_operation_at_path(&state_path).unwrap_err(), "Nothing to undo");
+
+        // Only the latest operation is kept
+        clear_state_at_path(&state_path).unwrap();
+        assert!(!state_path.exists());
+        undo_last_state_operation_at_path(&state_path).unwrap();
+        assert_eq!(load_state_at_path(&state_path).unwrap().theme, "original");
+
+        // Undoing an import into an empty location removes the imported file
+        let fresh_path = temp_dir.path().join("fresh.bin");
+        import_state_json_at_path(&export_state_to_json(&imported).unwrap(), &fresh_path).unwrap();
+        undo_last_state_operation_at_path(&fresh_path).unwrap();
+        assert!(!fresh_path.exists());
+    }
 }
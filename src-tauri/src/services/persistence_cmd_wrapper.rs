@@ -1,25 +1,47 @@
 // Persistence command wrappers for Tauri
 // These wrappers re-export the persistence functions as Tauri commands
 
-use crate::state::AppState;
+use tauri::{Emitter, State};
+use crate::state::{AppState, PixelState, SharedState};
 use crate::services::persistence::{
-    save_state as save_state_impl,
-    load_state as load_state_impl,
+    PersistenceService,
+    CompactionResult,
+    LastSaveInfo,
+    SessionFileInfo,
+    StateDiff,
     create_backup as create_backup_impl,
+    get_last_backup_time as get_last_backup_time_impl,
     get_state_size as get_state_size_impl,
     export_state_json as export_state_json_impl,
     import_state_json as import_state_json_impl,
+    diff_states as diff_states_impl,
     clear_state as clear_state_impl,
+    undo_last_state_operation as undo_last_state_operation_impl,
+    get_last_save_info as get_last_save_info_impl,
+    rekey_state as rekey_state_impl,
+    save_encrypted_state as save_encrypted_state_impl,
+    load_encrypted_state as load_encrypted_state_impl,
+    save_session_file as save_session_file_impl,
+    list_session_files as list_session_files_impl,
+    delete_session_file as delete_session_file_impl,
 };
 
+/// Save the state and keep it as the backend's copy; held while a bulk operation runs
 #[tauri::command]
-pub fn save_state(state: AppState) -> Result<(), String> {
-    save_state_impl(&state)
+pub fn save_state(persistence: State<'_, PersistenceService>, state: AppState) -> Result<(), String> {
+    persistence.save_state(state)
 }
 
+/// Load the state file into the backend's copy and return it; when it had to be replaced
+/// by a backup, emits `state:restored_from_backup` with the backup's path so the user can be told
 #[tauri::command]
-pub fn load_state() -> Result<AppState, String> {
-    load_state_impl()
+pub fn load_state(persistence: State<'_, PersistenceService>, app_state: State<'_, PixelState>) -> Result<AppState, String> {
+    if let Some(backup_path) = persistence.load()? {
+        let _ = app_state.app_handle.get().emit("state:restored_from_backup", &serde_json::json!({
+            "backupPath": backup_path.to_string_lossy(),
+        }));
+    }
+    persistence.snapshot()
 }
 
 #[tauri::command]
@@ -27,6 +49,11 @@ pub fn create_backup() -> Result<(), String> {
     create_backup_impl()
 }
 
+#[tauri::command]
+pub fn get_last_backup_time() -> Option<u64> {
+    get_last_backup_time_impl()
+}
+
 #[tauri::command]
 pub fn get_state_size() -> Result<u64, String> {
     get_state_size_impl()
@@ -38,11 +65,86 @@ pub fn export_state_json() -> Result<String, String> {
 }
 
 #[tauri::command]
-pub fn import_state_json(json: String) -> Result<(), String> {
-    import_state_json_impl(json)
+pub fn import_state_json(persistence: State<'_, PersistenceService>, json: String) -> Result<(), String> {
+    import_state_json_impl(json)?;
+    persistence.load().map(|_| ())
+}
+
+/// Compare the current state against another (e.g. before importing it)
+#[tauri::command]
+pub fn diff_states(shared_state: State<'_, SharedState>, other_json: String) -> Result<StateDiff, String> {
+    shared_state.read(|state| diff_states_impl(state, &other_json))
+}
+
+#[tauri::command]
+pub fn clear_state(persistence: State<'_, PersistenceService>) -> Result<(), String> {
+    clear_state_impl()?;
+    persistence.load().map(|_| ())
+}
+
+/// Restore the state file as it was before the last import or clear
+/// The restored file may be encrypted, in which case the backend's copy stays unloaded
+#[tauri::command]
+pub fn undo_last_state_operation(persistence: State<'_, PersistenceService>) -> Result<(), String> {
+    undo_last_state_operation_impl()?;
+    let _ = persistence.load();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_last_save_info(persistence: State<'_, PersistenceService>) -> LastSaveInfo {
+    get_last_save_info_impl(persistence.is_auto_save_enabled())
+}
+
+#[tauri::command]
+pub fn begin_bulk_operation(persistence: State<'_, PersistenceService>) -> usize {
+    persistence.begin_bulk_operation()
+}
+
+#[tauri::command]
+pub fn end_bulk_operation(persistence: State<'_, PersistenceService>) -> Result<usize, String> {
+    persistence.end_bulk_operation()
+}
+
+/// Rewrite the state file now (with a fresh backup), returning the before/after sizes
+#[tauri::command]
+pub fn compact_state(persistence: State<'_, PersistenceService>) -> Result<CompactionResult, String> {
+    persistence.compact()
+}
+
+#[tauri::command]
+pub fn save_state_encrypted(persistence: State<'_, PersistenceService>, mut state: AppState, secret: String) -> Result<(), String> {
+    persistence.keep_backend_settings(&mut state)?;
+    save_encrypted_state_impl(&state, &secret)?;
+    persistence.replace_state(state)
+}
+
+#[tauri::command]
+pub fn load_state_encrypted(persistence: State<'_, PersistenceService>, secret: String) -> Result<AppState, String> {
+    let state = load_encrypted_state_impl(&secret)?;
+    persistence.replace_state(state.clone())?;
+    Ok(state)
+}
+
+#[tauri::command]
+pub fn rekey_state(old_secret: String, new_secret: String) -> Result<(), String> {
+    rekey_state_impl(&old_secret, &new_secret)
+}
+
+#[tauri::command]
+pub fn save_session_file(shared_state: State<'_, SharedState>, session_id: String) -> Result<(), String> {
+    let session = shared_state.read(|state| state.sessions.get(&session_id).cloned())
+        .ok_or_else(|| format!("Session '{}' not found", session_id))?;
+    save_session_file_impl(&session)
+}
+
+#[tauri::command]
+pub fn list_session_files() -> Result<Vec<SessionFileInfo>, String> {
+    list_session_files_impl()
 }
 
+/// Delete a session's file; the conversation itself stays in memory and in the state file
 #[tauri::command]
-pub fn clear_state() -> Result<(), String> {
-    clear_state_impl()
+pub fn delete_session_file(session_id: String) -> Result<bool, String> {
+    delete_session_file_impl(&session_id)
 }
@@ -2,21 +2,61 @@
 //! Uses pulldown-cmark for Markdown parsing and syntect for code highlighting
 
 use pulldown_cmark::{Options, Parser, Event, Tag, CodeBlockKind, TagEnd};
-use syntect::html::start_highlighted_html_snippet;
+use syntect::easy::HighlightLines;
+use syntect::html::{start_highlighted_html_snippet, styled_line_to_highlighted_html, IncludeBackground};
 use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+use crate::state::ChatSession;
 
-/// Pre-loaded syntax definitions
-static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_nonewlines);
+/// Loaded syntax definitions; swapped by `reload_highlighter_assets`
+static SYNTAX_SET: Lazy<RwLock<SyntaxSet>> = Lazy::new(|| RwLock::new(SyntaxSet::load_defaults_nonewlines()));
 
-/// Pre-loaded themes
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+/// Loaded themes; swapped by `reload_highlighter_assets`
+static THEME_SET: Lazy<RwLock<ThemeSet>> = Lazy::new(|| RwLock::new(ThemeSet::load_defaults()));
+
+/// Whether the loaded sets include definitions from the assets directory
+static CUSTOM_ASSETS_LOADED: AtomicBool = AtomicBool::new(false);
+
+/// Directory holding custom `syntaxes/` (`.sublime-syntax`) and `themes/` (`.tmTheme`)
+/// Relative to the working directory, like the state file
+const HIGHLIGHTER_ASSETS_DIR: &str = "resources";
+
+fn syntax_set() -> RwLockReadGuard<'static, SyntaxSet> {
+    SYNTAX_SET.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn theme_set() -> RwLockReadGuard<'static, ThemeSet> {
+    THEME_SET.read().unwrap_or_else(PoisonError::into_inner)
+}
 
 /// Default theme name
 const DEFAULT_THEME: &str = "base16-ocean.dark";
 
+/// Inline styles for standalone session exports (code-block, theme and message classes)
+const SESSION_HTML_STYLES: &str = r#"
+body { margin: 0; padding: 2rem; background: #1e1e2e; color: #cdd6f4; font-family: -apple-system, "Segoe UI", Roboto, sans-serif; line-height: 1.6; }
+main { max-width: 860px; margin: 0 auto; }
+h1 { font-size: 1.5rem; border-bottom: 1px solid #45475a; padding-bottom: 0.5rem; }
+.message { margin: 1rem 0; padding: 1rem; border-radius: 8px; background: #313244; }
+.message-user { background: #45475a; }
+.message-role { font-size: 0.8rem; text-transform: uppercase; color: #a6adc8; margin-bottom: 0.5rem; }
+.code-block { margin: 0.75rem 0; border-radius: 6px; overflow-x: auto; background: #2b303b; }
+.code-block::before { content: attr(data-language-label); display: block; padding: 0.25rem 0.75rem; font-size: 0.75rem; color: #a6adc8; }
+pre.syntect, pre code { margin: 0; padding: 0.75rem; font-family: "JetBrains Mono", Consolas, monospace; font-size: 0.875rem; }
+.thinking-block { margin: 0.5rem 0; padding: 0.5rem 0.75rem; border-left: 3px solid #89b4fa; background: #1e1e2e; }
+.thinking-content { white-space: pre-wrap; color: #a6adc8; }
+blockquote { margin: 0; padding-left: 1rem; border-left: 3px solid #585b70; }
+table { border-collapse: collapse; }
+td { border: 1px solid #585b70; padding: 0.25rem 0.5rem; }
+a { color: #89b4fa; }
+"#;
+
 /// Language alias mappings for common names
 static LANGUAGE_ALIASES: Lazy<HashMap<String, String>> = Lazy::new(|| {
     let mut map = HashMap::new();
@@ -47,6 +87,202 @@ static LANGUAGE_ALIASES: Lazy<HashMap<String, String>> = Lazy::new(|| {
     map
 });
 
+/// Display names for code block badges, keyed by the languages `LANGUAGE_ALIASES` resolves to
+static LANGUAGE_DISPLAY_NAMES: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    HashMap::from([
+        ("javascript", "JavaScript"),
+        ("typescript", "TypeScript"),
+        ("python", "Python"),
+        ("rust", "Rust"),
+        ("ruby", "Ruby"),
+        ("yaml", "YAML"),
+        ("bash", "Bash"),
+        ("json", "JSON"),
+        ("html", "HTML"),
+        ("css", "CSS"),
+        ("c++", "C++"),
+        ("c", "C"),
+        ("java", "Java"),
+        ("kotlin", "Kotlin"),
+        ("go", "Go"),
+        ("sql", "SQL"),
+        ("xml", "XML"),
+        ("markdown", "Markdown"),
+        ("text", "Plain Text"),
+    ])
+});
+
+/// Friendly name of a code block language ("ts" -> "TypeScript")
+/// Unknown languages fall back to the highlighter's syntax name, then to the tag itself
+fn language_label(language: &str) -> String {
+    let lower = language.to_lowercase();
+    let resolved = LANGUAGE_ALIASES.get(&lower).map(String::as_str).unwrap_or(&lower);
+    if let Some(label) = LANGUAGE_DISPLAY_NAMES.get(resolved) {
+        return label.to_string();
+    }
+    match syntax_set().find_syntax_by_token(resolved) {
+        Some(syntax) => syntax.name.clone(),
+        None => language.to_string(),
+    }
+}
+
+/// Weighted markers that suggest a language, keyed by syntect token
+const LANGUAGE_MARKERS: &[(&str, &[(&str, u32)])] = &[
+    ("rust", &[("fn ", 2), ("let mut ", 3), ("impl ", 3), ("println!", 4), ("pub fn", 4), ("use std", 4), ("-> ", 1), ("&str", 3), ("::", 1), ("match ", 1)]),
+    ("python", &[("def ", 2), ("import ", 1), ("self.", 2), ("elif ", 4), ("print(", 2), ("None", 2), ("__init__", 4), ("    return ", 1), ("):\n", 3)]),
+    ("javascript", &[("function ", 2), ("const ", 2), ("=> ", 2), ("console.log", 4), ("require(", 3), ("===", 3), ("document.", 3), ("let ", 1)]),
+    ("go", &[("package ", 3), ("func ", 3), (":= ", 3), ("fmt.", 4)]),
+    ("java", &[("public class", 4), ("System.out", 4), ("public static void", 4), ("private ", 1)]),
+    ("c++", &[("#include", 3), ("std::", 3), ("cout", 3), ("int main", 2)]),
+    ("bash", &[("echo ", 2), ("fi\n", 3), ("then\n", 2), ("$1", 2), ("export ", 1)]),
+    ("html", &[("<html", 4), ("<div", 3), ("</", 1), ("<!DOCTYPE", 4)]),
+    ("css", &[("px;", 3), ("color:", 2), ("margin:", 2), ("{\n", 1)]),
+    ("sql", &[("SELECT ", 3), ("FROM ", 2), ("WHERE ", 2), ("INSERT INTO", 4), ("CREATE TABLE", 4)]),
+    ("ruby", &[("puts ", 3), ("do |", 4), ("end\n", 1), ("attr_accessor", 4)]),
+];
+
+/// Best-guess language of a code snippet
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeLanguageGuess {
+    pub language: String,
+    pub confidence: f32,
+}
+
+/// A syntax the highlighter knows, with the file extensions that select it
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageInfo {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+/// Highlighter syntaxes and the aliases resolved before looking them up
+#[derive(Debug, Clone, Serialize)]
+pub struct SupportedLanguages {
+    pub languages: Vec<LanguageInfo>,
+    pub aliases: std::collections::BTreeMap<String, String>,
+}
+
+/// List the syntaxes in `SYNTAX_SET`, sorted by name, and the `LANGUAGE_ALIASES` map
+#[allow(dead_code)]
+pub fn list_supported_languages() -> SupportedLanguages {
+    let mut languages: Vec<LanguageInfo> = syntax_set().syntaxes().iter()
+        .map(|syntax| LanguageInfo {
+            name: syntax.name.clone(),
+            extensions: syntax.file_extensions.clone(),
+        })
+        .collect();
+    languages.sort_by_key(|language| language.name.to_lowercase());
+
+    SupportedLanguages {
+        languages,
+        aliases: LANGUAGE_ALIASES.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+    }
+}
+
+/// What the highlighter has loaded, for diagnosing missing highlighting
+#[derive(Debug, Clone, Serialize)]
+pub struct RendererCapabilities {
+    pub syntax_count: usize,
+    /// Theme names, sorted
+    pub themes: Vec<String>,
+    pub default_theme: String,
+    /// Whether syntaxes or themes beyond syntect's bundled defaults were loaded
+    pub custom_resources_loaded: bool,
+}
+
+/// Report the loaded syntaxes and themes
+#[allow(dead_code)]
+pub fn get_renderer_capabilities() -> RendererCapabilities {
+    let mut themes: Vec<String> = theme_set().themes.keys().cloned().collect();
+    themes.sort();
+
+    RendererCapabilities {
+        syntax_count: syntax_set().syntaxes().len(),
+        themes,
+        default_theme: DEFAULT_THEME.to_string(),
+        custom_resources_loaded: CUSTOM_ASSETS_LOADED.load(Ordering::SeqCst),
+    }
+}
+
+/// Re-read `resources/syntaxes` and `resources/themes` and swap them in
+/// Custom definitions are added on top of syntect's defaults; missing directories are skipped
+#[allow(dead_code)]
+pub fn reload_highlighter_assets() -> Result<RendererCapabilities, String> {
+    reload_highlighter_assets_from(Path::new(HIGHLIGHTER_ASSETS_DIR))
+}
+
+/// Load highlighter assets from `dir`, keeping the current sets if any file fails to load
+fn reload_highlighter_assets_from(dir: &Path) -> Result<RendererCapabilities, String> {
+    let mut syntaxes = SyntaxSet::load_defaults_nonewlines().into_builder();
+    let default_syntax_count = syntaxes.syntaxes().len();
+    let syntax_dir = dir.join("syntaxes");
+    if syntax_dir.is_dir() {
+        syntaxes.add_from_folder(&syntax_dir, false)
+            .map_err(|e| format!("Failed to load syntaxes from {}: {}", syntax_dir.display(), e))?;
+    }
+    let custom_syntaxes = syntaxes.syntaxes().len() > default_syntax_count;
+
+    let mut themes = ThemeSet::load_defaults();
+    let default_theme_count = themes.themes.len();
+    let theme_dir = dir.join("themes");
+    if theme_dir.is_dir() {
+        themes.add_from_folder(&theme_dir)
+            .map_err(|e| format!("Failed to load themes from {}: {}", theme_dir.display(), e))?;
+    }
+    let custom_themes = themes.themes.len() > default_theme_count;
+
+    *SYNTAX_SET.write().unwrap_or_else(PoisonError::into_inner) = syntaxes.build();
+    *THEME_SET.write().unwrap_or_else(PoisonError::into_inner) = themes;
+    CUSTOM_ASSETS_LOADED.store(custom_syntaxes || custom_themes, Ordering::SeqCst);
+
+    Ok(get_renderer_capabilities())
+}
+
+/// Guess the language of a code snippet from heuristics and syntect's syntaxes
+#[allow(dead_code)]
+pub fn detect_code_language(code: &str) -> CodeLanguageGuess {
+    let trimmed = code.trim();
+    if trimmed.is_empty() {
+        return CodeLanguageGuess { language: "text".to_string(), confidence: 0.0 };
+    }
+
+    let syntaxes = syntax_set();
+
+    // Shebangs and other first-line markers are decisive
+    if let Some(syntax) = syntaxes.find_syntax_by_first_line(trimmed) {
+        let token = syntax.file_extensions.first().cloned().unwrap_or_else(|| syntax.name.to_lowercase());
+        let language = LANGUAGE_ALIASES.get(&token).cloned().unwrap_or(token);
+        return CodeLanguageGuess { language, confidence: 0.95 };
+    }
+
+    if (trimmed.starts_with('{') || trimmed.starts_with('[')) && serde_json::from_str::<serde_json::Value>(trimmed).is_ok() {
+        return CodeLanguageGuess { language: "json".to_string(), confidence: 0.95 };
+    }
+
+    let mut scores: Vec<(&str, u32)> = LANGUAGE_MARKERS.iter()
+        .filter(|(token, _)| syntaxes.find_syntax_by_token(token).is_some())
+        .map(|(token, markers)| {
+            let score = markers.iter()
+                .filter(|(marker, _)| code.contains(marker))
+                .map(|(_, weight)| weight)
+                .sum();
+            (*token, score)
+        })
+        .filter(|(_, score)| *score > 0)
+        .collect();
+
+    let total: u32 = scores.iter().map(|(_, score)| score).sum();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    match scores.first() {
+        Some((language, score)) => CodeLanguageGuess {
+            language: language.to_string(),
+            confidence: *score as f32 / total as f32,
+        },
+        None => CodeLanguageGuess { language: "text".to_string(), confidence: 0.0 },
+    }
+}
+
 /// Render Markdown to HTML with syntax highlighting
 #[allow(dead_code)]
 pub fn render_markdown(markdown_input: String) -> Result<String, String> {
@@ -55,11 +291,29 @@ pub fn render_markdown(markdown_input: String) -> Result<String, String> {
     let mut html_output = String::with_capacity(markdown_input.len() * 2);
     let mut events: Vec<Event> = parser.collect();
     
-    process_markdown_events(&mut events, &mut html_output);
+    process_markdown_events(&mut events, &mut html_output, &mut HighlightCache::new());
     
     Ok(html_output)
 }
 
+/// Highlighted code blocks keyed by (language, code), shared across a batch
+type HighlightCache = HashMap<(String, String), String>;
+
+/// Render several Markdown inputs in one call, reusing highlighted code blocks
+/// Output order matches input order
+#[allow(dead_code)]
+pub fn render_markdown_batch(inputs: Vec<String>) -> Result<Vec<String>, String> {
+    let options = get_markdown_options();
+    let mut cache = HighlightCache::new();
+    
+    Ok(inputs.iter().map(|markdown_input| {
+        let mut html_output = String::with_capacity(markdown_input.len() * 2);
+        let mut events: Vec<Event> = Parser::new_ext(markdown_input, options).collect();
+        process_markdown_events(&mut events, &mut html_output, &mut cache);
+        html_output
+    }).collect())
+}
+
 /// Get markdown parsing options
 fn get_markdown_options() -> Options {
     let mut options = Options::empty();
@@ -73,7 +327,7 @@ fn get_markdown_options() -> Options {
 }
 
 /// Process markdown events with code highlighting
-fn process_markdown_events(events: &mut [Event], output: &mut String) {
+fn process_markdown_events(events: &mut [Event], output: &mut String, cache: &mut HighlightCache) {
     let mut in_code_block = false;
     let mut current_lang = String::new();
     let mut current_code = String::new();
@@ -99,8 +353,7 @@ fn process_markdown_events(events: &mut [Event], output: &mut String) {
                 if in_code_block {
                     current_code.push_str(text);
                 } else if last_event_was_code {
-                    let highlighted = highlight_code(&current_lang, &current_code);
-                    output.push_str(&highlighted);
+                    output.push_str(highlight_code_cached(cache, &current_lang, &current_code));
                     in_code_block = false;
                     last_event_was_code = false;
                 } else {
@@ -110,8 +363,7 @@ fn process_markdown_events(events: &mut [Event], output: &mut String) {
             }
             Event::End(TagEnd::CodeBlock) => {
                 if !current_code.is_empty() && in_code_block {
-                    let highlighted = highlight_code(&current_lang, &current_code);
-                    output.push_str(&highlighted);
+                    output.push_str(highlight_code_cached(cache, &current_lang, &current_code));
                 }
                 in_code_block = false;
                 last_event_was_code = false;
@@ -145,40 +397,86 @@ fn process_markdown_events(events: &mut [Event], output: &mut String) {
     }
 }
 
-/// Highlight code using syntect
-#[allow(unused_variables)]
-fn highlight_code(language: &str, code: &str) -> String {
+/// Resolve a language name or alias to a syntax, falling back to plain text
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, language: &str) -> &'a SyntaxReference {
     let lang = LANGUAGE_ALIASES
         .get(language.to_lowercase().as_str())
         .map(|s| s.as_str())
         .unwrap_or(language);
     
     let syntax_ref = if lang.is_empty() {
-        SYNTAX_SET.find_syntax_by_extension("txt")
+        syntaxes.find_syntax_by_extension("txt")
     } else {
-        SYNTAX_SET.find_syntax_by_token(lang)
+        syntaxes.find_syntax_by_token(lang)
     };
     
-    let _syntax = match syntax_ref {
+    match syntax_ref {
         Some(s) => s,
-        None => SYNTAX_SET.find_syntax_by_extension("txt")
-            .unwrap_or_else(|| SYNTAX_SET.syntaxes().first().unwrap()),
-    };
-    
-    let theme = THEME_SET.themes.get(DEFAULT_THEME)
-        .or_else(|| THEME_SET.themes.values().next())
-        .unwrap_or_else(|| &THEME_SET.themes[DEFAULT_THEME]);
+        None => syntaxes.find_syntax_by_extension("txt")
+            .unwrap_or_else(|| syntaxes.syntaxes().first().unwrap()),
+    }
+}
+
+/// Highlight code using syntect
+#[allow(unused_variables)]
+fn highlight_code(language: &str, code: &str) -> String {
+    let themes = theme_set();
+    let theme = themes.themes.get(DEFAULT_THEME)
+        .or_else(|| themes.themes.values().next())
+        .unwrap_or_else(|| &themes.themes[DEFAULT_THEME]);
     
     // syntect 5.0 API: start_highlighted_html_snippet(theme) -> (html, styles)
     let (highlighted_html, _) = start_highlighted_html_snippet(theme);
     
     format!(
-        r#"<div class="code-block" data-language="{}"><pre class="syntect">{}</pre></div>"#,
+        r#"<div class="code-block" data-language="{}" data-language-label="{}"><pre class="syntect">{}</pre></div>"#,
         escape_html(language),
+        escape_html(&language_label(language)),
         highlighted_html
     )
 }
 
+/// Highlight code, reusing an earlier result for the same language and code
+fn highlight_code_cached<'a>(cache: &'a mut HighlightCache, language: &str, code: &str) -> &'a str {
+    cache.entry((language.to_string(), code.to_string()))
+        .or_insert_with(|| highlight_code(language, code))
+}
+
+/// Highlight code with a `line-number` gutter cell on every line
+/// `theme` names a syntect theme; empty uses the default
+pub fn highlight_code_with_line_numbers(code: &str, language: &str, theme: &str) -> Result<String, String> {
+    let theme_name = if theme.is_empty() { DEFAULT_THEME } else { theme };
+    // Resolved before taking the read locks so the syntax set isn't locked twice
+    let label = language_label(language);
+    let (syntaxes, themes) = (syntax_set(), theme_set());
+    let theme = themes.themes.get(theme_name)
+        .ok_or_else(|| format!("Unknown theme '{}'", theme_name))?;
+    let mut highlighter = HighlightLines::new(find_syntax(&syntaxes, language), theme);
+    
+    // syntect renders the background on the <pre> and colors on the spans inside it
+    let (mut html, _) = start_highlighted_html_snippet(theme);
+    // `lines()` ignores a single trailing newline, so "a\nb\n" is two lines
+    for (index, line) in code.lines().enumerate() {
+        let regions = highlighter.highlight_line(line, &syntaxes)
+            .map_err(|e| format!("Failed to highlight line {}: {}", index + 1, e))?;
+        let line_html = styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+            .map_err(|e| format!("Failed to render line {}: {}", index + 1, e))?;
+        html.push_str(&format!(
+            r#"<div class="code-line"><span class="line-number">{}</span><span class="line-content">{}</span></div>"#,
+            index + 1,
+            line_html
+        ));
+    }
+    html.push_str("</pre>");
+    
+    Ok(format!(
+        r#"<div class="code-block code-block-numbered" data-language="{}" data-language-label="{}">{}</div>"#,
+        escape_html(language),
+        escape_html(&label),
+        html
+    ))
+}
+
 /// HTML escape for plain text
 fn escape_html(text: &str) -> String {
     html_escape::encode_safe(text).to_string()
@@ -248,6 +546,37 @@ fn push_tag_end(output: &mut String, tag_end: &TagEnd) {
     }
 }
 
+/// Strip markdown to plain text (for TTS and plain copy)
+/// Code blocks are kept as raw text and links reduce to their text
+#[allow(dead_code)]
+pub fn markdown_to_plaintext(markdown_input: String) -> Result<String, String> {
+    let parser = Parser::new_ext(&markdown_input, get_markdown_options());
+    let mut output = String::with_capacity(markdown_input.len());
+
+    for event in parser {
+        match event {
+            Event::Text(text) | Event::Code(text) => output.push_str(&text),
+            Event::SoftBreak => output.push(' '),
+            Event::HardBreak => output.push('\n'),
+            Event::Start(Tag::Item) if !output.is_empty() && !output.ends_with('\n') => output.push('\n'),
+            Event::End(TagEnd::TableCell) => output.push(' '),
+            Event::End(TagEnd::Item | TagEnd::TableRow | TagEnd::TableHead) => output.push('\n'),
+            Event::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::BlockQuote | TagEnd::List(_) | TagEnd::Table,
+            ) => {
+                while output.ends_with('\n') {
+                    output.pop();
+                }
+                output.push_str("\n\n");
+            }
+            Event::Rule => output.push_str("\n\n"),
+            _ => {}
+        }
+    }
+
+    Ok(output.trim().to_string())
+}
+
 /// Process custom markdown extensions (thinking tags, tool actions)
 #[allow(dead_code)]
 pub fn process_custom_syntax(markdown_input: String) -> Result<String, String> {
@@ -268,6 +597,36 @@ pub fn process_custom_syntax(markdown_input: String) -> Result<String, String> {
     Ok(result)
 }
 
+/// Render a chat session as a self-contained HTML document with inline styles
+#[allow(dead_code)]
+pub fn render_session_html(session: &ChatSession) -> Result<String, String> {
+    let title = escape_html(&session.title);
+    let mut body = String::new();
+
+    for message in &session.messages {
+        let mut content = String::new();
+        if let Some(reasoning) = message.reasoning_content.as_deref().filter(|r| !r.trim().is_empty()) {
+            content.push_str(&format!(
+                r#"<details class="thinking-block"><summary>Thinking...</summary><div class="thinking-content">{}</div></details>"#,
+                escape_html(reasoning)
+            ));
+        }
+        content.push_str(&render_markdown(process_custom_syntax(message.content.clone())?)?);
+
+        body.push_str(&format!(
+            "<section class=\"message message-{}\">\n<div class=\"message-role\">{}</div>\n<div class=\"message-content\">{}</div>\n</section>\n",
+            escape_html(&message.role),
+            escape_html(&message.role),
+            content
+        ));
+    }
+
+    Ok(format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\" />\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<main>\n<h1>{}</h1>\n{}</main>\n</body>\n</html>\n",
+        title, SESSION_HTML_STYLES, title, body
+    ))
+}
+
 /// Highlight code synchronously (for non-Tauri use)
 #[allow(dead_code)]
 pub fn highlight_code_sync(code: String, language: String) -> Result<String, String> {
@@ -287,6 +646,109 @@ mod tests {
         assert!(result.contains("code-block"));
     }
     
+    #[test]
+    fn test_code_block_language_label() {
+        let result = render_markdown("```ts\nconst x: number = 1;\n```".to_string()).unwrap();
+        assert!(result.contains(r#"data-language="ts""#));
+        assert!(result.contains(r#"data-language-label="TypeScript""#));
+
+        assert_eq!(language_label("Rust"), "Rust");
+        assert_eq!(language_label("no-such-lang"), "no-such-lang");
+    }
+    
+    #[test]
+    fn test_render_markdown_batch_matches_individual_renders() {
+        let inputs = vec![
+            "# One\n\n```rust\nfn main() {}\n```".to_string(),
+            "Plain *text* with `inline` code".to_string(),
+            String::new(),
+            "```rust\nfn main() {}\n```\n\n- a\n- b".to_string(),
+        ];
+        let batch = render_markdown_batch(inputs.clone()).unwrap();
+        let individual: Vec<String> = inputs.into_iter().map(|md| render_markdown(md).unwrap()).collect();
+        assert_eq!(batch, individual);
+        assert!(render_markdown_batch(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_highlight_code_with_line_numbers() {
+        let code = "fn main() {\n    println!(\"hi\");\n}\n";
+        let html = highlight_code_with_line_numbers(code, "rust", "").unwrap();
+
+        for n in 1..=3 {
+            assert!(html.contains(&format!(r#"<span class="line-number">{}</span>"#, n)));
+        }
+        assert!(!html.contains(r#"<span class="line-number">4</span>"#));
+        assert!(html.contains("style=\"color:"));
+        assert!(highlight_code_with_line_numbers(code, "rust", "no-such-theme").is_err());
+    }
+
+    #[test]
+    fn test_detect_python() {
+        let code = "import os\n\ndef list_files(path):\n    if not path:\n        return None\n    elif os.path.isdir(path):\n        print(path)\n    return os.listdir(path)\n";
+        let guess = detect_code_language(code);
+        assert_eq!(guess.language, "python");
+        assert!(guess.confidence > 0.5);
+    }
+
+    #[test]
+    fn test_detect_rust() {
+        let code = "use std::collections::HashMap;\n\npub fn count(words: &[&str]) -> HashMap<String, usize> {\n    let mut counts = HashMap::new();\n    for w in words {\n        *counts.entry(w.to_string()).or_insert(0) += 1;\n    }\n    println!(\"{:?}\", counts);\n    counts\n}\n";
+        let guess = detect_code_language(code);
+        assert_eq!(guess.language, "rust");
+        assert!(guess.confidence > 0.5);
+    }
+
+    #[test]
+    fn test_list_supported_languages() {
+        let supported = list_supported_languages();
+        for (name, extension) in [("rust", "rs"), ("python", "py"), ("json", "json")] {
+            let language = supported.languages.iter()
+                .find(|l| l.name.eq_ignore_ascii_case(name))
+                .unwrap_or_else(|| panic!("{} missing", name));
+            assert!(language.extensions.iter().any(|e| e == extension));
+        }
+        assert_eq!(supported.aliases.get("js").map(String::as_str), Some("javascript"));
+    }
+
+    #[test]
+    fn test_renderer_capabilities() {
+        let capabilities = get_renderer_capabilities();
+        assert!(capabilities.syntax_count > 0);
+        assert_eq!(capabilities.default_theme, "base16-ocean.dark");
+        assert!(capabilities.themes.contains(&capabilities.default_theme));
+    }
+
+    #[test]
+    fn test_reload_highlighter_assets_adds_syntax() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("syntaxes")).unwrap();
+        std::fs::write(
+            temp_dir.path().join("syntaxes").join("PixelScript.sublime-syntax"),
+            "%YAML 1.2\n---\nname: PixelScript\nfile_extensions: [pxs]\nscope: source.pixelscript\ncontexts:\n  main:\n    - match: '\\bpixel\\b'\n      scope: keyword.control.pixelscript\n",
+        ).unwrap();
+
+        let capabilities = reload_highlighter_assets_from(temp_dir.path()).unwrap();
+        assert!(capabilities.custom_resources_loaded);
+        assert_eq!(find_syntax(&syntax_set(), "pxs").name, "PixelScript");
+        assert_eq!(language_label("pxs"), "PixelScript");
+        let html = highlight_code_with_line_numbers("pixel art", "pxs", "").unwrap();
+        assert!(html.contains("<span style="));
+
+        // Reloading without the fixture drops it again
+        let empty_dir = tempfile::TempDir::new().unwrap();
+        let capabilities = reload_highlighter_assets_from(empty_dir.path()).unwrap();
+        assert!(!capabilities.custom_resources_loaded);
+        assert_eq!(find_syntax(&syntax_set(), "pxs").name, "Plain Text");
+    }
+
+    #[test]
+    fn test_markdown_to_plaintext() {
+        let md = "# Title\n\nSome **bold** and *italic* text with a [link](https://example.com).\n\n```rust\nfn main() {}\n```".to_string();
+        let text = markdown_to_plaintext(md).unwrap();
+        assert_eq!(text, "Title\n\nSome bold and italic text with a link.\n\nfn main() {}");
+    }
+
     #[test]
     fn test_escape_html() {
         let input = "<script>alert('xss')</script>";
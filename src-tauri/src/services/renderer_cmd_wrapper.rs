@@ -1,13 +1,18 @@
 // Renderer command wrappers for Tauri
 // These wrappers re-export the renderer functions as Tauri commands
 
-use crate::services::renderer::{render_markdown as render_markdown_impl, process_custom_syntax as process_custom_syntax_impl, highlight_code_sync as highlight_code_sync_impl};
+use crate::services::renderer::{render_markdown as render_markdown_impl, render_markdown_batch as render_markdown_batch_impl, process_custom_syntax as process_custom_syntax_impl, highlight_code_sync as highlight_code_sync_impl, highlight_code_with_line_numbers as highlight_code_with_line_numbers_impl, detect_code_language as detect_code_language_impl, markdown_to_plaintext as markdown_to_plaintext_impl, list_supported_languages as list_supported_languages_impl, get_renderer_capabilities as get_renderer_capabilities_impl, reload_highlighter_assets as reload_highlighter_assets_impl, CodeLanguageGuess, SupportedLanguages, RendererCapabilities};
 
 #[tauri::command]
 pub fn render_markdown(markdown_input: String) -> Result<String, String> {
     render_markdown_impl(markdown_input)
 }
 
+#[tauri::command]
+pub fn render_markdown_batch(inputs: Vec<String>) -> Result<Vec<String>, String> {
+    render_markdown_batch_impl(inputs)
+}
+
 #[tauri::command]
 pub fn process_custom_syntax(markdown_input: String) -> Result<String, String> {
     process_custom_syntax_impl(markdown_input)
@@ -17,3 +22,33 @@ pub fn process_custom_syntax(markdown_input: String) -> Result<String, String> {
 pub fn highlight_code_sync(code: String, language: String) -> Result<String, String> {
     highlight_code_sync_impl(code, language)
 }
+
+#[tauri::command]
+pub fn highlight_code_with_line_numbers(code: String, language: String, theme: String) -> Result<String, String> {
+    highlight_code_with_line_numbers_impl(&code, &language, &theme)
+}
+
+#[tauri::command]
+pub fn detect_code_language(code: String) -> CodeLanguageGuess {
+    detect_code_language_impl(&code)
+}
+
+#[tauri::command]
+pub fn markdown_to_plaintext(markdown_input: String) -> Result<String, String> {
+    markdown_to_plaintext_impl(markdown_input)
+}
+
+#[tauri::command]
+pub fn list_supported_languages() -> SupportedLanguages {
+    list_supported_languages_impl()
+}
+
+#[tauri::command]
+pub fn get_renderer_capabilities() -> RendererCapabilities {
+    get_renderer_capabilities_impl()
+}
+
+#[tauri::command]
+pub fn reload_highlighter_assets() -> Result<RendererCapabilities, String> {
+    reload_highlighter_assets_impl()
+}
@@ -18,6 +18,49 @@ pub struct AppConfig {
     pub notifications: bool,
     pub active_model_id: Option<String>,
     pub active_provider_id: Option<String>,
+    /// Hours between automatic backups; None disables them
+    #[serde(default)]
+    pub backup_interval_hours: Option<u32>,
+    /// Proxy for outbound requests (`http://`, `https://` or `socks5://`); None connects directly
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+    /// Times a chat stream that fails before `[DONE]` is re-issued; 0 disables retries
+    #[serde(default)]
+    pub stream_retry: u32,
+    /// Main window geometry saved on move/resize and restored on launch
+    #[serde(default)]
+    pub window_state: Option<WindowState>,
+    /// Closing the main window hides it to the tray instead of quitting
+    #[serde(default = "default_close_to_tray")]
+    pub close_to_tray: bool,
+    /// Trusted key that signed skill imports are verified against
+    #[serde(default)]
+    pub skill_signer: Option<SkillSigner>,
+}
+
+fn default_close_to_tray() -> bool {
+    true
+}
+
+/// Trusted public key used to verify signed skills
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SkillSigner {
+    /// Human-readable signer identity stored on verified skills
+    pub identity: String,
+    /// Base64-encoded Ed25519 public key
+    pub public_key: String,
+}
+
+/// Main window geometry in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WindowState {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
 }
 
 impl Default for AppConfig {
@@ -30,11 +73,30 @@ impl Default for AppConfig {
             notifications: true,
             active_model_id: None,
             active_provider_id: None,
+            backup_interval_hours: None,
+            proxy_url: None,
+            stream_retry: 0,
+            window_state: None,
+            close_to_tray: true,
+            skill_signer: None,
         }
     }
 }
 
+impl AppConfig {
+    /// Take the settings the backend changes itself from `current`, so saving a state the
+    /// frontend read earlier does not revert them
+    pub fn keep_backend_settings(&mut self, current: &AppConfig) {
+        self.window_state = current.window_state;
+        self.close_to_tray = current.close_to_tray;
+        self.proxy_url = current.proxy_url.clone();
+        self.stream_retry = current.stream_retry;
+        self.skill_signer = current.skill_signer.clone();
+    }
+}
+
 /// Chat message structure with reasoning support
+/// Fields after `timestamp` default when missing, so messages saved by older versions still load
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct Message {
@@ -42,12 +104,24 @@ pub struct Message {
     pub role: String,
     pub content: String,
     pub timestamp: u64,
+    #[serde(default)]
     pub model_id: Option<String>,
+    #[serde(default)]
     pub attachments: Vec<String>,
+    #[serde(default)]
     pub images: Vec<String>,
+    #[serde(default)]
     pub reasoning_content: Option<String>,
+    #[serde(default)]
     pub reasoning_blocks: Vec<ReasoningBlock>,
+    #[serde(default)]
     pub token_usage: Option<usize>,
+    /// Prompt and completion share of `token_usage`, when the provider reports them
+    #[serde(default)]
+    pub prompt_tokens: Option<usize>,
+    #[serde(default)]
+    pub completion_tokens: Option<usize>,
+    #[serde(default)]
     pub is_deep_thinking: bool,
 }
 
@@ -64,6 +138,8 @@ impl Message {
             reasoning_content: None,
             reasoning_blocks: Vec::new(),
             token_usage: None,
+            prompt_tokens: None,
+            completion_tokens: None,
             is_deep_thinking: false,
         }
     }
@@ -80,6 +156,20 @@ pub struct ChatSession {
     pub updated_at: u64,
     pub model_id: Option<String>,
     pub deep_thinking_config: DeepThinkingConfig,
+    #[serde(default)]
+    pub temperature_override: Option<f32>,
+    /// Session this one was forked from
+    #[serde(default)]
+    pub parent_session_id: Option<String>,
+    /// Last message of the parent included in the fork
+    #[serde(default)]
+    pub forked_from_message_id: Option<String>,
+    /// Cap on the tokens this session may use across all its messages
+    #[serde(default)]
+    pub token_budget: Option<usize>,
+    /// System prompt sent ahead of the conversation on every request
+    #[serde(default)]
+    pub system_prompt: Option<String>,
 }
 
 impl ChatSession {
@@ -93,10 +183,25 @@ impl ChatSession {
             updated_at: now,
             model_id: None,
             deep_thinking_config: DeepThinkingConfig::default(),
+            temperature_override: None,
+            parent_session_id: None,
+            forked_from_message_id: None,
+            token_budget: None,
+            system_prompt: None,
         }
     }
 }
 
+/// Generate a chat session id (`session_<uuid>`)
+pub fn new_session_id() -> String {
+    format!("session_{}", uuid::Uuid::new_v4())
+}
+
+/// Generate a chat message id (`msg_<uuid>`)
+pub fn new_message_id() -> String {
+    format!("msg_{}", uuid::Uuid::new_v4())
+}
+
 /// LLM Provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -107,6 +212,13 @@ pub struct LLMProvider {
     pub base_url: String,
     pub api_key: String,
     pub enabled: bool,
+    #[serde(default)]
+    pub custom_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub default_model_id: Option<String>,
+    /// Cached result of the streaming support probe; None until probed
+    #[serde(default)]
+    pub supports_streaming: Option<bool>,
 }
 
 /// LLM Model configuration
@@ -134,6 +246,15 @@ pub struct McpServer {
     pub command: String,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    /// Request timeout for tool calls; None uses the default
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Endpoint of an `http` server; unused for `stdio`
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Working directory of a `stdio` server process; None inherits the app's
+    #[serde(default)]
+    pub cwd: Option<String>,
 }
 
 /// MCP Tool definition
@@ -144,12 +265,37 @@ pub struct McpToolDefinition {
     pub input_schema: serde_json::Value,
 }
 
+/// Requests awaiting a response from a running MCP server, keyed by JSON-RPC id
+#[derive(Debug, Default)]
+pub struct PendingMcpResponses {
+    pub waiting: HashMap<u64, std::sync::mpsc::SyncSender<serde_json::Value>>,
+    /// Set once the server's stdout closed; no further responses will arrive
+    pub closed: bool,
+}
+
 /// Running MCP Server instance (not Clone-able due to Child process)
 pub struct RunningMcpServer {
     pub server_id: String,
     pub process: std::process::Child,
-    pub stdin: std::sync::Mutex<std::process::ChildStdin>,
-    pub stdout: std::sync::Mutex<std::process::ChildStdout>,
+    /// Shared with the dispatcher thread, which answers requests the server sends us
+    pub stdin: Arc<std::sync::Mutex<std::process::ChildStdin>>,
+    /// Filled by the dispatcher thread that owns stdout and routes each response by id
+    pub pending_responses: Arc<std::sync::Mutex<PendingMcpResponses>>,
+    /// Tool call timeout copied from the server config
+    pub timeout_ms: Option<u64>,
+    /// Next JSON-RPC request id; each server numbers its requests from 1
+    pub rpc_id: std::sync::atomic::AtomicU64,
+    /// Protocol version agreed on in the initialize handshake
+    pub protocol_version: Option<String>,
+    /// Most recent stderr lines, oldest first, filled by a background reader
+    pub stderr_log: Arc<std::sync::Mutex<std::collections::VecDeque<String>>>,
+}
+
+impl RunningMcpServer {
+    /// Take the next request id in this server's sequence
+    pub fn next_rpc_id(&self) -> u64 {
+        self.rpc_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    }
 }
 
 /// MCP Server status for frontend (tools as JSON to avoid TS constraint)
@@ -161,10 +307,31 @@ pub enum McpServerStatusInfo {
     Error { server_id: String, error: String },
 }
 
+/// Connected MCP server reached over HTTP (`http` server type)
+#[derive(Debug)]
+pub struct RemoteMcpServer {
+    pub url: String,
+    /// Session id the server assigned on initialize, sent back with every request
+    pub session_id: Option<String>,
+    pub timeout_ms: Option<u64>,
+    pub rpc_id: std::sync::atomic::AtomicU64,
+    /// Protocol version agreed on initialize, sent back with every request
+    pub protocol_version: Option<String>,
+}
+
+impl RemoteMcpServer {
+    /// Take the next request id in this server's sequence
+    pub fn next_rpc_id(&self) -> u64 {
+        self.rpc_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
 /// Active MCP servers (running processes)
 #[derive(Default)]
 pub struct McpServerManager {
     pub servers: Arc<RwLock<HashMap<String, RunningMcpServer>>>,
+    /// Connected `http` servers
+    pub remote_servers: Arc<RwLock<HashMap<String, RemoteMcpServer>>>,
 }
 
 /// Thinking depth levels for Deep Thinking mode (kept for compatibility, not used)
@@ -383,6 +550,70 @@ pub struct Skill {
     pub enabled: bool,
     pub created_at: u64,
     pub updated_at: u64,
+    /// JSON Schema the skill's return value must conform to
+    #[serde(default, with = "json_text")]
+    pub output_schema: Option<serde_json::Value>,
+    /// Identity of the trusted signer that verified this skill on import
+    #[serde(default)]
+    pub signed_by: Option<String>,
+    /// Record each execution's inputs and outputs in `AppState.skill_runs`
+    #[serde(default)]
+    pub log_runs: bool,
+    /// Risky patterns found in `code` when the skill was created, imported or edited
+    #[serde(default)]
+    pub security_findings: Vec<SecurityFinding>,
+    /// JS stack limit in bytes for this skill; None uses the global limit
+    #[serde(default)]
+    pub max_stack_size: Option<usize>,
+}
+
+/// A risky pattern in skill code, found by a lint rather than a sandbox
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityFinding {
+    /// 1-based line number
+    pub line: usize,
+    pub pattern: String,
+    pub message: String,
+}
+
+/// One logged execution of a skill with its inputs and outputs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillRun {
+    pub timestamp: u64,
+    #[serde(with = "json_text")]
+    pub input: serde_json::Value,
+    #[serde(with = "json_text")]
+    pub output: serde_json::Value,
+    pub success: bool,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Serde adapter for JSON values (including optional ones) in persisted state
+/// Binary formats such as bincode cannot deserialize a `serde_json::Value` directly,
+/// so non-human-readable formats store it as JSON text instead
+mod json_text {
+    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            serde_json::to_string(value)
+                .map_err(serde::ser::Error::custom)?
+                .serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            T::deserialize(deserializer)
+        } else {
+            let text = String::deserialize(deserializer)?;
+            serde_json::from_str(&text).map_err(serde::de::Error::custom)
+        }
+    }
 }
 
 impl Default for Skill {
@@ -398,6 +629,11 @@ impl Default for Skill {
             enabled: true,
             created_at: now,
             updated_at: now,
+            output_schema: None,
+            signed_by: None,
+            log_runs: false,
+            security_findings: Vec::new(),
+            max_stack_size: None,
         }
     }
 }
@@ -415,6 +651,9 @@ pub struct AppState {
     pub ace_config: AceConfig,
     pub theme: String,
     pub language: String,
+    /// Logged runs per skill id, oldest first, for skills with `log_runs` enabled
+    #[serde(default)]
+    pub skill_runs: HashMap<String, Vec<SkillRun>>,
 }
 
 impl Default for AppState {
@@ -430,7 +669,89 @@ impl Default for AppState {
             ace_config: AceConfig::default(),
             theme: "dark".to_string(),
             language: "zh".to_string(),
+            skill_runs: HashMap::new(),
+        }
+    }
+}
+
+/// Number of single-character insertions, deletions and substitutions turning `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
         }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+impl AppState {
+    /// Names of the skills, providers or models, by `kind`
+    fn names_of_kind(&self, kind: &str) -> Vec<&str> {
+        match kind {
+            "skill" => self.skills.iter().map(|s| s.name.as_str()).collect(),
+            "provider" => self.providers.iter().map(|p| p.name.as_str()).collect(),
+            "model" => self.models.iter().map(|m| m.name.as_str()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The existing name of `kind` nearest to `query` by edit distance (ignoring case)
+    /// None when nothing is close enough to pass for a typo
+    pub fn suggest_closest_name(&self, kind: &str, query: &str) -> Option<String> {
+        let query = query.to_lowercase();
+        let max_distance = (query.chars().count() / 3).max(2);
+        self.names_of_kind(kind)
+            .into_iter()
+            .map(|name| (levenshtein(&query, &name.to_lowercase()), name))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, name)| name.to_string())
+    }
+
+    /// "<Kind> '<query>' not found", with a "did you mean" hint when a name is close
+    pub fn not_found_message(&self, kind: &str, query: &str) -> String {
+        let label = match kind {
+            "skill" => "Skill",
+            "provider" => "Provider",
+            "model" => "Model",
+            other => other,
+        };
+        match self.suggest_closest_name(kind, query) {
+            Some(name) => format!("{} '{}' not found, did you mean '{}'?", label, query, name),
+            None => format!("{} '{}' not found", label, query),
+        }
+    }
+
+    /// Bring messages saved by older versions up to the current schema
+    /// Fills in missing ids and timestamps, normalizes roles and derives `is_deep_thinking`
+    /// from stored reasoning. Returns the number of messages changed
+    pub fn migrate_legacy_messages(&mut self) -> usize {
+        let mut migrated = 0;
+        for session in self.sessions.values_mut() {
+            for message in &mut session.messages {
+                let before = (message.id.clone(), message.role.clone(), message.timestamp, message.is_deep_thinking);
+                if message.id.is_empty() {
+                    message.id = new_message_id();
+                }
+                message.role = message.role.trim().to_lowercase();
+                if message.timestamp == 0 {
+                    message.timestamp = session.created_at;
+                }
+                let has_reasoning = message.reasoning_content.as_deref().is_some_and(|r| !r.is_empty())
+                    || !message.reasoning_blocks.is_empty();
+                message.is_deep_thinking |= has_reasoning;
+
+                if before != (message.id.clone(), message.role.clone(), message.timestamp, message.is_deep_thinking) {
+                    migrated += 1;
+                }
+            }
+        }
+        migrated
     }
 }
 
@@ -513,3 +834,73 @@ impl SharedState {
         f(&mut state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generated_ids_are_prefixed_and_unique() {
+        let message_ids: HashSet<String> = (0..1000).map(|_| new_message_id()).collect();
+        assert_eq!(message_ids.len(), 1000);
+        assert!(message_ids.iter().all(|id| id.starts_with("msg_")));
+
+        let session_ids: HashSet<String> = (0..1000).map(|_| new_session_id()).collect();
+        assert_eq!(session_ids.len(), 1000);
+        assert!(session_ids.iter().all(|id| id.starts_with("session_")));
+    }
+
+    #[test]
+    fn test_misspelled_skill_name_suggests_closest() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+
+        let mut state = AppState::default();
+        for name in ["Weather Lookup", "Word Counter", "Unit Converter"] {
+            state.skills.push(Skill { name: name.to_string(), ..Skill::default() });
+        }
+
+        assert_eq!(state.suggest_closest_name("skill", "wether lookup").as_deref(), Some("Weather Lookup"));
+        assert_eq!(state.suggest_closest_name("skill", "Word Countr").as_deref(), Some("Word Counter"));
+        assert_eq!(state.suggest_closest_name("skill", "translate"), None);
+        assert_eq!(state.suggest_closest_name("provider", "Weather Lookup"), None);
+        assert_eq!(
+            state.not_found_message("skill", "Unit Convertor"),
+            "Skill 'Unit Convertor' not found, did you mean 'Unit Converter'?"
+        );
+        assert_eq!(state.not_found_message("skill", "translate"), "Skill 'translate' not found");
+    }
+
+    #[test]
+    fn test_minimal_legacy_message_loads_with_defaults() {
+        let message: Message = serde_json::from_str(
+            r#"{ "id": "", "role": "User", "content": "hello", "timestamp": 0 }"#
+        ).unwrap();
+        assert!(message.model_id.is_none());
+        assert!(message.attachments.is_empty() && message.images.is_empty());
+        assert!(message.reasoning_content.is_none() && message.reasoning_blocks.is_empty());
+        assert!(message.token_usage.is_none());
+        assert!(!message.is_deep_thinking);
+
+        let mut reasoned: Message = serde_json::from_str(
+            r#"{ "id": "msg_2", "role": "assistant", "content": "hi", "timestamp": 5 }"#
+        ).unwrap();
+        reasoned.reasoning_content = Some("thought about it".to_string());
+
+        let mut session = ChatSession::new("session_1".to_string(), "Legacy".to_string());
+        session.created_at = 1000;
+        session.messages = vec![message, reasoned];
+        let mut state = AppState::default();
+        state.sessions.insert(session.id.clone(), session);
+
+        assert_eq!(state.migrate_legacy_messages(), 2);
+        let messages = &state.sessions["session_1"].messages;
+        assert!(messages[0].id.starts_with("msg_"));
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(messages[0].timestamp, 1000);
+        assert!(messages[1].is_deep_thinking);
+        assert_eq!(messages[1].timestamp, 5);
+        assert_eq!(state.migrate_legacy_messages(), 0);
+    }
+}
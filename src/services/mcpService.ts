@@ -150,7 +150,7 @@ export async function testMcpConnection(id: string): Promise<boolean> {
 export function getServerTypeOptions(): Array<{ value: string; label: string }> {
   return [
     { value: 'stdio', label: 'STDIO (Command Line)' },
-    { value: 'sse', label: 'SSE (Server-Sent Events)' },
+    { value: 'http', label: 'HTTP (Streamable HTTP)' },
     { value: 'websocket', label: 'WebSocket' },
   ];
 }
@@ -3,4 +3,8 @@
 /**
  * LLM Provider configuration
  */
-export type LLMProvider = { id: string, name: string, provider_type: string, base_url: string, api_key: string, enabled: boolean, };
+export type LLMProvider = { id: string, name: string, provider_type: string, base_url: string, api_key: string, enabled: boolean, custom_headers: { [key in string]?: string }, default_model_id: string | null, 
+/**
+ * Cached result of the streaming support probe; None until probed
+ */
+supports_streaming: boolean | null, };
@@ -3,4 +3,16 @@
 /**
  * MCP Server configuration
  */
-export type McpServer = { id: string, server_type: string, command: string, args: Array<string>, env: { [key in string]?: string }, };
+export type McpServer = { id: string, server_type: string, command: string, args: Array<string>, env: { [key in string]?: string }, 
+/**
+ * Request timeout for tool calls; None uses the default
+ */
+timeout_ms: bigint | null, 
+/**
+ * Endpoint of an `http` server; unused for `stdio`
+ */
+url: string | null, 
+/**
+ * Working directory of a `stdio` server process; None inherits the app's
+ */
+cwd: string | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Main window geometry in physical pixels
+ */
+export type WindowState = { x: number, y: number, width: number, height: number, maximized: boolean, };
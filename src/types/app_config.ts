@@ -1,6 +1,27 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { WindowState } from "./WindowState";
 
 /**
  * Application configuration
  */
-export type AppConfig = { theme: string, language: string, font_size: number, auto_save: boolean, notifications: boolean, active_model_id: string | null, active_provider_id: string | null, };
+export type AppConfig = { theme: string, language: string, font_size: number, auto_save: boolean, notifications: boolean, active_model_id: string | null, active_provider_id: string | null, 
+/**
+ * Hours between automatic backups; None disables them
+ */
+backup_interval_hours: number | null, 
+/**
+ * Proxy for outbound requests (`http://`, `https://` or `socks5://`); None connects directly
+ */
+proxy_url: string | null, 
+/**
+ * Times a chat stream that fails before `[DONE]` is re-issued; 0 disables retries
+ */
+stream_retry: number, 
+/**
+ * Main window geometry saved on move/resize and restored on launch
+ */
+window_state: WindowState | null, 
+/**
+ * Closing the main window hides it to the tray instead of quitting
+ */
+close_to_tray: boolean, };
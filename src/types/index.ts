@@ -2,6 +2,7 @@
 // Generated automatically from Rust state types
 
 export type { AppConfig } from './app_config';
+export type { WindowState } from './WindowState';
 export type { Message } from './message';
 export type { ChatSession } from './ChatSession';
 export type { LLMProvider } from './LLMProvider';